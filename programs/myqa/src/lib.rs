@@ -1,11 +1,18 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
-    token::{self, Token, Transfer, Mint, TokenAccount},
+    token::{self, Token, Transfer, Mint, MintTo, TokenAccount, CloseAccount},
+    token_interface::{self, TokenInterface, TransferChecked},
+    token_2022::spl_token_2022::{
+        self,
+        extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions},
+    },
     metadata::*,
-    associated_token::AssociatedToken,
+    associated_token::{self, AssociatedToken},
 };
 
-use mpl_token_metadata::types::DataV2;
+use anchor_lang::solana_program::{keccak, program::invoke};
+use anchor_lang::system_program;
+use mpl_token_metadata::types::{Collection, CollectionDetails, Creator, DataV2};
 
 declare_id!("EsXaHoxZzsBAmMGKVWrNgysfs2Rv1XasV1JTHdyvwskM");
 
@@ -20,6 +27,19 @@ const MAX_QUESTIONS_PER_USER: u64 = 100;
 #[cfg(not(feature = "test"))]
 const OPERATION_COOLDOWN: i64 = 60; // 60 seconds for production
 
+// Time a seller has to hand over the re-encrypted key before the buyer can reclaim
+#[cfg(not(feature = "test"))]
+const SALE_FULFILLMENT_WINDOW: i64 = 86400; // 24 hours for production
+#[cfg(feature = "test")]
+const SALE_FULFILLMENT_WINDOW: i64 = 10;
+
+// Auction bounds; bids close to the end push it back by the extension window
+const MAX_AUCTION_DURATION: i64 = 30 * 86400; // 30 days
+#[cfg(not(feature = "test"))]
+const AUCTION_EXTENSION_WINDOW: i64 = 300; // 5 minutes for production
+#[cfg(feature = "test")]
+const AUCTION_EXTENSION_WINDOW: i64 = 2;
+
 const MIN_METADATA_LENGTH: usize = 5;
 const MAX_ENCRYPTED_KEY_LENGTH: usize = 1024;
 const MIN_OPERATION_COOLDOWN: i64 = 300; // 5 minutes
@@ -36,12 +56,74 @@ const UNLOCK_KEY_BASE_SIZE: usize = 8 + // anchor account discriminator
     32 + // owner: Pubkey
     32 + // question: Pubkey
     8 +  // token_id: u64
+    32 + // mint: Pubkey
     1 +  // is_listed: bool
     8 +  // list_price: u64
     8 +  // mint_time: i64
     8 +  // last_sold_price: u64
     8 +  // last_sold_time: i64
-    8;   // list_time: i64
+    8 +  // list_time: i64
+    1;   // reencryption_pending: bool
+
+// Question size without the variable length content_cid
+const QUESTION_BASE_SIZE: usize = MIN_ACCOUNT_SPACE +
+    32 + // creator: Pubkey
+    4 +  // content_cid: String prefix
+    32 + // content_hash: [u8; 32]
+    8 +  // content_version: u64
+    8 +  // unlock_price: u64
+    8 +  // max_keys: u64
+    8 +  // current_keys: u64
+    8 +  // index: u64
+    8 +  // creation_time: i64
+    8 +  // total_sales: u64
+    1 +  // is_active: bool
+    1 +  // is_taken_down: bool
+    8 +  // validation_timestamp: i64
+    PRICING_CURVE_SIZE +
+    1 + LAUNCH_SCHEDULE_SIZE + // launch_schedule: Option<LaunchSchedule>
+    1 + 32 + // allowlist_root: Option<[u8; 32]>
+    32 +     // payment_mint: Pubkey
+    1 +      // payment_type: PaymentType
+    2 +      // royalty_bps: u16
+    1 + 32 + // collection_mint: Option<Pubkey>
+    1 +      // key_mode: KeyMode
+    1 + 8 +  // max_keys_per_wallet: Option<u64>
+    1 + 8 +  // refund_window: Option<i64>
+    1 +      // has_split: bool
+    8;       // open_escrows: u64
+
+// Largest PricingCurve variant (Step) plus the enum tag
+const PRICING_CURVE_SIZE: usize = 1 + 8 + 8;
+const MAX_CURVE_GROWTH_BPS: u16 = 10000; // at most doubles per key sold
+const CURVE_SCALE: u128 = 1_000_000_000; // fixed-point scale for exponential curves
+
+const MAX_ALLOWLIST_PROOF_LENGTH: usize = 20;
+
+const MAX_REFUND_WINDOW: i64 = 30 * 86400; // 30 days
+
+// Co-authors a question's creator share and royalties can be split between. Every
+// payee is a Metaplex creator on the key NFTs, next to the mint authority PDA, and
+// Metaplex allows at most five creators.
+const MAX_SPLIT_PAYEES: usize = 4;
+const SPLIT_PAYEE_SIZE: usize = 32 + 2; // payee: Pubkey, share_bps: u16
+
+// Creator earnings vault, one per creator and payment mint
+const CREATOR_VAULT_SIZE: usize = MIN_ACCOUNT_SPACE +
+    32 + // creator: Pubkey
+    32 + // payment_mint: Pubkey
+    8 +  // total_earned: u64
+    8;   // total_withdrawn: u64
+
+const MAX_KEYS_PER_PURCHASE: u8 = 5; // keys a single batch can mint
+const BATCH_ACCOUNTS_PER_KEY: usize = 5; // unlock_key, mint, key token account, metadata, master edition
+
+// Compressed keys: a depth-20 tree holds about a million keys per question
+const MAX_KEY_TREE_DEPTH: u8 = 20;
+const KEY_TREE_BUFFER_SIZE: usize = 8; // changes a proof may lag behind and still verify
+
+const LAUNCH_SCHEDULE_SIZE: usize = 8 + 8 + 8 + 2 + 8;
+const SECONDS_PER_DAY: i64 = 86400;
 
 // Add this near the top of the file with other constants
 const UNLOCK_KEY_DISCRIMINATOR: u8 = 1;
@@ -53,6 +135,677 @@ pub struct UserState {
     pub is_blacklisted: bool,
 }
 
+fn validate_content_cid(content_cid: &str) -> Result<()> {
+    require!(
+        content_cid.len() >= IPFS_CID_LENGTH && content_cid.len() <= MAX_CID_LENGTH,
+        ErrorCode::InvalidCIDFormat
+    );
+    require!(
+        content_cid.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+        ErrorCode::InvalidCIDFormat
+    );
+    Ok(())
+}
+
+// Splits a secondary sale price into (platform_fee, creator_royalty, seller_payment)
+fn split_sale_proceeds(
+    price: u64,
+    marketplace: &Marketplace,
+    question: &Question,
+) -> Result<(u64, u64, u64)> {
+    let platform_fee = price
+        .checked_mul(marketplace.platform_fee_bps as u64)
+        .ok_or(ErrorCode::NumericalOverflow)?
+        .checked_div(10000)
+        .ok_or(ErrorCode::NumericalOverflow)?;
+    let creator_royalty = price
+        .checked_mul(question.royalty_bps as u64)
+        .ok_or(ErrorCode::NumericalOverflow)?
+        .checked_div(10000)
+        .ok_or(ErrorCode::NumericalOverflow)?;
+    let seller_payment = price
+        .checked_sub(platform_fee)
+        .ok_or(ErrorCode::NumericalOverflow)?
+        .checked_sub(creator_royalty)
+        .ok_or(ErrorCode::NumericalOverflow)?;
+    Ok((platform_fee, creator_royalty, seller_payment))
+}
+
+// Linear price decay from start_price to end_price over the auction duration
+fn dutch_auction_price(auction: &Auction, now: i64) -> Result<u64> {
+    if now >= auction.end_time {
+        return Ok(auction.end_price);
+    }
+    let elapsed = now.saturating_sub(auction.start_time).max(0) as u128;
+    let duration = auction.end_time
+        .checked_sub(auction.start_time)
+        .ok_or(ErrorCode::NumericalOverflow)? as u128;
+    let drop = (auction.start_price - auction.end_price) as u128;
+    let decayed = drop
+        .checked_mul(elapsed)
+        .ok_or(ErrorCode::NumericalOverflow)?
+        .checked_div(duration)
+        .ok_or(ErrorCode::NumericalOverflow)? as u64;
+    Ok(auction.start_price - decayed)
+}
+
+// Lamport payment from a wallet, used by SOL-priced questions
+fn transfer_lamports<'info>(
+    system_program: &Program<'info, System>,
+    from: &Signer<'info>,
+    to: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    system_program::transfer(
+        CpiContext::new(
+            system_program.to_account_info(),
+            system_program::Transfer {
+                from: from.to_account_info(),
+                to: to.clone(),
+            },
+        ),
+        amount,
+    )
+}
+
+// Pays lamports out of a program-owned escrow account
+fn release_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    let remaining = from.lamports()
+        .checked_sub(amount)
+        .ok_or(ErrorCode::NumericalOverflow)?;
+    let credited = to.lamports()
+        .checked_add(amount)
+        .ok_or(ErrorCode::NumericalOverflow)?;
+    **from.try_borrow_mut_lamports()? = remaining;
+    **to.try_borrow_mut_lamports()? = credited;
+    Ok(())
+}
+
+// Token payment accounts are optional for SOL-priced questions
+fn payment_account<T>(account: &Option<T>) -> Result<&T> {
+    account.as_ref().ok_or_else(|| error!(ErrorCode::MissingPaymentAccount))
+}
+
+// Transfer-fee settings of a Token-2022 payment mint, if it has any
+fn transfer_fee_config(
+    mint: &InterfaceAccount<token_interface::Mint>,
+) -> Result<Option<TransferFeeConfig>> {
+    let info = mint.to_account_info();
+    if *info.owner != spl_token_2022::ID {
+        return Ok(None);
+    }
+    let data = info.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    Ok(state.get_extension::<TransferFeeConfig>().ok().copied())
+}
+
+// Portion of `amount` a transfer of this mint withholds from the recipient
+fn transfer_fee(mint: &InterfaceAccount<token_interface::Mint>, amount: u64) -> Result<u64> {
+    match transfer_fee_config(mint)? {
+        Some(config) => config
+            .calculate_epoch_fee(Clock::get()?.epoch, amount)
+            .ok_or_else(|| error!(ErrorCode::NumericalOverflow)),
+        None => Ok(0),
+    }
+}
+
+// Portion of `amount` the recipient of a transfer of this mint actually receives
+fn net_of_transfer_fee(mint: &InterfaceAccount<token_interface::Mint>, amount: u64) -> Result<u64> {
+    amount
+        .checked_sub(transfer_fee(mint, amount)?)
+        .ok_or_else(|| error!(ErrorCode::NumericalOverflow))
+}
+
+// Moves payment tokens with transfer_checked so Token-2022 mints work too
+fn transfer_payment<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    from: &InterfaceAccount<'info, token_interface::TokenAccount>,
+    to: &InterfaceAccount<'info, token_interface::TokenAccount>,
+    mint: &InterfaceAccount<'info, token_interface::Mint>,
+    authority: AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: from.to_account_info(),
+                mint: mint.to_account_info(),
+                to: to.to_account_info(),
+                authority,
+            },
+            signer_seeds,
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+// Closes an emptied payment escrow. Token-2022 refuses to close accounts holding
+// withheld transfer fees, so those are swept to the mint first.
+fn close_payment_escrow<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    escrow: &InterfaceAccount<'info, token_interface::TokenAccount>,
+    mint: &InterfaceAccount<'info, token_interface::Mint>,
+    destination: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    if transfer_fee_config(mint)?.is_some() {
+        let harvest = spl_token_2022::extension::transfer_fee::instruction::harvest_withheld_tokens_to_mint(
+            &token_program.key(),
+            &mint.key(),
+            &[&escrow.key()],
+        )?;
+        invoke(
+            &harvest,
+            &[mint.to_account_info(), escrow.to_account_info(), token_program.to_account_info()],
+        )?;
+    }
+
+    token_interface::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        token_interface::CloseAccount {
+            account: escrow.to_account_info(),
+            destination,
+            authority,
+        },
+        signer_seeds,
+    ))
+}
+
+// Payment accounts of a primary key sale; the token or SOL set may be absent.
+// The creator accounts receive the creator share, which may be a refund escrow.
+struct UnlockPaymentAccounts<'a, 'info> {
+    buyer_token_account: &'a Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    treasury_token_account: &'a Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    creator_token_account: &'a Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    payment_mint: &'a Option<InterfaceAccount<'info, token_interface::Mint>>,
+    payment_mint_config: &'a Option<Account<'info, PaymentMint>>,
+    treasury: &'a Option<UncheckedAccount<'info>>,
+    creator: Option<AccountInfo<'info>>,
+    payment_token_program: &'a Interface<'info, TokenInterface>,
+    system_program: &'a Program<'info, System>,
+}
+
+// Treasury's cut of a primary sale
+fn platform_fee(unlock_price: u64, platform_fee_bps: u16) -> Result<u64> {
+    Ok(unlock_price
+        .checked_mul(platform_fee_bps as u64)
+        .ok_or(ErrorCode::NumericalOverflow)?
+        .checked_div(10000)
+        .ok_or(ErrorCode::NumericalOverflow)?)
+}
+
+// Charges a primary sale, splitting the price between the treasury and the
+// question creator, and returns the creator share as received after transfer fees
+fn collect_unlock_payment<'info>(
+    payment_type: PaymentType,
+    unlock_price: u64,
+    platform_fee_bps: u16,
+    buyer: &Signer<'info>,
+    accounts: UnlockPaymentAccounts<'_, 'info>,
+) -> Result<u64> {
+    // calculate fees
+    let platform_fee = platform_fee(unlock_price, platform_fee_bps)?;
+    let creator_payment = unlock_price
+        .checked_sub(platform_fee)
+        .ok_or(ErrorCode::NumericalOverflow)?;
+
+    match payment_type {
+        PaymentType::Token => {
+            let buyer_token_account = payment_account(accounts.buyer_token_account)?;
+            let payment_mint = payment_account(accounts.payment_mint)?;
+            payment_account(accounts.payment_mint_config)?;
+
+            // balance check before transferring
+            require!(
+                buyer_token_account.amount >= unlock_price,
+                ErrorCode::InsufficientFunds
+            );
+
+            // transfer payment tokens
+            transfer_payment(
+                accounts.payment_token_program,
+                buyer_token_account,
+                payment_account(accounts.treasury_token_account)?,
+                payment_mint,
+                buyer.to_account_info(),
+                &[],
+                platform_fee,
+            )?;
+            transfer_payment(
+                accounts.payment_token_program,
+                buyer_token_account,
+                payment_account(accounts.creator_token_account)?,
+                payment_mint,
+                buyer.to_account_info(),
+                &[],
+                creator_payment,
+            )?;
+
+            net_of_transfer_fee(payment_mint, creator_payment)
+        }
+        PaymentType::Sol => {
+            require!(
+                buyer.lamports() >= unlock_price,
+                ErrorCode::InsufficientFunds
+            );
+
+            transfer_lamports(
+                accounts.system_program,
+                buyer,
+                &payment_account(accounts.treasury)?.to_account_info(),
+                platform_fee,
+            )?;
+            transfer_lamports(
+                accounts.system_program,
+                buyer,
+                payment_account(&accounts.creator)?,
+                creator_payment,
+            )?;
+
+            Ok(creator_payment)
+        }
+    }
+}
+
+// Accounts that pass a creator share on from the question creator's vault to the
+// question's split payees. Every payee takes a group of remaining accounts in
+// split order: its creator_vault, plus its creator_vault_tokens for token questions.
+struct SplitPayoutAccounts<'a, 'info> {
+    creator_vault: AccountInfo<'info>,
+    creator_vault_tokens: Option<&'a InterfaceAccount<'info, token_interface::TokenAccount>>,
+    payment_mint: Option<&'a InterfaceAccount<'info, token_interface::Mint>>,
+    listing_authority: Option<AccountInfo<'info>>,
+    listing_authority_bump: u8,
+    payment_token_program: &'a Interface<'info, TokenInterface>,
+    payee_accounts: &'a [AccountInfo<'info>],
+}
+
+// Who a creator share belongs to, read from the question or from the snapshot a
+// refund escrow took of it. No payees means the creator keeps everything.
+struct CreatorShare<'a> {
+    creator: Pubkey,
+    payment_type: PaymentType,
+    payment_mint: Pubkey,
+    payees: &'a [SplitPayee],
+}
+
+impl<'a> CreatorShare<'a> {
+    fn of_question(
+        question: &Question,
+        question_split: &'a Option<Account<'_, QuestionSplit>>,
+    ) -> Result<Self> {
+        require!(
+            question.has_split == question_split.is_some(),
+            ErrorCode::InvalidSplitAccounts
+        );
+        Ok(Self {
+            creator: question.creator,
+            payment_type: question.payment_type,
+            payment_mint: question.payment_mint,
+            payees: question_split
+                .as_ref()
+                .map(|split| split.payees.as_slice())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+// Splits `amount`, which the creator's vault has just received, between the
+// share's payees and returns the part the creator keeps
+fn distribute_creator_share<'info>(
+    share: &CreatorShare<'_>,
+    amount: u64,
+    accounts: SplitPayoutAccounts<'_, 'info>,
+) -> Result<u64> {
+    if share.payees.is_empty() {
+        return Ok(amount);
+    }
+
+    let accounts_per_payee = match share.payment_type {
+        PaymentType::Token => 2,
+        PaymentType::Sol => 1,
+    };
+    require!(
+        accounts.payee_accounts.len() >= share.payees.len() * accounts_per_payee,
+        ErrorCode::InvalidSplitAccounts
+    );
+
+    let mut kept = 0u64;
+    let payouts = share.payees
+        .iter()
+        .zip(QuestionSplit::portions(share.payees, amount)?)
+        .zip(accounts.payee_accounts.chunks(accounts_per_payee));
+    for ((payee, portion), payee_accounts) in payouts {
+        if payee.payee == share.creator {
+            kept = kept.checked_add(portion).ok_or(ErrorCode::NumericalOverflow)?;
+            continue;
+        }
+        if portion == 0 {
+            continue;
+        }
+
+        let vault_info = &payee_accounts[0];
+        require_keys_eq!(*vault_info.owner, crate::ID, ErrorCode::InvalidSplitAccounts);
+        let mut payee_vault = CreatorVault::try_deserialize(&mut &vault_info.try_borrow_data()?[..])?;
+        require!(
+            payee_vault.creator == payee.payee && payee_vault.payment_mint == share.payment_mint,
+            ErrorCode::InvalidSplitAccounts
+        );
+        let earned = match share.payment_type {
+            PaymentType::Token => {
+                let (vault_tokens_address, _) = Pubkey::find_program_address(
+                    &[b"creator_vault_tokens", vault_info.key().as_ref()],
+                    &crate::ID,
+                );
+                require_keys_eq!(
+                    payee_accounts[1].key(),
+                    vault_tokens_address,
+                    ErrorCode::InvalidSplitAccounts
+                );
+                let payment_mint = accounts.payment_mint
+                    .ok_or(ErrorCode::MissingPaymentAccount)?;
+                let creator_vault_tokens = accounts.creator_vault_tokens
+                    .ok_or(ErrorCode::MissingPaymentAccount)?;
+                let listing_authority = accounts.listing_authority
+                    .clone()
+                    .ok_or(ErrorCode::MissingPaymentAccount)?;
+                // the vault token account was created at this address with the
+                // payment mint and listing authority, so it needs no further checks
+                token_interface::transfer_checked(
+                    CpiContext::new_with_signer(
+                        accounts.payment_token_program.to_account_info(),
+                        TransferChecked {
+                            from: creator_vault_tokens.to_account_info(),
+                            mint: payment_mint.to_account_info(),
+                            to: payee_accounts[1].clone(),
+                            authority: listing_authority,
+                        },
+                        &[&[b"listing_authority", &[accounts.listing_authority_bump]]],
+                    ),
+                    portion,
+                    payment_mint.decimals,
+                )?;
+                net_of_transfer_fee(payment_mint, portion)?
+            }
+            PaymentType::Sol => {
+                release_lamports(&accounts.creator_vault, vault_info, portion)?;
+                portion
+            }
+        };
+        payee_vault.record_earnings(earned)?;
+        payee_vault.try_serialize(&mut &mut vault_info.try_borrow_mut_data()?[..])?;
+    }
+
+    Ok(kept)
+}
+
+// Collection a key NFT is verified into
+struct KeyCollectionAccounts<'info> {
+    mint: AccountInfo<'info>,
+    metadata: AccountInfo<'info>,
+    master_edition: AccountInfo<'info>,
+}
+
+// Collection accounts are required once the question has a collection
+fn key_collection_accounts<'info>(
+    collection_mint: Option<Pubkey>,
+    mint: &Option<UncheckedAccount<'info>>,
+    metadata: &Option<UncheckedAccount<'info>>,
+    master_edition: &Option<UncheckedAccount<'info>>,
+) -> Result<Option<KeyCollectionAccounts<'info>>> {
+    if collection_mint.is_none() {
+        return Ok(None);
+    }
+    let collection_account = |account: &Option<UncheckedAccount<'info>>| {
+        account
+            .as_ref()
+            .map(|account| account.to_account_info())
+            .ok_or_else(|| error!(ErrorCode::InvalidCollection))
+    };
+    Ok(Some(KeyCollectionAccounts {
+        mint: collection_account(mint)?,
+        metadata: collection_account(metadata)?,
+        master_edition: collection_account(master_edition)?,
+    }))
+}
+
+// Creates a rent-exempt PDA account, as `init` would for a statically known account
+fn create_pda_account<'info>(
+    payer: &Signer<'info>,
+    account: &AccountInfo<'info>,
+    space: usize,
+    owner: &Pubkey,
+    system_program: &Program<'info, System>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    system_program::create_account(
+        CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            system_program::CreateAccount {
+                from: payer.to_account_info(),
+                to: account.clone(),
+            },
+            signer_seeds,
+        ),
+        Rent::get()?.minimum_balance(space),
+        space as u64,
+        owner,
+    )
+}
+
+// Accounts that turn an initialized key mint into a 1/1 Metaplex NFT
+struct KeyNftAccounts<'info> {
+    mint: AccountInfo<'info>,
+    token_account: AccountInfo<'info>,
+    metadata: AccountInfo<'info>,
+    master_edition: AccountInfo<'info>,
+    mint_authority: AccountInfo<'info>,
+    update_authority: AccountInfo<'info>,
+    payer: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    metadata_program: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+    rent: AccountInfo<'info>,
+    collection: Option<KeyCollectionAccounts<'info>>,
+}
+
+// Metaplex creators for a key NFT. The mint authority PDA signs the mint, which proves
+// the key came from this marketplace; the question creator, or the split payees by
+// their shares, follow unverified and sign later through verify_key_creator.
+fn key_nft_creators(mint_authority: Pubkey, share: &CreatorShare<'_>) -> Result<Vec<Creator>> {
+    let mut creators = vec![Creator {
+        address: mint_authority,
+        verified: true,
+        share: 0,
+    }];
+    if share.payees.is_empty() {
+        creators.push(Creator {
+            address: share.creator,
+            verified: false,
+            share: 100,
+        });
+        return Ok(creators);
+    }
+
+    // Metaplex shares are whole percentages
+    let percentages = QuestionSplit::portions(share.payees, 100)?;
+    for (payee, percentage) in share.payees.iter().zip(percentages) {
+        creators.push(Creator {
+            address: payee.payee,
+            verified: false,
+            share: percentage as u8,
+        });
+    }
+    Ok(creators)
+}
+
+// Mints the key's single token, then creates its metadata and a max-supply-0 master edition
+fn mint_key_nft<'info>(
+    accounts: KeyNftAccounts<'info>,
+    name: String,
+    metadata_uri: String,
+    creators: Vec<Creator>,
+    royalty_bps: u16,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    // mint the single NFT token into the buyer's ATA
+    token::mint_to(
+        CpiContext::new_with_signer(
+            accounts.token_program.clone(),
+            MintTo {
+                mint: accounts.mint.clone(),
+                to: accounts.token_account.clone(),
+                authority: accounts.mint_authority.clone(),
+            },
+            signer_seeds,
+        ),
+        1,
+    )?;
+
+    // create Metaplex metadata
+    create_metadata_accounts_v3(
+        CpiContext::new_with_signer(
+            accounts.metadata_program.clone(),
+            CreateMetadataAccountsV3 {
+                metadata: accounts.metadata.clone(),
+                mint: accounts.mint.clone(),
+                mint_authority: accounts.mint_authority.clone(),
+                update_authority: accounts.update_authority.clone(),
+                payer: accounts.payer.clone(),
+                system_program: accounts.system_program.clone(),
+                rent: accounts.rent.clone(),
+            },
+            signer_seeds,
+        ),
+        DataV2 {
+            name,
+            symbol: "QAK".to_string(),
+            uri: metadata_uri,
+            seller_fee_basis_points: royalty_bps,
+            creators: Some(creators),
+            collection: accounts.collection.as_ref().map(|collection| Collection {
+                verified: false,
+                key: collection.mint.key(),
+            }),
+            uses: None,
+        },
+        true, // is_mutable
+        true, // update_authority_is_signer
+        None, // collection_details
+    )?;
+
+    // create the master edition with max supply 0 so the key is a 1/1 NFT
+    create_master_edition_v3(
+        CpiContext::new_with_signer(
+            accounts.metadata_program.clone(),
+            CreateMasterEditionV3 {
+                edition: accounts.master_edition.clone(),
+                mint: accounts.mint.clone(),
+                update_authority: accounts.update_authority.clone(),
+                mint_authority: accounts.mint_authority.clone(),
+                payer: accounts.payer.clone(),
+                metadata: accounts.metadata.clone(),
+                token_program: accounts.token_program.clone(),
+                system_program: accounts.system_program.clone(),
+                rent: accounts.rent.clone(),
+            },
+            signer_seeds,
+        ),
+        Some(0),
+    )?;
+
+    // the mint authority PDA is the collection's update authority, so it can verify
+    if let Some(collection) = accounts.collection {
+        verify_sized_collection_item(
+            CpiContext::new_with_signer(
+                accounts.metadata_program,
+                VerifySizedCollectionItem {
+                    payer: accounts.payer,
+                    metadata: accounts.metadata,
+                    collection_authority: accounts.mint_authority,
+                    collection_mint: collection.mint,
+                    collection_metadata: collection.metadata,
+                    collection_master_edition: collection.master_edition,
+                },
+                signer_seeds,
+            ),
+            None,
+        )?;
+    }
+
+    Ok(())
+}
+
+// Leaf committed to by a question's allowlist root
+fn allowlist_leaf(buyer: &Pubkey, price: u64, max_quantity: u64) -> [u8; 32] {
+    keccak::hashv(&[
+        buyer.as_ref(),
+        &price.to_le_bytes(),
+        &max_quantity.to_le_bytes(),
+    ])
+    .0
+}
+
+// Sorted-pair Merkle proof check, so proofs need no left/right flags
+fn verify_merkle_proof(proof: &[[u8; 32]], root: [u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof.iter().fold(leaf, |node, sibling| {
+        if node <= *sibling {
+            keccak::hashv(&[&node, sibling]).0
+        } else {
+            keccak::hashv(&[sibling, &node]).0
+        }
+    });
+    computed == root
+}
+
+// Leaf a compressed key occupies in its question's key tree
+fn compressed_key_leaf(
+    question: &Pubkey,
+    token_id: u64,
+    owner: &Pubkey,
+    encrypted_key_hash: &[u8; 32],
+) -> [u8; 32] {
+    keccak::hashv(&[
+        question.as_ref(),
+        &token_id.to_le_bytes(),
+        owner.as_ref(),
+        encrypted_key_hash,
+    ])
+    .0
+}
+
+fn hash_key_tree_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    keccak::hashv(&[left, right]).0
+}
+
+// Roots of all-empty subtrees, indexed by level
+fn empty_key_tree_nodes(max_depth: u8) -> Vec<[u8; 32]> {
+    let mut nodes = vec![[0u8; 32]];
+    for level in 0..max_depth as usize {
+        let node = hash_key_tree_pair(&nodes[level], &nodes[level]);
+        nodes.push(node);
+    }
+    nodes
+}
+
+// Walks a positional proof up from the leaf, returning the root and the nodes on the way
+fn key_tree_path(leaf: [u8; 32], proof: &[[u8; 32]], index: u32) -> ([u8; 32], Vec<[u8; 32]>) {
+    let mut path = Vec::with_capacity(proof.len());
+    let node = proof.iter().enumerate().fold(leaf, |node, (level, sibling)| {
+        path.push(node);
+        if (index >> level) & 1 == 0 {
+            hash_key_tree_pair(&node, sibling)
+        } else {
+            hash_key_tree_pair(sibling, &node)
+        }
+    });
+    (node, path)
+}
+
 #[program]
 pub mod myqa {
     use super::*;
@@ -76,6 +829,7 @@ pub mod myqa {
         marketplace.paused = false;
         marketplace.paused_operations = PausedOperations::default();
         marketplace.bonk_mint = ctx.accounts.bonk_mint.key();
+        marketplace.key_custodian = ctx.accounts.authority.key();
 
         emit!(MarketplaceInitialized {
             authority: marketplace.authority,
@@ -100,6 +854,53 @@ pub mod myqa {
         Ok(())
     }
 
+    pub fn update_key_custodian(ctx: Context<UpdateKeyCustodian>, new_custodian: Pubkey) -> Result<()> {
+        let marketplace = &mut ctx.accounts.marketplace;
+
+        emit!(KeyCustodianUpdated {
+            previous_custodian: marketplace.key_custodian,
+            new_custodian,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        marketplace.key_custodian = new_custodian;
+        Ok(())
+    }
+
+    pub fn add_payment_mint(ctx: Context<AddPaymentMint>) -> Result<()> {
+        let config = &mut ctx.accounts.payment_mint_config;
+        config.marketplace = ctx.accounts.marketplace.key();
+        config.mint = ctx.accounts.mint.key();
+        config.decimals = ctx.accounts.mint.decimals;
+        config.enabled = true;
+
+        emit!(PaymentMintUpdated {
+            mint: config.mint,
+            decimals: config.decimals,
+            enabled: config.enabled,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_payment_mint_enabled(
+        ctx: Context<SetPaymentMintEnabled>,
+        enabled: bool,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.payment_mint_config;
+        config.enabled = enabled;
+
+        emit!(PaymentMintUpdated {
+            mint: config.mint,
+            decimals: config.decimals,
+            enabled,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
     pub fn update_fees(
         ctx: Context<UpdateFees>,
         new_platform_fee_bps: u16,
@@ -127,12 +928,18 @@ pub mod myqa {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn create_question(
         ctx: Context<CreateQuestion>,
         content_cid: String,
         content_hash: [u8; 32],
         unlock_price: u64,
         max_keys: u64,
+        pricing_curve: PricingCurve,
+        launch_schedule: Option<LaunchSchedule>,
+        payment_type: PaymentType,
+        royalty_bps: u16,
+        payees: Vec<SplitPayee>,
     ) -> Result<()> {
 
         require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
@@ -160,22 +967,29 @@ pub mod myqa {
         );
 
         // Validate CID format and length
-        require!(
-            content_cid.len() >= IPFS_CID_LENGTH && content_cid.len() <= MAX_CID_LENGTH,
-            ErrorCode::InvalidCIDFormat
-        );
-        require!(
-            content_cid.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
-            ErrorCode::InvalidCIDFormat
-        );
+        validate_content_cid(&content_cid)?;
 
         // Input validation
         require!(max_keys > 0, ErrorCode::InvalidKeyCount);
         require!(unlock_price > 0, ErrorCode::InvalidPrice);
+        pricing_curve.validate(unlock_price, max_keys)?;
+        if let Some(schedule) = &launch_schedule {
+            schedule.validate(unlock_price)?;
+        }
+
+        require!(royalty_bps <= MAX_FEE_BPS, ErrorCode::FeeTooHigh);
+        // co-authored questions share the creator's earnings between the payees
+        require!(
+            payees.is_empty() == ctx.accounts.question_split.is_none(),
+            ErrorCode::InvalidSplitAccounts
+        );
+        if !payees.is_empty() {
+            QuestionSplit::validate(&payees)?;
+        }
 
         // Add total fee validation
         let total_fee_bps = ctx.accounts.marketplace.platform_fee_bps
-            .checked_add(ctx.accounts.marketplace.creator_royalty_bps)
+            .checked_add(royalty_bps)
             .ok_or(ErrorCode::NumericalOverflow)?;
         require!(
             total_fee_bps <= MAX_TOTAL_FEE_BPS,
@@ -188,6 +1002,7 @@ pub mod myqa {
         question.creator = ctx.accounts.creator.key();
         question.content_cid = content_cid;
         question.content_hash = content_hash;
+        question.content_version = 1;
         question.unlock_price = unlock_price;
         question.max_keys = max_keys;
         question.current_keys = 0;
@@ -195,7 +1010,22 @@ pub mod myqa {
         question.creation_time = current_time;
         question.total_sales = 0;
         question.is_active = true;
+        question.is_taken_down = false;
         question.validation_timestamp = current_time;
+        question.pricing_curve = pricing_curve;
+        question.launch_schedule = launch_schedule;
+        question.allowlist_root = None;
+        question.payment_type = payment_type;
+        question.royalty_bps = royalty_bps;
+        question.key_mode = KeyMode::Standard;
+        question.max_keys_per_wallet = None;
+        question.refund_window = None;
+        question.has_split = !payees.is_empty();
+        question.open_escrows = 0;
+        question.payment_mint = match payment_type {
+            PaymentType::Token => payment_account(&ctx.accounts.payment_mint_config)?.mint,
+            PaymentType::Sol => Pubkey::default(),
+        };
         
         marketplace.question_counter = marketplace.question_counter
             .checked_add(1)
@@ -205,10 +1035,22 @@ pub mod myqa {
             question_id: question.index,
             creator: question.creator,
             unlock_price,
+            payment_mint: question.payment_mint,
+            payment_type,
+            royalty_bps,
             max_keys,
             creation_time: question.creation_time,
         });
 
+        if let Some(question_split) = &mut ctx.accounts.question_split {
+            question_split.question = question.key();
+            question_split.payees = payees.clone();
+            emit!(QuestionSplitCreated {
+                question_id: question.index,
+                payees,
+            });
+        }
+
         // Update rate limiting state
         user_state.questions_created = user_state.questions_created
             .checked_add(1)
@@ -222,188 +1064,377 @@ pub mod myqa {
         Ok(())
     }
 
-    pub fn mint_unlock_key(
-        ctx: Context<MintUnlockKey>,
+    pub fn mint_unlock_key<'info>(
+        ctx: Context<'_, '_, '_, 'info, MintUnlockKey<'info>>,
         metadata_uri: String,
         encrypted_key: Vec<u8>,
+        max_price: u64,
+        expected_token_id: u64,
     ) -> Result<()> {
-        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
-        
+        ctx.accounts.validate_mint(&metadata_uri, &encrypted_key)?;
         require!(
-            !ctx.accounts.marketplace.paused_operations.mint_key,
-            ErrorCode::OperationPaused
+            ctx.accounts.question.current_keys == expected_token_id,
+            ErrorCode::UnexpectedTokenId
         );
 
-        require!(ctx.accounts.question.is_active, ErrorCode::QuestionInactive);
-        require!(metadata_uri.len() <= MAX_URI_LENGTH, ErrorCode::URITooLong);
+        // the price may have moved since the buyer signed
+        let unlock_price = ctx.accounts.question
+            .next_unlock_price(Clock::get()?.unix_timestamp)?;
+        require!(unlock_price <= max_price, ErrorCode::PriceExceedsMaximum);
+        ctx.accounts.process_mint(
+            ctx.bumps.mint_authority,
+            ctx.bumps.listing_authority,
+            ctx.remaining_accounts,
+            metadata_uri,
+            encrypted_key,
+            unlock_price,
+        )
+    }
 
+    // Allowlisted wallets pay their proven price, and may mint ahead of the launch schedule
+    pub fn mint_unlock_key_with_proof<'info>(
+        ctx: Context<'_, '_, '_, 'info, MintUnlockKeyWithProof<'info>>,
+        metadata_uri: String,
+        encrypted_key: Vec<u8>,
+        price: u64,
+        max_quantity: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        ctx.accounts.mint_key.validate_mint(&metadata_uri, &encrypted_key)?;
         require!(
-            ctx.accounts.question.current_keys < ctx.accounts.question.max_keys,
-            ErrorCode::NoKeysAvailable
+            proof.len() <= MAX_ALLOWLIST_PROOF_LENGTH,
+            ErrorCode::InvalidAllowlistProof
         );
 
-        // input validation
+        // the proven price replaces the scheduled one, but the sale still opens at start_time
+        if let Some(schedule) = &ctx.accounts.mint_key.question.launch_schedule {
+            schedule.require_started(Clock::get()?.unix_timestamp)?;
+        }
+
+        let question_key = ctx.accounts.mint_key.question.key();
+        let buyer = ctx.accounts.mint_key.buyer.key();
+        let root = ctx.accounts.mint_key.question.allowlist_root
+            .ok_or(ErrorCode::AllowlistNotSet)?;
         require!(
-            encrypted_key.len() <= MAX_ENCRYPTED_KEY_LENGTH,
-            ErrorCode::InvalidKeyLength
+            verify_merkle_proof(&proof, root, allowlist_leaf(&buyer, price, max_quantity)),
+            ErrorCode::InvalidAllowlistProof
         );
+
+        let claim = &mut ctx.accounts.allowlist_claim;
+        require!(claim.claimed < max_quantity, ErrorCode::AllowlistClaimExceeded);
+        claim.question = question_key;
+        claim.buyer = buyer;
+        claim.claimed = claim.claimed
+            .checked_add(1)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+
+        emit!(AllowlistClaimed {
+            question_id: ctx.accounts.mint_key.question.index,
+            buyer,
+            price,
+            claimed: claim.claimed,
+            max_quantity,
+        });
+
+        ctx.accounts.mint_key.process_mint(
+            ctx.bumps.mint_key.mint_authority,
+            ctx.bumps.mint_key.listing_authority,
+            ctx.remaining_accounts,
+            metadata_uri,
+            encrypted_key,
+            price,
+        )
+    }
+
+    // Mints `quantity` consecutive keys to one buyer for their summed price, paid in
+    // a single pair of transfers. Each key takes BATCH_ACCOUNTS_PER_KEY remaining
+    // accounts: unlock_key, key mint, buyer key token account, metadata, master edition.
+    // Split payee vaults follow the key accounts.
+    pub fn mint_unlock_keys_batch<'info>(
+        ctx: Context<'_, '_, '_, 'info, MintUnlockKeysBatch<'info>>,
+        metadata_uri: String,
+        quantity: u8,
+        encrypted_keys: Vec<Vec<u8>>,
+        max_total_price: u64,
+        expected_token_id: u64,
+    ) -> Result<()> {
+        let marketplace = &ctx.accounts.marketplace;
+        let question = &ctx.accounts.question;
+        require!(!marketplace.paused, ErrorCode::MarketplacePaused);
         require!(
-            metadata_uri.chars().all(|c| c.is_ascii()),
-            ErrorCode::InvalidMetadataFormat
+            !marketplace.paused_operations.mint_key,
+            ErrorCode::OperationPaused
         );
+        require!(question.is_active, ErrorCode::QuestionInactive);
         require!(
-            metadata_uri.len() >= MIN_METADATA_LENGTH,
+            question.key_mode == KeyMode::Standard,
+            ErrorCode::UnsupportedKeyMode
+        );
+        // refunds escrow per key, so refundable questions sell one key at a time
+        require!(question.refund_window.is_none(), ErrorCode::RefundEscrowMismatch);
+        require!(metadata_uri.len() <= MAX_URI_LENGTH, ErrorCode::URITooLong);
+        require!(
+            metadata_uri.is_ascii() && metadata_uri.len() >= MIN_METADATA_LENGTH,
             ErrorCode::InvalidMetadataFormat
         );
 
-        // Update BONK token validation
         require!(
-            ctx.accounts.bonk_mint.key() == ctx.accounts.marketplace.bonk_mint,
-            ErrorCode::InvalidBonkMint
+            quantity > 0
+                && quantity <= MAX_KEYS_PER_PURCHASE
+                && encrypted_keys.len() == quantity as usize,
+            ErrorCode::InvalidBatchSize
+        );
+        require!(
+            encrypted_keys.iter().all(|key| key.len() <= MAX_ENCRYPTED_KEY_LENGTH),
+            ErrorCode::InvalidKeyLength
+        );
+        require!(
+            ctx.remaining_accounts.len() >= quantity as usize * BATCH_ACCOUNTS_PER_KEY,
+            ErrorCode::InvalidBatchAccounts
         );
+        let (key_accounts, payee_accounts) = ctx.remaining_accounts
+            .split_at(quantity as usize * BATCH_ACCOUNTS_PER_KEY);
+        require!(
+            question.current_keys == expected_token_id,
+            ErrorCode::UnexpectedTokenId
+        );
+        let first_token_id = question.current_keys;
+        let end_token_id = first_token_id
+            .checked_add(quantity as u64)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+        require!(end_token_id <= question.max_keys, ErrorCode::NoKeysAvailable);
 
-        let question_key = ctx.accounts.question.key();
-        let current_keys = ctx.accounts.question.current_keys;
-        let unlock_price = ctx.accounts.question.unlock_price;
-        let platform_fee_bps = ctx.accounts.marketplace.platform_fee_bps;
-        let _creator = ctx.accounts.question.creator;
-        let _index = ctx.accounts.question.index;
+        // each key is priced as if it were minted on its own, in order
+        let now = Clock::get()?.unix_timestamp;
+        let prices = (first_token_id..end_token_id)
+            .map(|token_id| question.unlock_price_at(token_id, now))
+            .collect::<Result<Vec<u64>>>()?;
+        let total_price = prices
+            .iter()
+            .try_fold(0u64, |total, price| total.checked_add(*price))
+            .ok_or(ErrorCode::NumericalOverflow)?;
+        require!(total_price <= max_total_price, ErrorCode::PriceExceedsMaximum);
+        ctx.accounts.purchase_record.record(
+            &ctx.accounts.question,
+            ctx.accounts.buyer.key(),
+            quantity as u64,
+        )?;
+
+        let creator_payment = collect_unlock_payment(
+            question.payment_type,
+            total_price,
+            marketplace.platform_fee_bps,
+            &ctx.accounts.buyer,
+            UnlockPaymentAccounts {
+                buyer_token_account: &ctx.accounts.buyer_token_account,
+                treasury_token_account: &ctx.accounts.treasury_token_account,
+                creator_token_account: &ctx.accounts.creator_vault_tokens,
+                payment_mint: &ctx.accounts.payment_mint,
+                payment_mint_config: &ctx.accounts.payment_mint_config,
+                treasury: &ctx.accounts.treasury,
+                creator: Some(ctx.accounts.creator_vault.to_account_info()),
+                payment_token_program: &ctx.accounts.payment_token_program,
+                system_program: &ctx.accounts.system_program,
+            },
+        )?;
+        let kept = distribute_creator_share(
+            &CreatorShare::of_question(question, &ctx.accounts.question_split)?,
+            creator_payment,
+            SplitPayoutAccounts {
+                creator_vault: ctx.accounts.creator_vault.to_account_info(),
+                creator_vault_tokens: ctx.accounts.creator_vault_tokens.as_ref(),
+                payment_mint: ctx.accounts.payment_mint.as_ref(),
+                listing_authority: ctx.accounts.listing_authority
+                    .as_ref()
+                    .map(|authority| authority.to_account_info()),
+                listing_authority_bump: ctx.bumps.listing_authority,
+                payment_token_program: &ctx.accounts.payment_token_program,
+                payee_accounts,
+            },
+        )?;
+        ctx.accounts.creator_vault.credit(question, kept)?;
+
+        let accounts = &ctx.accounts;
+        let question_key = question.key();
+        let buyer = accounts.buyer.key();
+        let mint_authority = accounts.mint_authority.key();
+        let mint_authority_seeds: &[&[&[u8]]] =
+            &[&[b"mint_authority", &[ctx.bumps.mint_authority]]];
+        let creators = key_nft_creators(
+            mint_authority,
+            &CreatorShare::of_question(question, &accounts.question_split)?,
+        )?;
+
+        let key_accounts = key_accounts.chunks(BATCH_ACCOUNTS_PER_KEY);
+        for (((token_id, price), encrypted_key), key_accounts) in (first_token_id..end_token_id)
+            .zip(prices)
+            .zip(encrypted_keys)
+            .zip(key_accounts)
+        {
+            let [unlock_key, mint, buyer_key_account, metadata, master_edition] = key_accounts else {
+                return err!(ErrorCode::InvalidBatchAccounts);
+            };
+
+            let token_id_bytes = token_id.to_le_bytes();
+            let (unlock_key_address, unlock_key_bump) = Pubkey::find_program_address(
+                &[b"unlock_key", question_key.as_ref(), &token_id_bytes],
+                ctx.program_id,
+            );
+            require_keys_eq!(unlock_key.key(), unlock_key_address, ErrorCode::InvalidBatchAccounts);
+            let (mint_address, mint_bump) = Pubkey::find_program_address(
+                &[b"key_mint", unlock_key_address.as_ref()],
+                ctx.program_id,
+            );
+            require_keys_eq!(mint.key(), mint_address, ErrorCode::InvalidBatchAccounts);
+
+            // the same accounts `init` creates for a single mint
+            let space = UNLOCK_KEY_BASE_SIZE + 4 + encrypted_key.len() + 4 + metadata_uri.len();
+            create_pda_account(
+                &accounts.buyer,
+                unlock_key,
+                space,
+                ctx.program_id,
+                &accounts.system_program,
+                &[&[b"unlock_key", question_key.as_ref(), &token_id_bytes, &[unlock_key_bump]]],
+            )?;
+            create_pda_account(
+                &accounts.buyer,
+                mint,
+                Mint::LEN,
+                &accounts.token_program.key(),
+                &accounts.system_program,
+                &[&[b"key_mint", unlock_key_address.as_ref(), &[mint_bump]]],
+            )?;
+            token::initialize_mint2(
+                CpiContext::new(
+                    accounts.token_program.to_account_info(),
+                    token::InitializeMint2 { mint: mint.clone() },
+                ),
+                0,
+                &mint_authority,
+                Some(&mint_authority),
+            )?;
+            associated_token::create(CpiContext::new(
+                accounts.associated_token_program.to_account_info(),
+                associated_token::Create {
+                    payer: accounts.buyer.to_account_info(),
+                    associated_token: buyer_key_account.clone(),
+                    authority: accounts.buyer.to_account_info(),
+                    mint: mint.clone(),
+                    system_program: accounts.system_program.to_account_info(),
+                    token_program: accounts.token_program.to_account_info(),
+                },
+            ))?;
+
+            mint_key_nft(
+                KeyNftAccounts {
+                    mint: mint.clone(),
+                    token_account: buyer_key_account.clone(),
+                    metadata: metadata.clone(),
+                    master_edition: master_edition.clone(),
+                    mint_authority: accounts.mint_authority.to_account_info(),
+                    update_authority: accounts.mint_authority.to_account_info(),
+                    payer: accounts.buyer.to_account_info(),
+                    token_program: accounts.token_program.to_account_info(),
+                    metadata_program: accounts.metadata_program.to_account_info(),
+                    system_program: accounts.system_program.to_account_info(),
+                    rent: accounts.rent.to_account_info(),
+                    collection: key_collection_accounts(
+                        question.collection_mint,
+                        &accounts.collection_mint,
+                        &accounts.collection_metadata,
+                        &accounts.collection_master_edition,
+                    )?,
+                },
+                format!("QA Key #{} - Q{}", token_id, question.index),
+                metadata_uri.clone(),
+                creators.clone(),
+                question.royalty_bps,
+                mint_authority_seeds,
+            )?;
+
+            let key = UnlockKey {
+                discriminator: UNLOCK_KEY_DISCRIMINATOR,
+                owner: buyer,
+                question: question_key,
+                token_id,
+                mint: mint_address,
+                encrypted_key,
+                is_listed: false,
+                list_price: 0,
+                mint_time: now,
+                metadata_uri: metadata_uri.clone(),
+                last_sold_price: 0,
+                last_sold_time: 0,
+                list_time: 0,
+                reencryption_pending: false,
+            };
+            key.try_serialize(&mut &mut unlock_key.try_borrow_mut_data()?[..])?;
+
+            emit!(KeyMinted {
+                key_id: token_id,
+                question_id: question.index,
+                owner: buyer,
+                mint_time: now,
+                price,
+            });
+        }
 
         let question = &mut ctx.accounts.question;
+        question.current_keys = end_token_id;
+        question.total_sales = question.total_sales
+            .checked_add(total_price)
+            .ok_or(ErrorCode::NumericalOverflow)?;
         let marketplace = &mut ctx.accounts.marketplace;
-        let key = &mut ctx.accounts.unlock_key;
+        marketplace.total_volume = marketplace.total_volume
+            .checked_add(total_price)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+
+        #[cfg(not(feature = "test"))]
+        {
+            ctx.accounts.user_state.last_operation_time = now;
+        }
+
+        Ok(())
+    }
 
-        // balance check before calculating fees
+    pub fn list_key(
+        ctx: Context<ListKey>,
+        price: u64
+    ) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
         require!(
-            ctx.accounts.buyer_token_account.amount >= unlock_price,
-            ErrorCode::InsufficientFunds
+            !ctx.accounts.marketplace.paused_operations.list_key,
+            ErrorCode::OperationPaused
         );
 
-        // calculate fees
-        let platform_fee = (unlock_price * platform_fee_bps as u64)
-            .checked_div(10000)
-            .ok_or(ErrorCode::NumericalOverflow)?;
-        let creator_payment = unlock_price
-            .checked_sub(platform_fee)
-            .ok_or(ErrorCode::NumericalOverflow)?;
+        require!(price > 0, ErrorCode::InvalidPrice);
+        
+        let key = &mut ctx.accounts.unlock_key;
+        require!(key.owner == ctx.accounts.seller.key(), ErrorCode::KeyOwnerOutOfSync);
+        require!(!key.is_listed, ErrorCode::AlreadyListed);
 
-        // transfer BONK tokens
+        // custody the key NFT in the listing escrow while it is for sale
         token::transfer(
             CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
                 Transfer {
-                    from: ctx.accounts.buyer_token_account.to_account_info(),
-                    to: ctx.accounts.treasury_token_account.to_account_info(),
-                    authority: ctx.accounts.buyer.to_account_info(),
+                    from: ctx.accounts.seller_key_account.to_account_info(),
+                    to: ctx.accounts.listing_escrow.to_account_info(),
+                    authority: ctx.accounts.seller.to_account_info(),
                 },
             ),
-            platform_fee,
+            1,
         )?;
+        
+        key.is_listed = true;
+        key.list_price = price;
+        key.list_time = Clock::get()?.unix_timestamp;
 
-        token::transfer(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.buyer_token_account.to_account_info(),
-                    to: ctx.accounts.creator_token_account.to_account_info(),
-                    authority: ctx.accounts.buyer.to_account_info(),
-                },
-            ),
-            creator_payment,
-        )?;
-
-        // Set the discriminator
-        key.discriminator = UNLOCK_KEY_DISCRIMINATOR;
-        
-        // store the encrypted symmetric key
-        key.encrypted_key = encrypted_key;
-        
-        // create NFT metadata and mint token
-        key.owner = ctx.accounts.buyer.key();
-        key.question = question_key;
-        key.token_id = current_keys;
-        key.is_listed = false;
-        key.list_price = 0;
-        key.mint_time = Clock::get()?.unix_timestamp;
-        key.metadata_uri = metadata_uri.clone();
-        
-        // update statistics
-        question.current_keys = question.current_keys
+        let question = &mut ctx.accounts.question;
+        question.open_escrows = question.open_escrows
             .checked_add(1)
             .ok_or(ErrorCode::NumericalOverflow)?;
-        question.total_sales = question.total_sales
-            .checked_add(question.unlock_price)
-            .ok_or(ErrorCode::NumericalOverflow)?;
-        marketplace.total_volume = marketplace.total_volume
-            .checked_add(question.unlock_price)
-            .ok_or(ErrorCode::NumericalOverflow)?;
-
-        // create Metaplex metadata
-        create_metadata_accounts_v3(
-            CpiContext::new_with_signer(
-                ctx.accounts.metadata_program.to_account_info(),
-                CreateMetadataAccountsV3 {
-                    metadata: ctx.accounts.metadata.to_account_info(),
-                    mint: ctx.accounts.mint.to_account_info(),
-                    mint_authority: ctx.accounts.mint_authority.to_account_info(),
-                    update_authority: ctx.accounts.update_authority.to_account_info(),
-                    payer: ctx.accounts.buyer.to_account_info(),
-                    system_program: ctx.accounts.system_program.to_account_info(),
-                    rent: ctx.accounts.rent.to_account_info(),
-                },
-                &[&[b"mint_authority", &[ctx.bumps.mint_authority]]],
-            ),
-            DataV2 {
-                name: format!("QA Key #{} - Q{}", key.token_id, question.index),
-                symbol: "QAK".to_string(),
-                uri: metadata_uri,
-                seller_fee_basis_points: 0,
-                creators: None,
-                collection: None,
-                uses: None,
-            },
-            true,  // is_mutable
-            false, // collection_details
-            None,  // uses
-        )?;
-
-        emit!(KeyMinted {
-            key_id: key.token_id,
-            question_id: question.index,
-            owner: key.owner,
-            mint_time: key.mint_time,
-            price: question.unlock_price,
-        });
-
-        // Update rate limiting state - only in non-test mode
-        #[cfg(not(feature = "test"))]
-        {
-            let current_time = Clock::get()?.unix_timestamp;
-            ctx.accounts.user_state.last_operation_time = current_time;
-        }
-
-        Ok(())
-    }
-
-    pub fn list_key(
-        ctx: Context<ListKey>,
-        price: u64
-    ) -> Result<()> {
-        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
-        require!(
-            !ctx.accounts.marketplace.paused_operations.list_key,
-            ErrorCode::OperationPaused
-        );
-
-        require!(price > 0, ErrorCode::InvalidPrice);
-        
-        let key = &mut ctx.accounts.unlock_key;
-        require!(key.owner == ctx.accounts.seller.key(), ErrorCode::NotKeyOwner);
-        require!(!key.is_listed, ErrorCode::AlreadyListed);
-        
-        key.is_listed = true;
-        key.list_price = price;
-        key.list_time = Clock::get()?.unix_timestamp;
 
         emit!(KeyListed {
             key_id: key.token_id,
@@ -445,10 +1476,37 @@ pub mod myqa {
         let key = &mut ctx.accounts.unlock_key;
         require!(key.owner == ctx.accounts.seller.key(), ErrorCode::NotKeyOwner);
         require!(key.is_listed, ErrorCode::NotListed);
+
+        // return the key NFT to the seller and close the escrow
+        let signer_seeds: &[&[&[u8]]] = &[&[b"listing_authority", &[ctx.bumps.listing_authority]]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.listing_escrow.to_account_info(),
+                    to: ctx.accounts.seller_key_account.to_account_info(),
+                    authority: ctx.accounts.listing_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            1,
+        )?;
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.listing_escrow.to_account_info(),
+                destination: ctx.accounts.seller.to_account_info(),
+                authority: ctx.accounts.listing_authority.to_account_info(),
+            },
+            signer_seeds,
+        ))?;
         
         key.is_listed = false;
         key.list_price = 0;
 
+        let question = &mut ctx.accounts.question;
+        question.open_escrows = question.open_escrows.saturating_sub(1);
+
         emit!(ListingCancelled {
             key_id: key.token_id,
             seller: key.owner,
@@ -459,7 +1517,8 @@ pub mod myqa {
 
     pub fn buy_listed_key(
         ctx: Context<BuyListedKey>,
-        new_encrypted_key: Vec<u8>,
+        max_price: u64,
+        expected_token_id: u64,
     ) -> Result<()> {
         require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
         require!(
@@ -467,92 +1526,235 @@ pub mod myqa {
             ErrorCode::OperationPaused
         );
 
-        // Add input validation
-        require!(
-            new_encrypted_key.len() <= MAX_ENCRYPTED_KEY_LENGTH,
-            ErrorCode::InvalidKeyLength
-        );
-
-      
         require!(ctx.accounts.question.is_active, ErrorCode::QuestionInactive);
         
+        let key_address = ctx.accounts.unlock_key.key();
         let key = &mut ctx.accounts.unlock_key;
         require!(key.is_listed, ErrorCode::NotListed);
         require!(key.owner != ctx.accounts.buyer.key(), ErrorCode::CannotBuyOwnKey);
 
+        require!(key.token_id == expected_token_id, ErrorCode::UnexpectedTokenId);
+
+        // the seller may have repriced the listing since the buyer signed
         let price = key.list_price;
-        
-        // balance check before calculating fees
+        require!(price <= max_price, ErrorCode::PriceExceedsMaximum);
+        let payment_type = ctx.accounts.question.payment_type;
+
+        // hold the payment in escrow until the seller hands over the key
+        let escrowed = match payment_type {
+            PaymentType::Token => {
+                let buyer_token_account = payment_account(&ctx.accounts.buyer_token_account)?;
+                let payment_mint = payment_account(&ctx.accounts.payment_mint)?;
+                require!(
+                    buyer_token_account.amount >= price,
+                    ErrorCode::InsufficientFunds
+                );
+
+                transfer_payment(
+                    &ctx.accounts.payment_token_program,
+                    buyer_token_account,
+                    payment_account(&ctx.accounts.sale_escrow)?,
+                    payment_mint,
+                    ctx.accounts.buyer.to_account_info(),
+                    &[],
+                    price,
+                )?;
+
+                // payouts can only come out of what the escrow actually received
+                price
+                    .checked_sub(transfer_fee(payment_mint, price)?)
+                    .ok_or(ErrorCode::NumericalOverflow)?
+            }
+            PaymentType::Sol => {
+                // lamports are escrowed on the pending sale account itself
+                require!(
+                    ctx.accounts.buyer.lamports() >= price,
+                    ErrorCode::InsufficientFunds
+                );
+                transfer_lamports(
+                    &ctx.accounts.system_program,
+                    &ctx.accounts.buyer,
+                    &ctx.accounts.pending_sale.to_account_info(),
+                    price,
+                )?;
+                price
+            }
+        };
+
+        let (platform_fee, creator_royalty, seller_payment) =
+            split_sale_proceeds(escrowed, &ctx.accounts.marketplace, &ctx.accounts.question)?;
+
+        let current_time = Clock::get()?.unix_timestamp;
+        let sale = &mut ctx.accounts.pending_sale;
+        sale.unlock_key = key_address;
+        sale.buyer = ctx.accounts.buyer.key();
+        sale.seller = key.owner;
+        sale.price = price;
+        sale.platform_fee = platform_fee;
+        sale.creator_royalty = creator_royalty;
+        sale.seller_payment = seller_payment;
+        sale.created_at = current_time;
+        sale.expires_at = current_time
+            .checked_add(SALE_FULFILLMENT_WINDOW)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+        sale.payment_type = payment_type;
+
+        // lock the listing while the sale is pending
+        key.is_listed = false;
+
+        emit!(SaleInitiated {
+            key_id: key.token_id,
+            question_id: ctx.accounts.question.index,
+            seller: sale.seller,
+            buyer: sale.buyer,
+            price,
+            expires_at: sale.expires_at,
+        });
+
+        // update rate limiting state - only in non-test mode
+        #[cfg(not(feature = "test"))]
+        {
+            ctx.accounts.user_state.last_operation_time = current_time;
+        }
+
+        Ok(())
+    }
+
+    pub fn fulfill_sale<'info>(
+        ctx: Context<'_, '_, '_, 'info, FulfillSale<'info>>,
+        new_encrypted_key: Vec<u8>,
+    ) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
         require!(
-            ctx.accounts.buyer_token_account.amount >= price,
-            ErrorCode::InsufficientFunds
+            new_encrypted_key.len() <= MAX_ENCRYPTED_KEY_LENGTH,
+            ErrorCode::InvalidKeyLength
         );
 
-        let platform_fee = (price * ctx.accounts.marketplace.platform_fee_bps as u64)
-            .checked_div(10000)
-            .ok_or(ErrorCode::NumericalOverflow)?;
-        let creator_royalty = (price * ctx.accounts.marketplace.creator_royalty_bps as u64)
-            .checked_div(10000)
-            .ok_or(ErrorCode::NumericalOverflow)?;
-        let seller_payment = price
-            .checked_sub(platform_fee)
-            .ok_or(ErrorCode::NumericalOverflow)?
-            .checked_sub(creator_royalty)
-            .ok_or(ErrorCode::NumericalOverflow)?;
+        let fulfiller = ctx.accounts.fulfiller.key();
+        require!(
+            fulfiller == ctx.accounts.seller.key()
+                || fulfiller == ctx.accounts.marketplace.key_custodian,
+            ErrorCode::NotKeyCustodian
+        );
 
-        // transfer payments
-        token::transfer(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.buyer_token_account.to_account_info(),
-                    to: ctx.accounts.treasury_token_account.to_account_info(),
-                    authority: ctx.accounts.buyer.to_account_info(),
-                },
-            ),
-            platform_fee,
-        )?;
+        let current_time = Clock::get()?.unix_timestamp;
+        let sale = &ctx.accounts.pending_sale;
+        require!(current_time <= sale.expires_at, ErrorCode::SaleExpired);
 
-        token::transfer(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.buyer_token_account.to_account_info(),
-                    to: ctx.accounts.creator_token_account.to_account_info(),
-                    authority: ctx.accounts.buyer.to_account_info(),
-                },
-            ),
-            creator_royalty,
+        let signer_seeds: &[&[&[u8]]] = &[&[b"listing_authority", &[ctx.bumps.listing_authority]]];
+
+        // release the escrowed payment, the royalty into the creator's vault
+        let royalty_earned = match sale.payment_type {
+            PaymentType::Token => {
+                let sale_escrow = payment_account(&ctx.accounts.sale_escrow)?;
+                let payment_mint = payment_account(&ctx.accounts.payment_mint)?;
+                let payouts = [
+                    (payment_account(&ctx.accounts.treasury_token_account)?, sale.platform_fee),
+                    (payment_account(&ctx.accounts.creator_vault_tokens)?, sale.creator_royalty),
+                    (payment_account(&ctx.accounts.seller_token_account)?, sale.seller_payment),
+                ];
+                for (recipient, amount) in payouts {
+                    transfer_payment(
+                        &ctx.accounts.payment_token_program,
+                        sale_escrow,
+                        recipient,
+                        payment_mint,
+                        ctx.accounts.listing_authority.to_account_info(),
+                        signer_seeds,
+                        amount,
+                    )?;
+                }
+
+                close_payment_escrow(
+                    &ctx.accounts.payment_token_program,
+                    sale_escrow,
+                    payment_mint,
+                    ctx.accounts.buyer.to_account_info(),
+                    ctx.accounts.listing_authority.to_account_info(),
+                    signer_seeds,
+                )?;
+                net_of_transfer_fee(payment_mint, sale.creator_royalty)?
+            }
+            PaymentType::Sol => {
+                // the pending sale rent goes back to the buyer when it closes
+                let escrow = ctx.accounts.pending_sale.to_account_info();
+                release_lamports(
+                    &escrow,
+                    &payment_account(&ctx.accounts.treasury)?.to_account_info(),
+                    sale.platform_fee,
+                )?;
+                release_lamports(
+                    &escrow,
+                    &ctx.accounts.creator_vault.to_account_info(),
+                    sale.creator_royalty,
+                )?;
+                release_lamports(
+                    &escrow,
+                    &ctx.accounts.seller.to_account_info(),
+                    sale.seller_payment,
+                )?;
+                sale.creator_royalty
+            }
+        };
+        let kept = distribute_creator_share(
+            &CreatorShare::of_question(&ctx.accounts.question, &ctx.accounts.question_split)?,
+            royalty_earned,
+            SplitPayoutAccounts {
+                creator_vault: ctx.accounts.creator_vault.to_account_info(),
+                creator_vault_tokens: ctx.accounts.creator_vault_tokens.as_ref(),
+                payment_mint: ctx.accounts.payment_mint.as_ref(),
+                listing_authority: Some(ctx.accounts.listing_authority.to_account_info()),
+                listing_authority_bump: ctx.bumps.listing_authority,
+                payment_token_program: &ctx.accounts.payment_token_program,
+                payee_accounts: ctx.remaining_accounts,
+            },
         )?;
+        ctx.accounts.creator_vault.credit(&ctx.accounts.question, kept)?;
 
+        // release the escrowed key NFT to the buyer and refund the escrow rent
         token::transfer(
-            CpiContext::new(
+            CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
                 Transfer {
-                    from: ctx.accounts.buyer_token_account.to_account_info(),
-                    to: ctx.accounts.seller_token_account.to_account_info(),
-                    authority: ctx.accounts.buyer.to_account_info(),
+                    from: ctx.accounts.listing_escrow.to_account_info(),
+                    to: ctx.accounts.buyer_key_account.to_account_info(),
+                    authority: ctx.accounts.listing_authority.to_account_info(),
                 },
+                signer_seeds,
             ),
-            seller_payment,
+            1,
         )?;
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.listing_escrow.to_account_info(),
+                destination: ctx.accounts.seller.to_account_info(),
+                authority: ctx.accounts.listing_authority.to_account_info(),
+            },
+            signer_seeds,
+        ))?;
+
+        let price = sale.price;
+        let buyer = sale.buyer;
 
         // update statistics
         ctx.accounts.question.total_sales = ctx.accounts.question.total_sales
             .checked_add(price)
             .ok_or(ErrorCode::NumericalOverflow)?;
+        ctx.accounts.question.open_escrows = ctx.accounts.question.open_escrows.saturating_sub(1);
         ctx.accounts.marketplace.total_volume = ctx.accounts.marketplace.total_volume
             .checked_add(price)
             .ok_or(ErrorCode::NumericalOverflow)?;
 
         // update key ownership and encrypted key
+        let key = &mut ctx.accounts.unlock_key;
         let previous_owner = key.owner;
-        key.owner = ctx.accounts.buyer.key();
+        key.owner = buyer;
         key.encrypted_key = new_encrypted_key;
-        key.is_listed = false;
+        key.reencryption_pending = false;
         key.list_price = 0;
         key.last_sold_price = price;
-        key.last_sold_time = Clock::get()?.unix_timestamp;
+        key.last_sold_time = current_time;
 
         emit!(KeySold {
             key_id: key.token_id,
@@ -563,317 +1765,3935 @@ pub mod myqa {
             sold_time: key.last_sold_time,
         });
 
-        // update rate limiting state - only in non-test mode
-        #[cfg(not(feature = "test"))]
-        {
-            let current_time = Clock::get()?.unix_timestamp;
-            ctx.accounts.user_state.last_operation_time = current_time;
-        }
-
         Ok(())
     }
 
-    pub fn toggle_marketplace(ctx: Context<ToggleMarketplace>) -> Result<()> {
-        let marketplace = &mut ctx.accounts.marketplace;
-        marketplace.paused = !marketplace.paused;
+    pub fn reclaim_sale(ctx: Context<ReclaimSale>) -> Result<()> {
+        let sale = &ctx.accounts.pending_sale;
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(current_time > sale.expires_at, ErrorCode::SaleNotExpired);
 
-        emit!(MarketplaceToggled {
-            authority: marketplace.authority,
-            paused: marketplace.paused,
+        // refund the buyer in full and close the payment escrow
+        if sale.payment_type == PaymentType::Token {
+            let sale_escrow = payment_account(&ctx.accounts.sale_escrow)?;
+            let payment_mint = payment_account(&ctx.accounts.payment_mint)?;
+            let signer_seeds: &[&[&[u8]]] = &[&[b"listing_authority", &[ctx.bumps.listing_authority]]];
+            transfer_payment(
+                &ctx.accounts.payment_token_program,
+                sale_escrow,
+                payment_account(&ctx.accounts.buyer_token_account)?,
+                payment_mint,
+                ctx.accounts.listing_authority.to_account_info(),
+                signer_seeds,
+                sale_escrow.amount,
+            )?;
+            close_payment_escrow(
+                &ctx.accounts.payment_token_program,
+                sale_escrow,
+                payment_mint,
+                ctx.accounts.buyer.to_account_info(),
+                ctx.accounts.listing_authority.to_account_info(),
+                signer_seeds,
+            )?;
+        }
+        // SOL payments sit on the pending sale, which closes to the buyer
+
+        // the key NFT never left the listing escrow, so it goes back on sale
+        let key = &mut ctx.accounts.unlock_key;
+        key.is_listed = true;
+
+        emit!(SaleReclaimed {
+            key_id: key.token_id,
+            buyer: sale.buyer,
+            price: sale.price,
+            timestamp: current_time,
         });
 
         Ok(())
     }
 
-    pub fn blacklist_user(
-        ctx: Context<BlacklistUser>,
-        user: Pubkey,
-    ) -> Result<()> {
-        let user_state = &mut ctx.accounts.user_state;
-        user_state.is_blacklisted = true;
+    pub fn make_offer(ctx: Context<MakeOffer>, target: Pubkey, price: u64) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
+        require!(
+            !ctx.accounts.marketplace.paused_operations.buy_key,
+            ErrorCode::OperationPaused
+        );
+        require!(ctx.accounts.question.is_active, ErrorCode::QuestionInactive);
+        // offer escrows hold tokens, so SOL-priced questions take no offers
+        require!(
+            ctx.accounts.question.payment_type == PaymentType::Token,
+            ErrorCode::UnsupportedPaymentType
+        );
+        require!(price > 0, ErrorCode::InvalidPrice);
 
-        emit!(UserBlacklisted {
-            user,
-            authority: ctx.accounts.authority.key(),
-            timestamp: Clock::get()?.unix_timestamp,
+        // an offer targets either one key or, collection-wide, the question itself
+        let unlock_key = match &ctx.accounts.unlock_key {
+            Some(key) => {
+                require!(target == key.key(), ErrorCode::InvalidOfferTarget);
+                require!(key.owner != ctx.accounts.bidder.key(), ErrorCode::CannotBuyOwnKey);
+                Some(key.key())
+            }
+            None => {
+                require!(target == ctx.accounts.question.key(), ErrorCode::InvalidOfferTarget);
+                None
+            }
+        };
+
+        require!(
+            ctx.accounts.bidder_token_account.amount >= price,
+            ErrorCode::InsufficientFunds
+        );
+
+        transfer_payment(
+            &ctx.accounts.payment_token_program,
+            &ctx.accounts.bidder_token_account,
+            &ctx.accounts.offer_escrow,
+            &ctx.accounts.payment_mint,
+            ctx.accounts.bidder.to_account_info(),
+            &[],
+            price,
+        )?;
+
+        let offer = &mut ctx.accounts.offer;
+        offer.bidder = ctx.accounts.bidder.key();
+        offer.question = ctx.accounts.question.key();
+        offer.unlock_key = unlock_key;
+        offer.price = price;
+        offer.created_at = Clock::get()?.unix_timestamp;
+
+        emit!(OfferMade {
+            offer: offer.key(),
+            question_id: ctx.accounts.question.index,
+            unlock_key,
+            bidder: offer.bidder,
+            price,
+            timestamp: offer.created_at,
         });
 
         Ok(())
     }
 
-    pub fn unblacklist_user(
-        ctx: Context<BlacklistUser>,
-        user: Pubkey,
-    ) -> Result<()> {
-        let user_state = &mut ctx.accounts.user_state;
-        user_state.is_blacklisted = false;
+    pub fn cancel_offer(ctx: Context<CancelOffer>) -> Result<()> {
+        let offer = &ctx.accounts.offer;
+        let signer_seeds: &[&[&[u8]]] = &[&[b"listing_authority", &[ctx.bumps.listing_authority]]];
 
-        emit!(UserUnblacklisted {
-            user,
-            authority: ctx.accounts.authority.key(),
+        // refund the escrowed bid and close the escrow
+        transfer_payment(
+            &ctx.accounts.payment_token_program,
+            &ctx.accounts.offer_escrow,
+            &ctx.accounts.bidder_token_account,
+            &ctx.accounts.payment_mint,
+            ctx.accounts.listing_authority.to_account_info(),
+            signer_seeds,
+            ctx.accounts.offer_escrow.amount,
+        )?;
+        close_payment_escrow(
+            &ctx.accounts.payment_token_program,
+            &ctx.accounts.offer_escrow,
+            &ctx.accounts.payment_mint,
+            ctx.accounts.bidder.to_account_info(),
+            ctx.accounts.listing_authority.to_account_info(),
+            signer_seeds,
+        )?;
+
+        emit!(OfferCancelled {
+            offer: offer.key(),
+            bidder: offer.bidder,
+            price: offer.price,
             timestamp: Clock::get()?.unix_timestamp,
         });
 
         Ok(())
     }
 
-    // pause operations
-    pub fn toggle_operation(
-        ctx: Context<ToggleOperation>,
-        operation: OperationType,
+    pub fn accept_offer<'info>(
+        ctx: Context<'_, '_, '_, 'info, AcceptOffer<'info>>,
+        new_encrypted_key: Vec<u8>,
     ) -> Result<()> {
-        let marketplace = &mut ctx.accounts.marketplace;
-        marketplace.paused_operations.toggle(operation);
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
+        require!(
+            !ctx.accounts.marketplace.paused_operations.buy_key,
+            ErrorCode::OperationPaused
+        );
+        require!(ctx.accounts.question.is_active, ErrorCode::QuestionInactive);
+        require!(
+            new_encrypted_key.len() <= MAX_ENCRYPTED_KEY_LENGTH,
+            ErrorCode::InvalidKeyLength
+        );
 
-        emit!(OperationToggled {
-            operation,
-            is_paused: marketplace.paused_operations.is_paused(operation),
-            authority: ctx.accounts.authority.key(),
-        });
+        let offer = &ctx.accounts.offer;
+        let key_address = ctx.accounts.unlock_key.key();
+        if let Some(offer_key) = offer.unlock_key {
+            require!(offer_key == key_address, ErrorCode::InvalidOfferTarget);
+        }
+        require!(
+            ctx.accounts.unlock_key.owner == ctx.accounts.seller.key(),
+            ErrorCode::KeyOwnerOutOfSync
+        );
+        require!(!ctx.accounts.unlock_key.is_listed, ErrorCode::AlreadyListed);
+        require!(
+            ctx.accounts.unlock_key.owner != offer.bidder,
+            ErrorCode::CannotBuyOwnKey
+        );
 
-        Ok(())
-    }
+        // split what the escrow holds, which is net of any transfer fee
+        let price = offer.price;
+        let (platform_fee, creator_royalty, seller_payment) =
+            split_sale_proceeds(
+                ctx.accounts.offer_escrow.amount,
+                &ctx.accounts.marketplace,
+                &ctx.accounts.question,
+            )?;
 
-    pub fn transfer_authority(ctx: Context<TransferAuthority>) -> Result<()> {
-        let timestamp = Clock::get()?.unix_timestamp;
-        
-        let previous_authority = ctx.accounts.marketplace.authority;
-        
-        let marketplace = &mut ctx.accounts.marketplace;
-        marketplace.authority = ctx.accounts.new_authority.key();
-        
-        emit!(AuthorityTransferred {
-            previous_authority,
-            new_authority: marketplace.authority,
-            timestamp,
+        // hand the key NFT to the bidder
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.seller_key_account.to_account_info(),
+                    to: ctx.accounts.bidder_key_account.to_account_info(),
+                    authority: ctx.accounts.seller.to_account_info(),
+                },
+            ),
+            1,
+        )?;
+
+        // release the escrowed bid
+        let signer_seeds: &[&[&[u8]]] = &[&[b"listing_authority", &[ctx.bumps.listing_authority]]];
+        let payouts = [
+            (&ctx.accounts.treasury_token_account, platform_fee),
+            (&ctx.accounts.creator_vault_tokens, creator_royalty),
+            (&ctx.accounts.seller_token_account, seller_payment),
+        ];
+        for (recipient, amount) in payouts {
+            transfer_payment(
+                &ctx.accounts.payment_token_program,
+                &ctx.accounts.offer_escrow,
+                recipient,
+                &ctx.accounts.payment_mint,
+                ctx.accounts.listing_authority.to_account_info(),
+                signer_seeds,
+                amount,
+            )?;
+        }
+
+        close_payment_escrow(
+            &ctx.accounts.payment_token_program,
+            &ctx.accounts.offer_escrow,
+            &ctx.accounts.payment_mint,
+            ctx.accounts.bidder.to_account_info(),
+            ctx.accounts.listing_authority.to_account_info(),
+            signer_seeds,
+        )?;
+        let royalty_earned = net_of_transfer_fee(&ctx.accounts.payment_mint, creator_royalty)?;
+        let kept = distribute_creator_share(
+            &CreatorShare::of_question(&ctx.accounts.question, &ctx.accounts.question_split)?,
+            royalty_earned,
+            SplitPayoutAccounts {
+                creator_vault: ctx.accounts.creator_vault.to_account_info(),
+                creator_vault_tokens: Some(&ctx.accounts.creator_vault_tokens),
+                payment_mint: Some(&ctx.accounts.payment_mint),
+                listing_authority: Some(ctx.accounts.listing_authority.to_account_info()),
+                listing_authority_bump: ctx.bumps.listing_authority,
+                payment_token_program: &ctx.accounts.payment_token_program,
+                payee_accounts: ctx.remaining_accounts,
+            },
+        )?;
+        ctx.accounts.creator_vault.credit(&ctx.accounts.question, kept)?;
+
+        let current_time = Clock::get()?.unix_timestamp;
+
+        // update statistics
+        ctx.accounts.question.total_sales = ctx.accounts.question.total_sales
+            .checked_add(price)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+        ctx.accounts.marketplace.total_volume = ctx.accounts.marketplace.total_volume
+            .checked_add(price)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+
+        let key = &mut ctx.accounts.unlock_key;
+        key.owner = offer.bidder;
+        key.encrypted_key = new_encrypted_key;
+        key.reencryption_pending = false;
+        key.last_sold_price = price;
+        key.last_sold_time = current_time;
+
+        emit!(OfferAccepted {
+            offer: offer.key(),
+            key_id: key.token_id,
+            question_id: ctx.accounts.question.index,
+            seller: ctx.accounts.seller.key(),
+            bidder: offer.bidder,
+            price,
+            timestamp: current_time,
         });
-        
+
+        Ok(())
+    }
+
+    pub fn create_auction(
+        ctx: Context<CreateAuction>,
+        auction_type: AuctionType,
+        start_price: u64,
+        reserve_price: u64,
+        end_price: u64,
+        min_bid_increment: u64,
+        duration: i64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
+        require!(
+            !ctx.accounts.marketplace.paused_operations.list_key,
+            ErrorCode::OperationPaused
+        );
+        require!(ctx.accounts.question.is_active, ErrorCode::QuestionInactive);
+        // bids are escrowed in the payment token
+        require!(
+            ctx.accounts.question.payment_type == PaymentType::Token,
+            ErrorCode::UnsupportedPaymentType
+        );
+        require!(
+            duration > 0 && duration <= MAX_AUCTION_DURATION,
+            ErrorCode::InvalidAuctionParameters
+        );
+        match auction_type {
+            AuctionType::English => require!(
+                start_price > 0 && min_bid_increment > 0,
+                ErrorCode::InvalidAuctionParameters
+            ),
+            // a Dutch bid is accepted at the current price, so the reserve can't exceed the floor
+            AuctionType::Dutch => require!(
+                start_price > end_price && end_price > 0 && reserve_price <= end_price,
+                ErrorCode::InvalidAuctionParameters
+            ),
+        }
+
+        let key = &ctx.accounts.unlock_key;
+        require!(key.owner == ctx.accounts.seller.key(), ErrorCode::KeyOwnerOutOfSync);
+        require!(!key.is_listed, ErrorCode::AlreadyListed);
+
+        // custody the key NFT for the length of the auction
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.seller_key_account.to_account_info(),
+                    to: ctx.accounts.listing_escrow.to_account_info(),
+                    authority: ctx.accounts.seller.to_account_info(),
+                },
+            ),
+            1,
+        )?;
+
+        let question = &mut ctx.accounts.question;
+        question.open_escrows = question.open_escrows
+            .checked_add(1)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+
+        let current_time = Clock::get()?.unix_timestamp;
+        let auction = &mut ctx.accounts.auction;
+        auction.seller = ctx.accounts.seller.key();
+        auction.unlock_key = key.key();
+        auction.question = ctx.accounts.question.key();
+        auction.auction_type = auction_type;
+        auction.start_price = start_price;
+        auction.reserve_price = reserve_price;
+        auction.end_price = if auction_type == AuctionType::Dutch { end_price } else { 0 };
+        auction.min_bid_increment = min_bid_increment;
+        auction.start_time = current_time;
+        auction.end_time = current_time
+            .checked_add(duration)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+        auction.highest_bidder = Pubkey::default();
+        auction.highest_bid = 0;
+
+        emit!(AuctionCreated {
+            auction: auction.key(),
+            key_id: key.token_id,
+            seller: auction.seller,
+            auction_type,
+            start_price,
+            reserve_price,
+            end_price: auction.end_price,
+            end_time: auction.end_time,
+        });
+
+        Ok(())
+    }
+
+    pub fn place_bid(ctx: Context<PlaceBid>, amount: u64) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
+        require!(
+            !ctx.accounts.marketplace.paused_operations.buy_key,
+            ErrorCode::OperationPaused
+        );
+
+        let current_time = Clock::get()?.unix_timestamp;
+        let auction = &ctx.accounts.auction;
+        require!(current_time < auction.end_time, ErrorCode::AuctionEnded);
+        require!(
+            auction.seller != ctx.accounts.bidder.key(),
+            ErrorCode::CannotBuyOwnKey
+        );
+
+        // Dutch auctions sell to the first bid at or above the current price
+        let charge = match auction.auction_type {
+            AuctionType::English => {
+                let minimum = if auction.highest_bid == 0 {
+                    auction.start_price
+                } else {
+                    auction.highest_bid
+                        .checked_add(auction.min_bid_increment)
+                        .ok_or(ErrorCode::NumericalOverflow)?
+                };
+                require!(amount >= minimum, ErrorCode::BidTooLow);
+                amount
+            }
+            AuctionType::Dutch => {
+                let price = dutch_auction_price(auction, current_time)?;
+                require!(amount >= price, ErrorCode::BidTooLow);
+                price
+            }
+        };
+
+        require!(
+            ctx.accounts.bidder_token_account.amount >= charge,
+            ErrorCode::InsufficientFunds
+        );
+
+        // the escrow balance as loaded is exactly the outbid bidder's deposit,
+        // net of any transfer fee it paid
+        let outbid_deposit = ctx.accounts.auction_escrow.amount;
+
+        transfer_payment(
+            &ctx.accounts.payment_token_program,
+            &ctx.accounts.bidder_token_account,
+            &ctx.accounts.auction_escrow,
+            &ctx.accounts.payment_mint,
+            ctx.accounts.bidder.to_account_info(),
+            &[],
+            charge,
+        )?;
+
+        // refund the bid that was just outbid
+        if auction.highest_bid > 0 {
+            let previous = ctx.accounts.previous_bidder_token_account
+                .as_ref()
+                .ok_or(ErrorCode::MissingPreviousBidder)?;
+            require!(
+                previous.owner == auction.highest_bidder,
+                ErrorCode::MissingPreviousBidder
+            );
+
+            let signer_seeds: &[&[&[u8]]] = &[&[b"listing_authority", &[ctx.bumps.listing_authority]]];
+            transfer_payment(
+                &ctx.accounts.payment_token_program,
+                &ctx.accounts.auction_escrow,
+                previous,
+                &ctx.accounts.payment_mint,
+                ctx.accounts.listing_authority.to_account_info(),
+                signer_seeds,
+                outbid_deposit,
+            )?;
+        }
+
+        let auction = &mut ctx.accounts.auction;
+        auction.highest_bidder = ctx.accounts.bidder.key();
+        auction.highest_bid = charge;
+        match auction.auction_type {
+            AuctionType::English => {
+                if auction.end_time - current_time < AUCTION_EXTENSION_WINDOW {
+                    auction.end_time = current_time + AUCTION_EXTENSION_WINDOW;
+                }
+            }
+            AuctionType::Dutch => auction.end_time = current_time,
+        }
+
+        emit!(BidPlaced {
+            auction: auction.key(),
+            bidder: auction.highest_bidder,
+            amount: charge,
+            end_time: auction.end_time,
+        });
+
+        Ok(())
+    }
+
+    pub fn settle_auction<'info>(
+        ctx: Context<'_, '_, '_, 'info, SettleAuction<'info>>,
+        new_encrypted_key: Vec<u8>,
+    ) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
+        require!(
+            new_encrypted_key.len() <= MAX_ENCRYPTED_KEY_LENGTH,
+            ErrorCode::InvalidKeyLength
+        );
+
+        let settler = ctx.accounts.settler.key();
+        require!(
+            settler == ctx.accounts.seller.key()
+                || settler == ctx.accounts.marketplace.key_custodian,
+            ErrorCode::NotKeyCustodian
+        );
+
+        let current_time = Clock::get()?.unix_timestamp;
+        let auction = &ctx.accounts.auction;
+        require!(current_time >= auction.end_time, ErrorCode::AuctionNotEnded);
+        require!(auction.highest_bid > 0, ErrorCode::NoWinningBid);
+        require!(auction.highest_bid >= auction.reserve_price, ErrorCode::ReserveNotMet);
+        require!(
+            current_time <= auction.end_time.saturating_add(SALE_FULFILLMENT_WINDOW),
+            ErrorCode::SaleExpired
+        );
+
+        // split what the escrow holds, which is net of any transfer fee
+        let price = auction.highest_bid;
+        let winner = auction.highest_bidder;
+        let (platform_fee, creator_royalty, seller_payment) =
+            split_sale_proceeds(
+                ctx.accounts.auction_escrow.amount,
+                &ctx.accounts.marketplace,
+                &ctx.accounts.question,
+            )?;
+
+        // pay out the winning bid
+        let signer_seeds: &[&[&[u8]]] = &[&[b"listing_authority", &[ctx.bumps.listing_authority]]];
+        let payouts = [
+            (&ctx.accounts.treasury_token_account, platform_fee),
+            (&ctx.accounts.creator_vault_tokens, creator_royalty),
+            (&ctx.accounts.seller_token_account, seller_payment),
+        ];
+        for (recipient, amount) in payouts {
+            transfer_payment(
+                &ctx.accounts.payment_token_program,
+                &ctx.accounts.auction_escrow,
+                recipient,
+                &ctx.accounts.payment_mint,
+                ctx.accounts.listing_authority.to_account_info(),
+                signer_seeds,
+                amount,
+            )?;
+        }
+
+        close_payment_escrow(
+            &ctx.accounts.payment_token_program,
+            &ctx.accounts.auction_escrow,
+            &ctx.accounts.payment_mint,
+            ctx.accounts.seller.to_account_info(),
+            ctx.accounts.listing_authority.to_account_info(),
+            signer_seeds,
+        )?;
+        let royalty_earned = net_of_transfer_fee(&ctx.accounts.payment_mint, creator_royalty)?;
+        let kept = distribute_creator_share(
+            &CreatorShare::of_question(&ctx.accounts.question, &ctx.accounts.question_split)?,
+            royalty_earned,
+            SplitPayoutAccounts {
+                creator_vault: ctx.accounts.creator_vault.to_account_info(),
+                creator_vault_tokens: Some(&ctx.accounts.creator_vault_tokens),
+                payment_mint: Some(&ctx.accounts.payment_mint),
+                listing_authority: Some(ctx.accounts.listing_authority.to_account_info()),
+                listing_authority_bump: ctx.bumps.listing_authority,
+                payment_token_program: &ctx.accounts.payment_token_program,
+                payee_accounts: ctx.remaining_accounts,
+            },
+        )?;
+        ctx.accounts.creator_vault.credit(&ctx.accounts.question, kept)?;
+
+        // hand the key NFT to the winner
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.listing_escrow.to_account_info(),
+                    to: ctx.accounts.winner_key_account.to_account_info(),
+                    authority: ctx.accounts.listing_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            1,
+        )?;
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.listing_escrow.to_account_info(),
+                destination: ctx.accounts.seller.to_account_info(),
+                authority: ctx.accounts.listing_authority.to_account_info(),
+            },
+            signer_seeds,
+        ))?;
+
+        // update statistics
+        ctx.accounts.question.total_sales = ctx.accounts.question.total_sales
+            .checked_add(price)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+        ctx.accounts.question.open_escrows = ctx.accounts.question.open_escrows.saturating_sub(1);
+        ctx.accounts.marketplace.total_volume = ctx.accounts.marketplace.total_volume
+            .checked_add(price)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+
+        let key = &mut ctx.accounts.unlock_key;
+        key.owner = winner;
+        key.encrypted_key = new_encrypted_key;
+        key.reencryption_pending = false;
+        key.last_sold_price = price;
+        key.last_sold_time = current_time;
+
+        emit!(AuctionSettled {
+            auction: ctx.accounts.auction.key(),
+            key_id: key.token_id,
+            seller: ctx.accounts.seller.key(),
+            winner,
+            price,
+            timestamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_auction(ctx: Context<CancelAuction>) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let auction = &ctx.accounts.auction;
+
+        // the seller may withdraw an auction nobody has bid on; a bid below the
+        // reserve can be unwound by anyone once bidding ends, and a winning bid the
+        // seller never settled once the fulfillment window passes
+        let refunded_bidder = if auction.highest_bid == 0 {
+            require!(
+                ctx.accounts.caller.key() == auction.seller,
+                ErrorCode::NotKeyOwner
+            );
+            None
+        } else if auction.highest_bid < auction.reserve_price {
+            require!(current_time >= auction.end_time, ErrorCode::AuctionNotEnded);
+            Some(auction.highest_bidder)
+        } else {
+            require!(
+                current_time > auction.end_time.saturating_add(SALE_FULFILLMENT_WINDOW),
+                ErrorCode::SaleNotExpired
+            );
+            Some(auction.highest_bidder)
+        };
+
+        let signer_seeds: &[&[&[u8]]] = &[&[b"listing_authority", &[ctx.bumps.listing_authority]]];
+        if refunded_bidder.is_some() {
+            let bidder_token_account = ctx.accounts.bidder_token_account
+                .as_ref()
+                .ok_or(ErrorCode::MissingPreviousBidder)?;
+            require!(
+                bidder_token_account.owner == auction.highest_bidder,
+                ErrorCode::MissingPreviousBidder
+            );
+            transfer_payment(
+                &ctx.accounts.payment_token_program,
+                &ctx.accounts.auction_escrow,
+                bidder_token_account,
+                &ctx.accounts.payment_mint,
+                ctx.accounts.listing_authority.to_account_info(),
+                signer_seeds,
+                ctx.accounts.auction_escrow.amount,
+            )?;
+        }
+        close_payment_escrow(
+            &ctx.accounts.payment_token_program,
+            &ctx.accounts.auction_escrow,
+            &ctx.accounts.payment_mint,
+            ctx.accounts.seller.to_account_info(),
+            ctx.accounts.listing_authority.to_account_info(),
+            signer_seeds,
+        )?;
+
+        // return the key NFT to the seller
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.listing_escrow.to_account_info(),
+                    to: ctx.accounts.seller_key_account.to_account_info(),
+                    authority: ctx.accounts.listing_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            1,
+        )?;
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.listing_escrow.to_account_info(),
+                destination: ctx.accounts.seller.to_account_info(),
+                authority: ctx.accounts.listing_authority.to_account_info(),
+            },
+            signer_seeds,
+        ))?;
+
+        let question = &mut ctx.accounts.question;
+        question.open_escrows = question.open_escrows.saturating_sub(1);
+
+        emit!(AuctionCancelled {
+            auction: auction.key(),
+            key_id: ctx.accounts.unlock_key.token_id,
+            refunded_bidder,
+            timestamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    // Read-only; simulate to quote the price of the next key
+    pub fn get_next_unlock_price(ctx: Context<GetNextUnlockPrice>) -> Result<u64> {
+        ctx.accounts.question.next_unlock_price(Clock::get()?.unix_timestamp)
+    }
+
+    pub fn update_question_terms(
+        ctx: Context<UpdateQuestionTerms>,
+        new_unlock_price: u64,
+        new_max_keys: u64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
+        require!(
+            !ctx.accounts.marketplace.paused_operations.update_question,
+            ErrorCode::OperationPaused
+        );
+
+        let user_state = &mut ctx.accounts.user_state;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require!(
+            !user_state.is_blacklisted,
+            ErrorCode::UserBlacklisted
+        );
+
+        #[cfg(not(feature = "test"))]
+        require!(
+            current_time - user_state.last_operation_time >= OPERATION_COOLDOWN,
+            ErrorCode::RateLimitExceeded
+        );
+
+        let question = &mut ctx.accounts.question;
+
+        require!(new_unlock_price > 0, ErrorCode::InvalidPrice);
+        require!(
+            new_max_keys > 0 && new_max_keys >= question.current_keys,
+            ErrorCode::InvalidKeyCount
+        );
+        // compressed keys can't outgrow the tree they are appended to
+        if question.key_mode == KeyMode::Compressed {
+            let key_tree = ctx.accounts.key_tree
+                .as_ref()
+                .ok_or(ErrorCode::InvalidTreeDepth)?;
+            require!(
+                new_max_keys <= 1u64 << key_tree.max_depth,
+                ErrorCode::InvalidTreeDepth
+            );
+        }
+        question.pricing_curve.validate(new_unlock_price, new_max_keys)?;
+        if let Some(schedule) = &question.launch_schedule {
+            schedule.validate(new_unlock_price)?;
+        }
+
+        let old_unlock_price = question.unlock_price;
+        let old_max_keys = question.max_keys;
+        question.unlock_price = new_unlock_price;
+        question.max_keys = new_max_keys;
+
+        emit!(QuestionTermsUpdated {
+            question_id: question.index,
+            old_unlock_price,
+            new_unlock_price,
+            old_max_keys,
+            new_max_keys,
+            timestamp: current_time,
+        });
+
+        #[cfg(not(feature = "test"))]
+        {
+            user_state.last_operation_time = current_time;
+        }
+
         Ok(())
     }
+
+    pub fn update_question_content(
+        ctx: Context<UpdateQuestionContent>,
+        new_content_cid: String,
+        new_content_hash: [u8; 32],
+    ) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
+        require!(
+            !ctx.accounts.marketplace.paused_operations.update_question,
+            ErrorCode::OperationPaused
+        );
+
+        let user_state = &mut ctx.accounts.user_state;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require!(
+            !user_state.is_blacklisted,
+            ErrorCode::UserBlacklisted
+        );
+
+        #[cfg(not(feature = "test"))]
+        require!(
+            current_time - user_state.last_operation_time >= OPERATION_COOLDOWN,
+            ErrorCode::RateLimitExceeded
+        );
+
+        validate_content_cid(&new_content_cid)?;
+
+        let question = &mut ctx.accounts.question;
+
+        // archive the current content before replacing it
+        let revision = &mut ctx.accounts.revision;
+        revision.question = question.key();
+        revision.version = question.content_version;
+        revision.content_cid = question.content_cid.clone();
+        revision.content_hash = question.content_hash;
+        revision.replaced_at = current_time;
+
+        question.content_version = question.content_version
+            .checked_add(1)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+        question.content_cid = new_content_cid;
+        question.content_hash = new_content_hash;
+        question.validation_timestamp = current_time;
+
+        emit!(QuestionContentUpdated {
+            question_id: question.index,
+            content_version: question.content_version,
+            content_cid: question.content_cid.clone(),
+            content_hash: question.content_hash,
+            timestamp: current_time,
+        });
+
+        #[cfg(not(feature = "test"))]
+        {
+            user_state.last_operation_time = current_time;
+        }
+
+        Ok(())
+    }
+
+    pub fn set_allowlist_root(
+        ctx: Context<SetAllowlistRoot>,
+        allowlist_root: Option<[u8; 32]>,
+    ) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
+
+        let question = &mut ctx.accounts.question;
+        question.allowlist_root = allowlist_root;
+
+        emit!(AllowlistUpdated {
+            question_id: question.index,
+            allowlist_root,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_max_keys_per_wallet(
+        ctx: Context<SetMaxKeysPerWallet>,
+        max_keys_per_wallet: Option<u64>,
+    ) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
+        require!(max_keys_per_wallet != Some(0), ErrorCode::InvalidKeyCount);
+
+        let question = &mut ctx.accounts.question;
+        question.max_keys_per_wallet = max_keys_per_wallet;
+
+        emit!(PurchaseLimitUpdated {
+            question_id: question.index,
+            max_keys_per_wallet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Applies to keys minted from now on; existing escrows keep their deadline
+    pub fn set_refund_window(
+        ctx: Context<SetRefundWindow>,
+        refund_window: Option<i64>,
+    ) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
+        if let Some(window) = refund_window {
+            require!(
+                window > 0 && window <= MAX_REFUND_WINDOW,
+                ErrorCode::InvalidRefundWindow
+            );
+        }
+
+        let question = &mut ctx.accounts.question;
+        question.refund_window = refund_window;
+
+        emit!(RefundWindowUpdated {
+            question_id: question.index,
+            refund_window,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Mints the question's collection NFT, held and updated by the mint authority PDA
+    pub fn create_question_collection(
+        ctx: Context<CreateQuestionCollection>,
+        metadata_uri: String,
+    ) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
+        require!(metadata_uri.len() <= MAX_URI_LENGTH, ErrorCode::URITooLong);
+        require!(
+            metadata_uri.is_ascii() && metadata_uri.len() >= MIN_METADATA_LENGTH,
+            ErrorCode::InvalidMetadataFormat
+        );
+        require!(
+            ctx.accounts.question.collection_mint.is_none(),
+            ErrorCode::CollectionAlreadyExists
+        );
+
+        let signer_seeds: &[&[&[u8]]] = &[&[b"mint_authority", &[ctx.bumps.mint_authority]]];
+
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.collection_mint.to_account_info(),
+                    to: ctx.accounts.collection_token_account.to_account_info(),
+                    authority: ctx.accounts.mint_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            1,
+        )?;
+
+        let question = &ctx.accounts.question;
+        create_metadata_accounts_v3(
+            CpiContext::new_with_signer(
+                ctx.accounts.metadata_program.to_account_info(),
+                CreateMetadataAccountsV3 {
+                    metadata: ctx.accounts.collection_metadata.to_account_info(),
+                    mint: ctx.accounts.collection_mint.to_account_info(),
+                    mint_authority: ctx.accounts.mint_authority.to_account_info(),
+                    update_authority: ctx.accounts.mint_authority.to_account_info(),
+                    payer: ctx.accounts.creator.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    rent: ctx.accounts.rent.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            DataV2 {
+                name: format!("QA Keys - Q{}", question.index),
+                symbol: "QAK".to_string(),
+                uri: metadata_uri,
+                seller_fee_basis_points: question.royalty_bps,
+                creators: Some(vec![
+                    Creator {
+                        address: ctx.accounts.mint_authority.key(),
+                        verified: true,
+                        share: 0,
+                    },
+                    Creator {
+                        address: question.creator,
+                        verified: false,
+                        share: 100,
+                    },
+                ]),
+                collection: None,
+                uses: None,
+            },
+            true, // is_mutable
+            true, // update_authority_is_signer
+            Some(CollectionDetails::V1 { size: 0 }),
+        )?;
+
+        create_master_edition_v3(
+            CpiContext::new_with_signer(
+                ctx.accounts.metadata_program.to_account_info(),
+                CreateMasterEditionV3 {
+                    edition: ctx.accounts.collection_master_edition.to_account_info(),
+                    mint: ctx.accounts.collection_mint.to_account_info(),
+                    update_authority: ctx.accounts.mint_authority.to_account_info(),
+                    mint_authority: ctx.accounts.mint_authority.to_account_info(),
+                    payer: ctx.accounts.creator.to_account_info(),
+                    metadata: ctx.accounts.collection_metadata.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    rent: ctx.accounts.rent.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            Some(0),
+        )?;
+
+        let question = &mut ctx.accounts.question;
+        question.collection_mint = Some(ctx.accounts.collection_mint.key());
+
+        emit!(QuestionCollectionCreated {
+            question_id: question.index,
+            collection_mint: ctx.accounts.collection_mint.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Switches a question with no keys sold to compressed keys held in a KeyTree
+    pub fn create_key_tree(ctx: Context<CreateKeyTree>, max_depth: u8) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
+        let question = &mut ctx.accounts.question;
+        require!(question.current_keys == 0, ErrorCode::KeysAlreadyMinted);
+        require!(
+            max_depth > 0
+                && max_depth <= MAX_KEY_TREE_DEPTH
+                && question.max_keys <= 1u64 << max_depth,
+            ErrorCode::InvalidTreeDepth
+        );
+
+        ctx.accounts.key_tree.initialize(question.key(), max_depth);
+        question.key_mode = KeyMode::Compressed;
+
+        emit!(KeyTreeCreated {
+            question_id: question.index,
+            key_tree: ctx.accounts.key_tree.key(),
+            max_depth,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Charges the unlock price and appends the buyer's key to the question's tree.
+    // The encrypted key only lives in the emitted event; the leaf commits to its hash.
+    // Split payee vaults come in as remaining accounts
+    pub fn mint_compressed_key<'info>(
+        ctx: Context<'_, '_, '_, 'info, MintCompressedKey<'info>>,
+        encrypted_key: Vec<u8>,
+        max_price: u64,
+        expected_token_id: u64,
+    ) -> Result<()> {
+        let marketplace = &ctx.accounts.marketplace;
+        require!(!marketplace.paused, ErrorCode::MarketplacePaused);
+        require!(
+            !marketplace.paused_operations.mint_key,
+            ErrorCode::OperationPaused
+        );
+
+        let question = &ctx.accounts.question;
+        require!(question.is_active, ErrorCode::QuestionInactive);
+        // compressed keys cannot be burned for a refund
+        require!(question.refund_window.is_none(), ErrorCode::RefundEscrowMismatch);
+        require!(
+            question.current_keys < question.max_keys,
+            ErrorCode::NoKeysAvailable
+        );
+        require!(
+            encrypted_key.len() <= MAX_ENCRYPTED_KEY_LENGTH,
+            ErrorCode::InvalidKeyLength
+        );
+        require!(
+            question.current_keys == expected_token_id,
+            ErrorCode::UnexpectedTokenId
+        );
+
+        let mint_time = Clock::get()?.unix_timestamp;
+        let unlock_price = question.next_unlock_price(mint_time)?;
+        require!(unlock_price <= max_price, ErrorCode::PriceExceedsMaximum);
+        ctx.accounts.purchase_record.record(question, ctx.accounts.buyer.key(), 1)?;
+
+        let creator_payment = collect_unlock_payment(
+            question.payment_type,
+            unlock_price,
+            marketplace.platform_fee_bps,
+            &ctx.accounts.buyer,
+            UnlockPaymentAccounts {
+                buyer_token_account: &ctx.accounts.buyer_token_account,
+                treasury_token_account: &ctx.accounts.treasury_token_account,
+                creator_token_account: &ctx.accounts.creator_vault_tokens,
+                payment_mint: &ctx.accounts.payment_mint,
+                payment_mint_config: &ctx.accounts.payment_mint_config,
+                treasury: &ctx.accounts.treasury,
+                creator: Some(ctx.accounts.creator_vault.to_account_info()),
+                payment_token_program: &ctx.accounts.payment_token_program,
+                system_program: &ctx.accounts.system_program,
+            },
+        )?;
+        let kept = distribute_creator_share(
+            &CreatorShare::of_question(question, &ctx.accounts.question_split)?,
+            creator_payment,
+            SplitPayoutAccounts {
+                creator_vault: ctx.accounts.creator_vault.to_account_info(),
+                creator_vault_tokens: ctx.accounts.creator_vault_tokens.as_ref(),
+                payment_mint: ctx.accounts.payment_mint.as_ref(),
+                listing_authority: ctx.accounts.listing_authority
+                    .as_ref()
+                    .map(|authority| authority.to_account_info()),
+                listing_authority_bump: ctx.bumps.listing_authority,
+                payment_token_program: &ctx.accounts.payment_token_program,
+                payee_accounts: ctx.remaining_accounts,
+            },
+        )?;
+        ctx.accounts.creator_vault.credit(question, kept)?;
+
+        let question_key = question.key();
+        let owner = ctx.accounts.buyer.key();
+        let token_id = question.current_keys;
+        let leaf = compressed_key_leaf(
+            &question_key,
+            token_id,
+            &owner,
+            &keccak::hash(&encrypted_key).0,
+        );
+        let key_tree = &mut ctx.accounts.key_tree;
+        let leaf_index = key_tree.append(leaf)?;
+        // leaves are appended in mint order, so a key's leaf index is its token id
+        require!(leaf_index as u64 == token_id, ErrorCode::InvalidKeyProof);
+
+        let question = &mut ctx.accounts.question;
+        question.current_keys = question.current_keys
+            .checked_add(1)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+        question.total_sales = question.total_sales
+            .checked_add(unlock_price)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+        let marketplace = &mut ctx.accounts.marketplace;
+        marketplace.total_volume = marketplace.total_volume
+            .checked_add(unlock_price)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+
+        emit!(CompressedKeyMinted {
+            key_id: token_id,
+            question_id: question.index,
+            owner,
+            leaf,
+            root: key_tree.root,
+            encrypted_key,
+            price: unlock_price,
+            mint_time,
+        });
+
+        #[cfg(not(feature = "test"))]
+        {
+            ctx.accounts.user_state.last_operation_time = mint_time;
+        }
+
+        Ok(())
+    }
+
+    // The owner proves their leaf and re-encrypts the key for the recipient
+    pub fn transfer_compressed_key(
+        ctx: Context<TransferCompressedKey>,
+        root: [u8; 32],
+        token_id: u64,
+        encrypted_key_hash: [u8; 32],
+        new_encrypted_key: Vec<u8>,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
+        require!(
+            new_encrypted_key.len() <= MAX_ENCRYPTED_KEY_LENGTH,
+            ErrorCode::InvalidKeyLength
+        );
+        require!(token_id < ctx.accounts.question.current_keys, ErrorCode::InvalidKeyProof);
+
+        let question_key = ctx.accounts.question.key();
+        let owner = ctx.accounts.owner.key();
+        let new_owner = ctx.accounts.new_owner.key();
+        let old_leaf = compressed_key_leaf(&question_key, token_id, &owner, &encrypted_key_hash);
+        let new_leaf = compressed_key_leaf(
+            &question_key,
+            token_id,
+            &new_owner,
+            &keccak::hash(&new_encrypted_key).0,
+        );
+        let key_tree = &mut ctx.accounts.key_tree;
+        key_tree.replace_leaf(root, old_leaf, new_leaf, &proof, token_id as u32)?;
+
+        emit!(CompressedKeyTransferred {
+            key_id: token_id,
+            question_id: ctx.accounts.question.index,
+            from: owner,
+            to: new_owner,
+            leaf: new_leaf,
+            root: key_tree.root,
+            encrypted_key: new_encrypted_key,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Fails unless `owner` holds the compressed key, for gating off-chain content
+    pub fn verify_compressed_key(
+        ctx: Context<VerifyCompressedKey>,
+        root: [u8; 32],
+        token_id: u64,
+        owner: Pubkey,
+        encrypted_key_hash: [u8; 32],
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        require!(token_id < ctx.accounts.question.current_keys, ErrorCode::InvalidKeyProof);
+        let leaf = compressed_key_leaf(
+            &ctx.accounts.question.key(),
+            token_id,
+            &owner,
+            &encrypted_key_hash,
+        );
+        ctx.accounts.key_tree.verify_leaf(root, leaf, &proof, token_id as u32)
+    }
+
+    pub fn set_question_active(
+        ctx: Context<SetQuestionActive>,
+        is_active: bool,
+    ) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
+
+        let question = &mut ctx.accounts.question;
+        require!(!question.is_taken_down, ErrorCode::QuestionTakenDown);
+
+        question.is_active = is_active;
+
+        emit!(QuestionStatusChanged {
+            question_id: question.index,
+            is_active,
+            changed_by: ctx.accounts.creator.key(),
+            reason: QuestionStatusReason::CreatorRequest,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // moderation takedown, the creator cannot reactivate until reinstated
+    pub fn moderate_question(
+        ctx: Context<ModerateQuestion>,
+        take_down: bool,
+        reason: QuestionStatusReason,
+    ) -> Result<()> {
+        let question = &mut ctx.accounts.question;
+        question.is_taken_down = take_down;
+        question.is_active = !take_down;
+
+        emit!(QuestionStatusChanged {
+            question_id: question.index,
+            is_active: question.is_active,
+            changed_by: ctx.accounts.authority.key(),
+            reason,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn close_question<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CloseQuestion<'info>>,
+    ) -> Result<()> {
+        // a question with sold keys closes only once retired, and never while one of
+        // its keys sits in listing escrow: cancelling or settling a listing, pending
+        // sale or auction needs the question. Holders keep their keys, and refund
+        // escrows and burns don't touch the question
+        let question = &ctx.accounts.question;
+        let retired = question.is_taken_down || !question.is_active;
+        require!(question.current_keys == 0 || retired, ErrorCode::QuestionHasKeys);
+        require!(question.open_escrows == 0, ErrorCode::QuestionHasOpenEscrows);
+
+        // the split and key tree close alongside the question
+        require!(
+            question.has_split == ctx.accounts.question_split.is_some()
+                && (question.key_mode == KeyMode::Compressed) == ctx.accounts.key_tree.is_some(),
+            ErrorCode::QuestionAccountsOpen
+        );
+
+        // content revisions follow as remaining accounts; any left out can't be
+        // reclaimed once the question is gone
+        let creator = ctx.accounts.creator.to_account_info();
+        for revision in ctx.remaining_accounts {
+            let revision = Account::<QuestionRevision>::try_from(revision)?;
+            require!(revision.question == question.key(), ErrorCode::InvalidRevision);
+            revision.close(creator.clone())?;
+        }
+
+        let user_state = &mut ctx.accounts.user_state;
+        user_state.questions_created = user_state.questions_created.saturating_sub(1);
+
+        emit!(QuestionClosed {
+            question_id: question.index,
+            creator: question.creator,
+            keys_sold: question.current_keys,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // The question creator or a split payee verifies their creator entry on a key
+    // NFT. Buyers send the mint transactions, so creators can't sign there, and
+    // Metaplex rejects signers that aren't among the key's creators.
+    pub fn verify_key_creator(ctx: Context<VerifyKeyCreator>) -> Result<()> {
+        sign_metadata(CpiContext::new(
+            ctx.accounts.metadata_program.to_account_info(),
+            SignMetadata {
+                creator: ctx.accounts.creator.to_account_info(),
+                metadata: ctx.accounts.metadata.to_account_info(),
+            },
+        ))?;
+
+        emit!(KeyCreatorVerified {
+            key_id: ctx.accounts.unlock_key.token_id,
+            question: ctx.accounts.unlock_key.question,
+            creator: ctx.accounts.creator.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn burn_unlock_key(ctx: Context<BurnUnlockKey>) -> Result<()> {
+        let key = &ctx.accounts.unlock_key;
+        require!(!key.is_listed, ErrorCode::AlreadyListed);
+
+        // burn the NFT, closing its token, metadata and edition accounts; keys
+        // verified into a collection also shrink the collection size
+        let collection_metadata = ctx.accounts.collection_metadata
+            .as_ref()
+            .map(|account| account.to_account_info());
+        burn_nft(
+            CpiContext::new(
+                ctx.accounts.metadata_program.to_account_info(),
+                BurnNft {
+                    metadata: ctx.accounts.metadata.to_account_info(),
+                    owner: ctx.accounts.holder.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    token: ctx.accounts.holder_key_account.to_account_info(),
+                    edition: ctx.accounts.master_edition.to_account_info(),
+                    spl_token: ctx.accounts.token_program.to_account_info(),
+                },
+            )
+            .with_remaining_accounts(collection_metadata.iter().cloned().collect()),
+            collection_metadata.as_ref().map(|account| account.key()),
+        )?;
+
+        emit!(UnlockKeyBurned {
+            key_id: key.token_id,
+            question: key.question,
+            owner: ctx.accounts.holder.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // The original buyer burns their key within the refund window and gets the
+    // escrowed creator share back. This is a partial refund: the platform fee was
+    // paid to the treasury at mint and is not returned.
+    pub fn request_refund(ctx: Context<RequestRefund>) -> Result<()> {
+        let key = &ctx.accounts.unlock_key;
+        let escrow = &ctx.accounts.refund_escrow;
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(!key.is_listed, ErrorCode::AlreadyListed);
+        require!(current_time <= escrow.refundable_until, ErrorCode::RefundWindowClosed);
+
+        let collection_metadata = ctx.accounts.collection_metadata
+            .as_ref()
+            .map(|account| account.to_account_info());
+        burn_nft(
+            CpiContext::new(
+                ctx.accounts.metadata_program.to_account_info(),
+                BurnNft {
+                    metadata: ctx.accounts.metadata.to_account_info(),
+                    owner: ctx.accounts.buyer.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    token: ctx.accounts.buyer_key_account.to_account_info(),
+                    edition: ctx.accounts.master_edition.to_account_info(),
+                    spl_token: ctx.accounts.token_program.to_account_info(),
+                },
+            )
+            .with_remaining_accounts(collection_metadata.iter().cloned().collect()),
+            collection_metadata.as_ref().map(|account| account.key()),
+        )?;
+
+        let refunded = if escrow.payment_type == PaymentType::Token {
+            let escrow_tokens = payment_account(&ctx.accounts.refund_escrow_tokens)?;
+            let payment_mint = payment_account(&ctx.accounts.payment_mint)?;
+            let listing_authority = payment_account(&ctx.accounts.listing_authority)?;
+            let signer_seeds: &[&[&[u8]]] = &[&[b"listing_authority", &[ctx.bumps.listing_authority]]];
+            let amount = escrow_tokens.amount;
+            transfer_payment(
+                &ctx.accounts.payment_token_program,
+                escrow_tokens,
+                payment_account(&ctx.accounts.buyer_token_account)?,
+                payment_mint,
+                listing_authority.to_account_info(),
+                signer_seeds,
+                amount,
+            )?;
+            close_payment_escrow(
+                &ctx.accounts.payment_token_program,
+                escrow_tokens,
+                payment_mint,
+                ctx.accounts.buyer.to_account_info(),
+                listing_authority.to_account_info(),
+                signer_seeds,
+            )?;
+            amount
+        } else {
+            // SOL refunds sit on the escrow, which closes to the buyer
+            escrow.amount
+        };
+
+        emit!(KeyRefunded {
+            key_id: key.token_id,
+            question: key.question,
+            buyer: escrow.buyer,
+            creator_share_refunded: refunded,
+            platform_fee_retained: escrow.platform_fee,
+            timestamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    // Permissionless crank: once the refund window has passed, pays the escrowed
+    // creator share into the creator's vault and returns the escrow rent to the buyer
+    pub fn release_escrow<'info>(ctx: Context<'_, '_, '_, 'info, ReleaseEscrow<'info>>) -> Result<()> {
+        let escrow = &ctx.accounts.refund_escrow;
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(current_time > escrow.refundable_until, ErrorCode::RefundWindowOpen);
+
+        let released = if escrow.payment_type == PaymentType::Token {
+            let escrow_tokens = payment_account(&ctx.accounts.refund_escrow_tokens)?;
+            let payment_mint = payment_account(&ctx.accounts.payment_mint)?;
+            let listing_authority = payment_account(&ctx.accounts.listing_authority)?;
+            let signer_seeds: &[&[&[u8]]] = &[&[b"listing_authority", &[ctx.bumps.listing_authority]]];
+            let amount = escrow_tokens.amount;
+            transfer_payment(
+                &ctx.accounts.payment_token_program,
+                escrow_tokens,
+                payment_account(&ctx.accounts.creator_vault_tokens)?,
+                payment_mint,
+                listing_authority.to_account_info(),
+                signer_seeds,
+                amount,
+            )?;
+            close_payment_escrow(
+                &ctx.accounts.payment_token_program,
+                escrow_tokens,
+                payment_mint,
+                ctx.accounts.buyer.to_account_info(),
+                listing_authority.to_account_info(),
+                signer_seeds,
+            )?;
+            net_of_transfer_fee(payment_mint, amount)?
+        } else {
+            release_lamports(
+                &escrow.to_account_info(),
+                &ctx.accounts.creator_vault.to_account_info(),
+                escrow.amount,
+            )?;
+            escrow.amount
+        };
+        let kept = distribute_creator_share(
+            &CreatorShare {
+                creator: escrow.creator,
+                payment_type: escrow.payment_type,
+                payment_mint: escrow.payment_mint,
+                payees: &escrow.payees,
+            },
+            released,
+            SplitPayoutAccounts {
+                creator_vault: ctx.accounts.creator_vault.to_account_info(),
+                creator_vault_tokens: ctx.accounts.creator_vault_tokens.as_ref(),
+                payment_mint: ctx.accounts.payment_mint.as_ref(),
+                listing_authority: ctx.accounts.listing_authority
+                    .as_ref()
+                    .map(|authority| authority.to_account_info()),
+                listing_authority_bump: ctx.bumps.listing_authority,
+                payment_token_program: &ctx.accounts.payment_token_program,
+                payee_accounts: ctx.remaining_accounts,
+            },
+        )?;
+        ctx.accounts.creator_vault.record_earnings(kept)?;
+
+        emit!(EscrowReleased {
+            unlock_key: escrow.unlock_key,
+            question: escrow.question,
+            creator: escrow.creator,
+            amount: released,
+            timestamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_earnings(ctx: Context<WithdrawEarnings>, amount: u64) -> Result<()> {
+        let vault = &ctx.accounts.creator_vault;
+        require!(
+            amount > 0 && amount <= vault.available(),
+            ErrorCode::InvalidWithdrawalAmount
+        );
+
+        if vault.payment_mint == Pubkey::default() {
+            // SOL earnings sit on the vault above its rent-exempt minimum
+            release_lamports(
+                &vault.to_account_info(),
+                &ctx.accounts.creator.to_account_info(),
+                amount,
+            )?;
+        } else {
+            let signer_seeds: &[&[&[u8]]] = &[&[b"listing_authority", &[ctx.bumps.listing_authority]]];
+            transfer_payment(
+                &ctx.accounts.payment_token_program,
+                payment_account(&ctx.accounts.creator_vault_tokens)?,
+                payment_account(&ctx.accounts.creator_token_account)?,
+                payment_account(&ctx.accounts.payment_mint)?,
+                ctx.accounts.listing_authority.to_account_info(),
+                signer_seeds,
+                amount,
+            )?;
+        }
+
+        let vault = &mut ctx.accounts.creator_vault;
+        vault.total_withdrawn = vault.total_withdrawn
+            .checked_add(amount)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+
+        emit!(EarningsWithdrawn {
+            creator: vault.creator,
+            payment_mint: vault.payment_mint,
+            amount,
+            total_earned: vault.total_earned,
+            total_withdrawn: vault.total_withdrawn,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Opens a creator's vault ahead of their first sale, so co-authors can be
+    // paid through a question split. Pubkey::default() opens the SOL vault.
+    pub fn open_creator_vault(ctx: Context<OpenCreatorVault>, payment_mint: Pubkey) -> Result<()> {
+        require!(
+            payment_mint == Pubkey::default() || ctx.accounts.creator_vault_tokens.is_some(),
+            ErrorCode::MissingPaymentAccount
+        );
+
+        let vault = &mut ctx.accounts.creator_vault;
+        vault.creator = ctx.accounts.creator.key();
+        vault.payment_mint = payment_mint;
+
+        Ok(())
+    }
+
+    pub fn close_user_state(ctx: Context<CloseUserState>) -> Result<()> {
+        let user_state = &ctx.accounts.user_state;
+        require!(!user_state.is_blacklisted, ErrorCode::UserBlacklisted);
+        require!(user_state.questions_created == 0, ErrorCode::UserHasQuestions);
+
+        emit!(UserStateClosed {
+            user: ctx.accounts.user.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Records whoever now holds the key NFT as its owner. The key encrypted for the
+    // previous owner is dropped, and the key waits for the custodian or creator to
+    // re-encrypt it for the new holder through reencrypt_key.
+    pub fn sync_key_owner(ctx: Context<SyncKeyOwner>) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
+
+        let key = &mut ctx.accounts.unlock_key;
+        let holder = ctx.accounts.holder.key();
+        require!(key.owner != holder, ErrorCode::KeyOwnerInSync);
+
+        let previous_owner = key.owner;
+        key.owner = holder;
+        key.encrypted_key = Vec::new();
+        key.reencryption_pending = true;
+
+        emit!(KeyOwnerSynced {
+            key_id: key.token_id,
+            question: key.question,
+            previous_owner,
+            new_owner: holder,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn reencrypt_key(ctx: Context<ReencryptKey>, new_encrypted_key: Vec<u8>) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, ErrorCode::MarketplacePaused);
+        require!(
+            !new_encrypted_key.is_empty() && new_encrypted_key.len() <= MAX_ENCRYPTED_KEY_LENGTH,
+            ErrorCode::InvalidKeyLength
+        );
+
+        let signer = ctx.accounts.signer.key();
+        require!(
+            signer == ctx.accounts.marketplace.key_custodian
+                || signer == ctx.accounts.question.creator,
+            ErrorCode::NotReencryptionAuthority
+        );
+
+        let key = &mut ctx.accounts.unlock_key;
+        require!(key.reencryption_pending, ErrorCode::ReencryptionNotPending);
+        key.encrypted_key = new_encrypted_key;
+        key.reencryption_pending = false;
+
+        emit!(KeyReencrypted {
+            key_id: key.token_id,
+            question: key.question,
+            owner: key.owner,
+            reencrypted_by: signer,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn toggle_marketplace(ctx: Context<ToggleMarketplace>) -> Result<()> {
+        let marketplace = &mut ctx.accounts.marketplace;
+        marketplace.paused = !marketplace.paused;
+
+        emit!(MarketplaceToggled {
+            authority: marketplace.authority,
+            paused: marketplace.paused,
+        });
+
+        Ok(())
+    }
+
+    pub fn blacklist_user(
+        ctx: Context<BlacklistUser>,
+        user: Pubkey,
+    ) -> Result<()> {
+        let user_state = &mut ctx.accounts.user_state;
+        user_state.is_blacklisted = true;
+
+        emit!(UserBlacklisted {
+            user,
+            authority: ctx.accounts.authority.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn unblacklist_user(
+        ctx: Context<BlacklistUser>,
+        user: Pubkey,
+    ) -> Result<()> {
+        let user_state = &mut ctx.accounts.user_state;
+        user_state.is_blacklisted = false;
+
+        emit!(UserUnblacklisted {
+            user,
+            authority: ctx.accounts.authority.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // pause operations
+    pub fn toggle_operation(
+        ctx: Context<ToggleOperation>,
+        operation: OperationType,
+    ) -> Result<()> {
+        let marketplace = &mut ctx.accounts.marketplace;
+        marketplace.paused_operations.toggle(operation);
+
+        emit!(OperationToggled {
+            operation,
+            is_paused: marketplace.paused_operations.is_paused(operation),
+            authority: ctx.accounts.authority.key(),
+        });
+
+        Ok(())
+    }
+
+    pub fn transfer_authority(ctx: Context<TransferAuthority>) -> Result<()> {
+        let timestamp = Clock::get()?.unix_timestamp;
+        
+        let previous_authority = ctx.accounts.marketplace.authority;
+        
+        let marketplace = &mut ctx.accounts.marketplace;
+        marketplace.authority = ctx.accounts.new_authority.key();
+        
+        emit!(AuthorityTransferred {
+            previous_authority,
+            new_authority: marketplace.authority,
+            timestamp,
+        });
+        
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction()]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + // discriminator
+        32 + // authority: Pubkey
+        32 + // treasury: Pubkey
+        8 + // question_counter: u64
+        2 + // platform_fee_bps: u16
+        2 + // creator_royalty_bps: u16
+        8 + // total_volume: u64
+        1 + // paused: bool
+        5 + // paused_operations
+        32 + // bonk_mint: Pubkey
+        32, // key_custodian: Pubkey
+        seeds = [b"marketplace", authority.key().as_ref()],
+        bump
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    /// CHECK: Validated in instruction
+    pub bonk_mint: UncheckedAccount<'info>,
+    /// CHECK: Treasury account
+    pub treasury: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeUserState<'info> {
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 8 + 8 + 1,  // discriminator + questions_created + last_operation_time + is_blacklisted
+        seeds = [b"user_state", user.key().as_ref()],
+        bump
+    )]
+    pub user_state: Account<'info, UserState>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFees<'info> {
+    #[account(mut, has_one = authority)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(
+    content_cid: String,
+    content_hash: [u8; 32],
+    unlock_price: u64,
+    max_keys: u64,
+    pricing_curve: PricingCurve,
+    launch_schedule: Option<LaunchSchedule>,
+    payment_type: PaymentType,
+    royalty_bps: u16,
+    payees: Vec<SplitPayee>
+)]
+pub struct CreateQuestion<'info> {
+    #[account(mut)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(mut)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        init,
+        payer = creator,
+        space = MIN_ACCOUNT_SPACE +
+        32 +                        // creator: Pubkey
+        4 + content_cid.len() +     // content_cid: String
+        32 +                        // content_hash: [u8; 32]
+        8 +                         // content_version: u64
+        8 +                         // unlock_price: u64
+        8 +                         // max_keys: u64
+        8 +                         // current_keys: u64
+        8 +                         // index: u64
+        8 +                         // creation_time: i64
+        8 +                         // total_sales: u64
+        1 +                         // is_active: bool
+        1 +                         // is_taken_down: bool
+        8 +                         // validation_timestamp: i64
+        PRICING_CURVE_SIZE +        // pricing_curve: PricingCurve
+        1 + LAUNCH_SCHEDULE_SIZE +  // launch_schedule: Option<LaunchSchedule>
+        1 + 32 +                    // allowlist_root: Option<[u8; 32]>
+        32 +                        // payment_mint: Pubkey
+        1 +                         // payment_type: PaymentType
+        2 +                         // royalty_bps: u16
+        1 + 32 +                    // collection_mint: Option<Pubkey>
+        1 +                         // key_mode: KeyMode
+        1 + 8 +                     // max_keys_per_wallet: Option<u64>
+        1 + 8 +                     // refund_window: Option<i64>
+        1 +                         // has_split: bool
+        8,                          // open_escrows: u64
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &marketplace.question_counter.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        init,
+        payer = creator,
+        space = MIN_ACCOUNT_SPACE +
+        32 +                                  // question: Pubkey
+        4 + payees.len() * SPLIT_PAYEE_SIZE,  // payees: Vec<SplitPayee>
+        seeds = [b"question_split", question.key().as_ref()],
+        bump
+    )]
+    pub question_split: Option<Account<'info, QuestionSplit>>,
+    #[account(
+        seeds = [b"payment_mint", marketplace.key().as_ref(), payment_mint_config.mint.as_ref()],
+        bump,
+        constraint = payment_mint_config.enabled @ ErrorCode::PaymentMintDisabled
+    )]
+    pub payment_mint_config: Option<Account<'info, PaymentMint>>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(metadata_uri: String, encrypted_key: Vec<u8>)]
+pub struct MintUnlockKey<'info> {
+    #[account(mut)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        mut,
+        constraint = treasury_token_account.owner == marketplace.treasury,
+        token::mint = payment_mint
+    )]
+    pub treasury_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    #[account(
+        init,
+        payer = buyer,
+        space = MIN_ACCOUNT_SPACE +   // discriminator
+        1 +                          // discriminator: u8
+        32 +                         // owner: Pubkey
+        32 +                         // question: Pubkey
+        8 +                          // token_id: u64
+        32 +                         // mint: Pubkey
+        4 + encrypted_key.len() +    // encrypted_key: Vec<u8>
+        1 +                          // is_listed: bool
+        8 +                          // list_price: u64
+        8 +                          // mint_time: i64
+        4 + metadata_uri.len() +     // metadata_uri: String
+        8 +                          // last_sold_price: u64
+        8 +                          // last_sold_time: i64
+        8 +                          // list_time: i64
+        1,                           // reencryption_pending: bool
+        seeds = [
+            b"unlock_key",
+            question.key().as_ref(),
+            &question.current_keys.to_le_bytes()
+        ],
+        bump,
+        constraint = question.current_keys < question.max_keys @ ErrorCode::NoKeysAvailable
+    )]
+    pub unlock_key: Account<'info, UnlockKey>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = MIN_ACCOUNT_SPACE +
+        32 + // question: Pubkey
+        32 + // buyer: Pubkey
+        8,   // purchased: u64
+        seeds = [b"purchase_record", question.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub purchase_record: Account<'info, PurchaseRecord>,
+    #[account(
+        init,
+        payer = buyer,
+        space = MIN_ACCOUNT_SPACE +
+        32 + // unlock_key: Pubkey
+        32 + // question: Pubkey
+        32 + // buyer: Pubkey
+        32 + // creator: Pubkey
+        8 +  // amount: u64
+        8 +  // platform_fee: u64
+        1 +  // payment_type: PaymentType
+        32 + // payment_mint: Pubkey
+        4 + MAX_SPLIT_PAYEES * SPLIT_PAYEE_SIZE + // payees: Vec<SplitPayee>
+        8,   // refundable_until: i64
+        seeds = [b"refund_escrow", unlock_key.key().as_ref()],
+        bump
+    )]
+    pub refund_escrow: Option<Account<'info, RefundEscrow>>,
+    #[account(
+        init,
+        payer = buyer,
+        seeds = [b"refund_escrow_tokens", unlock_key.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = listing_authority,
+        token::token_program = payment_token_program
+    )]
+    pub refund_escrow_tokens: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    /// CHECK: PDA that owns refund escrow and creator vault token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: Option<UncheckedAccount<'info>>,
+    
+    #[account(
+        mut,
+        constraint = buyer_token_account.owner == buyer.key(),
+        token::mint = payment_mint
+    )]
+    pub buyer_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = CREATOR_VAULT_SIZE,
+        seeds = [b"creator_vault", question.creator.as_ref(), question.payment_mint.as_ref()],
+        bump
+    )]
+    pub creator_vault: Account<'info, CreatorVault>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        seeds = [b"creator_vault_tokens", creator_vault.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = listing_authority,
+        token::token_program = payment_token_program
+    )]
+    pub creator_vault_tokens: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    #[account(
+        seeds = [b"question_split", question.key().as_ref()],
+        bump
+    )]
+    pub question_split: Option<Account<'info, QuestionSplit>>,
+    
+    /// CHECK: receives the platform fee when the question is priced in SOL
+    #[account(mut, address = marketplace.treasury)]
+    pub treasury: Option<UncheckedAccount<'info>>,
+    
+    #[account(address = question.payment_mint @ ErrorCode::InvalidPaymentMint)]
+    pub payment_mint: Option<InterfaceAccount<'info, token_interface::Mint>>,
+    #[account(
+        seeds = [b"payment_mint", marketplace.key().as_ref(), question.payment_mint.as_ref()],
+        bump,
+        constraint = payment_mint_config.enabled @ ErrorCode::PaymentMintDisabled
+    )]
+    pub payment_mint_config: Option<Account<'info, PaymentMint>>,
+    
+    /// CHECK: Metaplex will check this
+    #[account(mut)]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: Metaplex will check this
+    #[account(mut)]
+    pub master_edition: UncheckedAccount<'info>,
+    
+    #[account(
+        init,
+        payer = buyer,
+        seeds = [b"key_mint", unlock_key.key().as_ref()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = mint_authority,
+        mint::freeze_authority = mint_authority
+    )]
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = buyer,
+        associated_token::mint = mint,
+        associated_token::authority = buyer
+    )]
+    pub buyer_key_account: Account<'info, TokenAccount>,
+    
+    /// CHECK: PDA for mint authority
+    #[account(
+        seeds = [b"mint_authority"],
+        bump
+    )]
+    pub mint_authority: UncheckedAccount<'info>,
+    
+    /// CHECK: must be the mint authority PDA so it can sign the master edition
+    #[account(constraint = update_authority.key() == mint_authority.key() @ ErrorCode::InvalidAuthority)]
+    pub update_authority: UncheckedAccount<'info>,
+    
+    /// CHECK: required when the question has a collection
+    #[account(constraint = question.collection_mint == Some(collection_mint.key()) @ ErrorCode::InvalidCollection)]
+    pub collection_mint: Option<UncheckedAccount<'info>>,
+    /// CHECK: Metaplex will check this
+    #[account(mut)]
+    pub collection_metadata: Option<UncheckedAccount<'info>>,
+    /// CHECK: Metaplex will check this
+    pub collection_master_edition: Option<UncheckedAccount<'info>>,
+    
+    pub token_program: Program<'info, Token>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub metadata_program: Program<'info, Metadata>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    #[account(mut)]
+    pub user_state: Account<'info, UserState>,
+}
+
+impl<'info> MintUnlockKey<'info> {
+    fn validate_mint(&self, metadata_uri: &str, encrypted_key: &[u8]) -> Result<()> {
+        require!(!self.marketplace.paused, ErrorCode::MarketplacePaused);
+        
+        require!(
+            !self.marketplace.paused_operations.mint_key,
+            ErrorCode::OperationPaused
+        );
+
+        require!(self.question.is_active, ErrorCode::QuestionInactive);
+        require!(
+            self.question.key_mode == KeyMode::Standard,
+            ErrorCode::UnsupportedKeyMode
+        );
+        require!(metadata_uri.len() <= MAX_URI_LENGTH, ErrorCode::URITooLong);
+
+        require!(
+            self.question.current_keys < self.question.max_keys,
+            ErrorCode::NoKeysAvailable
+        );
+
+        // input validation
+        require!(
+            encrypted_key.len() <= MAX_ENCRYPTED_KEY_LENGTH,
+            ErrorCode::InvalidKeyLength
+        );
+        require!(
+            metadata_uri.is_ascii(),
+            ErrorCode::InvalidMetadataFormat
+        );
+        require!(
+            metadata_uri.len() >= MIN_METADATA_LENGTH,
+            ErrorCode::InvalidMetadataFormat
+        );
+
+        Ok(())
+    }
+
+    // Charges unlock_price and mints the next key of the question to the buyer.
+    // Split payee vaults come in as remaining accounts.
+    fn process_mint(
+        &mut self,
+        mint_authority_bump: u8,
+        listing_authority_bump: u8,
+        payee_accounts: &[AccountInfo<'info>],
+        metadata_uri: String,
+        encrypted_key: Vec<u8>,
+        unlock_price: u64,
+    ) -> Result<()> {
+        let question_key = self.question.key();
+        let creators = key_nft_creators(
+            self.mint_authority.key(),
+            &CreatorShare::of_question(&self.question, &self.question_split)?,
+        )?;
+        let current_keys = self.question.current_keys;
+        let platform_fee_bps = self.marketplace.platform_fee_bps;
+        let question_creator = self.question.creator;
+        let royalty_bps = self.question.royalty_bps;
+        let collection_mint = self.question.collection_mint;
+        let _index = self.question.index;
+
+        let question = &mut self.question;
+        let marketplace = &mut self.marketplace;
+        let key = &mut self.unlock_key;
+
+        // while refunds are possible the creator share waits in the key's refund
+        // escrow, otherwise it goes straight into the creator's vault
+        require!(
+            question.refund_window.is_some() == self.refund_escrow.is_some(),
+            ErrorCode::RefundEscrowMismatch
+        );
+        let (creator_token_account, creator) = match &self.refund_escrow {
+            Some(refund_escrow) => (&self.refund_escrow_tokens, Some(refund_escrow.to_account_info())),
+            None => (&self.creator_vault_tokens, Some(self.creator_vault.to_account_info())),
+        };
+        let creator_payment = collect_unlock_payment(
+            question.payment_type,
+            unlock_price,
+            platform_fee_bps,
+            &self.buyer,
+            UnlockPaymentAccounts {
+                buyer_token_account: &self.buyer_token_account,
+                treasury_token_account: &self.treasury_token_account,
+                creator_token_account,
+                payment_mint: &self.payment_mint,
+                payment_mint_config: &self.payment_mint_config,
+                treasury: &self.treasury,
+                creator,
+                payment_token_program: &self.payment_token_program,
+                system_program: &self.system_program,
+            },
+        )?;
+        self.purchase_record.record(question, self.buyer.key(), 1)?;
+
+        if let (Some(window), Some(refund_escrow)) = (question.refund_window, &mut self.refund_escrow) {
+            refund_escrow.unlock_key = key.key();
+            refund_escrow.question = question_key;
+            refund_escrow.buyer = self.buyer.key();
+            refund_escrow.creator = question_creator;
+            refund_escrow.amount = creator_payment;
+            refund_escrow.platform_fee = platform_fee(unlock_price, platform_fee_bps)?;
+            refund_escrow.payment_type = question.payment_type;
+            refund_escrow.payment_mint = question.payment_mint;
+            refund_escrow.payees = CreatorShare::of_question(question, &self.question_split)?
+                .payees
+                .to_vec();
+            refund_escrow.refundable_until = Clock::get()?.unix_timestamp
+                .checked_add(window)
+                .ok_or(ErrorCode::NumericalOverflow)?;
+            // opens the vault now so release_escrow has somewhere to pay into
+            self.creator_vault.credit(question, 0)?;
+        } else {
+            let kept = distribute_creator_share(
+                &CreatorShare::of_question(question, &self.question_split)?,
+                creator_payment,
+                SplitPayoutAccounts {
+                    creator_vault: self.creator_vault.to_account_info(),
+                    creator_vault_tokens: self.creator_vault_tokens.as_ref(),
+                    payment_mint: self.payment_mint.as_ref(),
+                    listing_authority: self.listing_authority
+                        .as_ref()
+                        .map(|authority| authority.to_account_info()),
+                    listing_authority_bump,
+                    payment_token_program: &self.payment_token_program,
+                    payee_accounts,
+                },
+            )?;
+            self.creator_vault.credit(question, kept)?;
+        }
+
+        // Set the discriminator
+        key.discriminator = UNLOCK_KEY_DISCRIMINATOR;
+        
+        // store the encrypted symmetric key
+        key.encrypted_key = encrypted_key;
+        
+        // create NFT metadata and mint token
+        key.owner = self.buyer.key();
+        key.question = question_key;
+        key.token_id = current_keys;
+        key.mint = self.mint.key();
+        key.is_listed = false;
+        key.list_price = 0;
+        key.mint_time = Clock::get()?.unix_timestamp;
+        key.metadata_uri = metadata_uri.clone();
+        key.reencryption_pending = false;
+        
+        // update statistics
+        question.current_keys = question.current_keys
+            .checked_add(1)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+        question.total_sales = question.total_sales
+            .checked_add(unlock_price)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+        marketplace.total_volume = marketplace.total_volume
+            .checked_add(unlock_price)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+
+        let signer_seeds: &[&[&[u8]]] = &[&[b"mint_authority", &[mint_authority_bump]]];
+        let collection = key_collection_accounts(
+            collection_mint,
+            &self.collection_mint,
+            &self.collection_metadata,
+            &self.collection_master_edition,
+        )?;
+        mint_key_nft(
+            KeyNftAccounts {
+                mint: self.mint.to_account_info(),
+                token_account: self.buyer_key_account.to_account_info(),
+                metadata: self.metadata.to_account_info(),
+                master_edition: self.master_edition.to_account_info(),
+                mint_authority: self.mint_authority.to_account_info(),
+                update_authority: self.update_authority.to_account_info(),
+                payer: self.buyer.to_account_info(),
+                token_program: self.token_program.to_account_info(),
+                metadata_program: self.metadata_program.to_account_info(),
+                system_program: self.system_program.to_account_info(),
+                rent: self.rent.to_account_info(),
+                collection,
+            },
+            format!("QA Key #{} - Q{}", key.token_id, question.index),
+            metadata_uri,
+            creators,
+            royalty_bps,
+            signer_seeds,
+        )?;
+
+        emit!(KeyMinted {
+            key_id: key.token_id,
+            question_id: question.index,
+            owner: key.owner,
+            mint_time: key.mint_time,
+            price: unlock_price,
+        });
+
+        // Update rate limiting state - only in non-test mode
+        #[cfg(not(feature = "test"))]
+        {
+            let current_time = Clock::get()?.unix_timestamp;
+            self.user_state.last_operation_time = current_time;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct MintUnlockKeysBatch<'info> {
+    #[account(mut)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = MIN_ACCOUNT_SPACE +
+        32 + // question: Pubkey
+        32 + // buyer: Pubkey
+        8,   // purchased: u64
+        seeds = [b"purchase_record", question.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub purchase_record: Account<'info, PurchaseRecord>,
+    #[account(
+        mut,
+        constraint = buyer_token_account.owner == buyer.key(),
+        token::mint = payment_mint
+    )]
+    pub buyer_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = CREATOR_VAULT_SIZE,
+        seeds = [b"creator_vault", question.creator.as_ref(), question.payment_mint.as_ref()],
+        bump
+    )]
+    pub creator_vault: Account<'info, CreatorVault>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        seeds = [b"creator_vault_tokens", creator_vault.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = listing_authority,
+        token::token_program = payment_token_program
+    )]
+    pub creator_vault_tokens: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    #[account(
+        seeds = [b"question_split", question.key().as_ref()],
+        bump
+    )]
+    pub question_split: Option<Account<'info, QuestionSplit>>,
+    /// CHECK: PDA that owns escrow and creator vault token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: Option<UncheckedAccount<'info>>,
+    #[account(
+        mut,
+        constraint = treasury_token_account.owner == marketplace.treasury,
+        token::mint = payment_mint
+    )]
+    pub treasury_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    /// CHECK: receives the platform fee when the question is priced in SOL
+    #[account(mut, address = marketplace.treasury)]
+    pub treasury: Option<UncheckedAccount<'info>>,
+    #[account(address = question.payment_mint @ ErrorCode::InvalidPaymentMint)]
+    pub payment_mint: Option<InterfaceAccount<'info, token_interface::Mint>>,
+    #[account(
+        seeds = [b"payment_mint", marketplace.key().as_ref(), question.payment_mint.as_ref()],
+        bump,
+        constraint = payment_mint_config.enabled @ ErrorCode::PaymentMintDisabled
+    )]
+    pub payment_mint_config: Option<Account<'info, PaymentMint>>,
+    /// CHECK: PDA for mint authority, also every key's update authority
+    #[account(
+        seeds = [b"mint_authority"],
+        bump
+    )]
+    pub mint_authority: UncheckedAccount<'info>,
+    /// CHECK: required when the question has a collection
+    #[account(constraint = question.collection_mint == Some(collection_mint.key()) @ ErrorCode::InvalidCollection)]
+    pub collection_mint: Option<UncheckedAccount<'info>>,
+    /// CHECK: Metaplex will check this
+    #[account(mut)]
+    pub collection_metadata: Option<UncheckedAccount<'info>>,
+    /// CHECK: Metaplex will check this
+    pub collection_master_edition: Option<UncheckedAccount<'info>>,
+    pub token_program: Program<'info, Token>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub metadata_program: Program<'info, Metadata>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    #[account(mut)]
+    pub user_state: Account<'info, UserState>,
+}
+
+#[derive(Accounts)]
+pub struct MintUnlockKeyWithProof<'info> {
+    pub mint_key: MintUnlockKey<'info>,
+    #[account(
+        init_if_needed,
+        payer = mint_key.buyer,
+        space = MIN_ACCOUNT_SPACE +
+        32 + // question: Pubkey
+        32 + // buyer: Pubkey
+        8,   // claimed: u64
+        seeds = [
+            b"allowlist_claim",
+            mint_key.question.key().as_ref(),
+            mint_key.buyer.key().as_ref()
+        ],
+        bump
+    )]
+    pub allowlist_claim: Account<'info, AllowlistClaim>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ListKey<'info> {
+    #[account(mut)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        mut,
+        seeds = [
+            b"unlock_key",
+            question.key().as_ref(),
+            &unlock_key.token_id.to_le_bytes()
+        ],
+        bump
+    )]
+    pub unlock_key: Account<'info, UnlockKey>,
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        token::mint = unlock_key.mint,
+        token::authority = seller,
+        constraint = seller_key_account.amount == 1 @ ErrorCode::NotKeyOwner
+    )]
+    pub seller_key_account: Account<'info, TokenAccount>,
+    #[account(address = unlock_key.mint)]
+    pub key_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = seller,
+        seeds = [b"listing_escrow", unlock_key.key().as_ref()],
+        bump,
+        token::mint = key_mint,
+        token::authority = listing_authority
+    )]
+    pub listing_escrow: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns listing and sale escrow token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub user_state: Account<'info, UserState>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateListing<'info> {
+    #[account(mut)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        mut,
+        seeds = [
+            b"unlock_key",
+            question.key().as_ref(),
+            &unlock_key.token_id.to_le_bytes()
+        ],
+        bump
+    )]
+    pub unlock_key: Account<'info, UnlockKey>,
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        seeds = [b"listing_escrow", unlock_key.key().as_ref()],
+        bump,
+        constraint = listing_escrow.amount == 1 @ ErrorCode::NotListed
+    )]
+    pub listing_escrow: Account<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct CancelListing<'info> {
+    #[account(mut)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        mut,
+        seeds = [
+            b"unlock_key",
+            question.key().as_ref(),
+            &unlock_key.token_id.to_le_bytes()
+        ],
+        bump
+    )]
+    pub unlock_key: Account<'info, UnlockKey>,
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        token::mint = unlock_key.mint,
+        token::authority = seller
+    )]
+    pub seller_key_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"listing_escrow", unlock_key.key().as_ref()],
+        bump
+    )]
+    pub listing_escrow: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns listing and sale escrow token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct BuyListedKey<'info> {
+    #[account(mut)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump,
+        constraint = !marketplace.paused @ ErrorCode::MarketplacePaused,
+        constraint = !marketplace.paused_operations.buy_key @ ErrorCode::OperationPaused
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        mut,
+        seeds = [
+            b"unlock_key",
+            question.key().as_ref(),
+            &unlock_key.token_id.to_le_bytes()
+        ],
+        bump,
+        constraint = unlock_key.is_listed @ ErrorCode::NotListed,
+        constraint = unlock_key.owner != buyer.key() @ ErrorCode::CannotBuyOwnKey
+    )]
+    pub unlock_key: Account<'info, UnlockKey>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    #[account(
+        mut,
+        constraint = buyer_token_account.owner == buyer.key(),
+        token::mint = payment_mint
+    )]
+    pub buyer_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = MIN_ACCOUNT_SPACE +
+        32 + // unlock_key: Pubkey
+        32 + // buyer: Pubkey
+        32 + // seller: Pubkey
+        8 +  // price: u64
+        8 +  // platform_fee: u64
+        8 +  // creator_royalty: u64
+        8 +  // seller_payment: u64
+        8 +  // created_at: i64
+        8 +  // expires_at: i64
+        1,   // payment_type: PaymentType
+        seeds = [b"pending_sale", unlock_key.key().as_ref()],
+        bump
+    )]
+    pub pending_sale: Account<'info, PendingSale>,
+
+    #[account(
+        init,
+        payer = buyer,
+        seeds = [b"sale_escrow", unlock_key.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = listing_authority,
+        token::token_program = payment_token_program
+    )]
+    pub sale_escrow: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    /// CHECK: PDA that owns listing and sale escrow token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: UncheckedAccount<'info>,
+    
+    #[account(address = question.payment_mint @ ErrorCode::InvalidPaymentMint)]
+    pub payment_mint: Option<InterfaceAccount<'info, token_interface::Mint>>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    #[account(mut)]
+    pub user_state: Account<'info, UserState>,
+}
+
+#[derive(Accounts)]
+#[instruction(new_encrypted_key: Vec<u8>)]
+pub struct FulfillSale<'info> {
+    #[account(mut)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        mut,
+        seeds = [
+            b"unlock_key",
+            question.key().as_ref(),
+            &unlock_key.token_id.to_le_bytes()
+        ],
+        bump,
+        realloc = UNLOCK_KEY_BASE_SIZE + 
+            4 + new_encrypted_key.len() +  // encrypted_key: Vec<u8>
+            4 + unlock_key.metadata_uri.len(),  // metadata_uri: String
+        realloc::payer = fulfiller,
+        realloc::zero = false
+    )]
+    pub unlock_key: Account<'info, UnlockKey>,
+    #[account(
+        mut,
+        seeds = [b"pending_sale", unlock_key.key().as_ref()],
+        bump,
+        has_one = buyer,
+        has_one = seller,
+        close = buyer
+    )]
+    pub pending_sale: Account<'info, PendingSale>,
+    #[account(mut)]
+    pub fulfiller: Signer<'info>,
+    /// CHECK: receives the pending sale rent, checked against pending_sale
+    #[account(mut)]
+    pub buyer: UncheckedAccount<'info>,
+    /// CHECK: receives the listing escrow rent, checked against pending_sale
+    #[account(mut)]
+    pub seller: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"sale_escrow", unlock_key.key().as_ref()],
+        bump
+    )]
+    pub sale_escrow: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"listing_escrow", unlock_key.key().as_ref()],
+        bump
+    )]
+    pub listing_escrow: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = treasury_token_account.owner == marketplace.treasury,
+        token::mint = payment_mint
+    )]
+    pub treasury_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    #[account(
+        mut,
+        constraint = seller_token_account.owner == seller.key(),
+        token::mint = payment_mint
+    )]
+    pub seller_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = fulfiller,
+        space = CREATOR_VAULT_SIZE,
+        seeds = [b"creator_vault", question.creator.as_ref(), question.payment_mint.as_ref()],
+        bump
+    )]
+    pub creator_vault: Account<'info, CreatorVault>,
+    #[account(
+        init_if_needed,
+        payer = fulfiller,
+        seeds = [b"creator_vault_tokens", creator_vault.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = listing_authority,
+        token::token_program = payment_token_program
+    )]
+    pub creator_vault_tokens: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    #[account(
+        seeds = [b"question_split", question.key().as_ref()],
+        bump
+    )]
+    pub question_split: Option<Account<'info, QuestionSplit>>,
+    /// CHECK: receives the platform fee when the sale is paid in SOL
+    #[account(mut, address = marketplace.treasury)]
+    pub treasury: Option<UncheckedAccount<'info>>,
+    #[account(
+        mut,
+        token::mint = unlock_key.mint,
+        token::authority = buyer
+    )]
+    pub buyer_key_account: Account<'info, TokenAccount>,
+
+    /// CHECK: PDA that owns listing and sale escrow token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: UncheckedAccount<'info>,
+
+    #[account(mut, address = question.payment_mint @ ErrorCode::InvalidPaymentMint)]
+    pub payment_mint: Option<InterfaceAccount<'info, token_interface::Mint>>,
+    pub token_program: Program<'info, Token>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimSale<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"unlock_key",
+            unlock_key.question.as_ref(),
+            &unlock_key.token_id.to_le_bytes()
+        ],
+        bump
+    )]
+    pub unlock_key: Account<'info, UnlockKey>,
+    #[account(
+        mut,
+        seeds = [b"pending_sale", unlock_key.key().as_ref()],
+        bump,
+        has_one = buyer,
+        close = buyer
+    )]
+    pub pending_sale: Account<'info, PendingSale>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = buyer
+    )]
+    pub buyer_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"sale_escrow", unlock_key.key().as_ref()],
+        bump,
+        token::mint = payment_mint
+    )]
+    pub sale_escrow: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    /// CHECK: PDA that owns listing and sale escrow token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub payment_mint: Option<InterfaceAccount<'info, token_interface::Mint>>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(target: Pubkey)]
+pub struct MakeOffer<'info> {
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        constraint = unlock_key.question == question.key() @ ErrorCode::InvalidOfferTarget
+    )]
+    pub unlock_key: Option<Account<'info, UnlockKey>>,
+    #[account(
+        init,
+        payer = bidder,
+        space = MIN_ACCOUNT_SPACE +
+        32 + // bidder: Pubkey
+        32 + // question: Pubkey
+        33 + // unlock_key: Option<Pubkey>
+        8 +  // price: u64
+        8,   // created_at: i64
+        seeds = [b"offer", target.as_ref(), bidder.key().as_ref()],
+        bump
+    )]
+    pub offer: Account<'info, Offer>,
+    #[account(
+        init,
+        payer = bidder,
+        seeds = [b"offer_escrow", offer.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = listing_authority,
+        token::token_program = payment_token_program
+    )]
+    pub offer_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(
+        mut,
+        constraint = bidder_token_account.owner == bidder.key(),
+        token::mint = payment_mint
+    )]
+    pub bidder_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    /// CHECK: PDA that owns listing and sale escrow token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: UncheckedAccount<'info>,
+    #[account(address = question.payment_mint @ ErrorCode::InvalidPaymentMint)]
+    pub payment_mint: InterfaceAccount<'info, token_interface::Mint>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOffer<'info> {
+    #[account(
+        mut,
+        has_one = bidder,
+        close = bidder
+    )]
+    pub offer: Account<'info, Offer>,
+    #[account(
+        mut,
+        seeds = [b"offer_escrow", offer.key().as_ref()],
+        bump
+    )]
+    pub offer_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(
+        mut,
+        token::mint = offer_escrow.mint,
+        token::authority = bidder
+    )]
+    pub bidder_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    /// CHECK: PDA that owns listing and sale escrow token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: UncheckedAccount<'info>,
+    #[account(mut, address = offer_escrow.mint @ ErrorCode::InvalidPaymentMint)]
+    pub payment_mint: InterfaceAccount<'info, token_interface::Mint>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(new_encrypted_key: Vec<u8>)]
+pub struct AcceptOffer<'info> {
+    #[account(mut)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        mut,
+        seeds = [
+            b"unlock_key",
+            question.key().as_ref(),
+            &unlock_key.token_id.to_le_bytes()
+        ],
+        bump,
+        realloc = UNLOCK_KEY_BASE_SIZE + 
+            4 + new_encrypted_key.len() +  // encrypted_key: Vec<u8>
+            4 + unlock_key.metadata_uri.len(),  // metadata_uri: String
+        realloc::payer = seller,
+        realloc::zero = false
+    )]
+    pub unlock_key: Account<'info, UnlockKey>,
+    #[account(
+        mut,
+        has_one = question @ ErrorCode::InvalidOfferTarget,
+        has_one = bidder,
+        close = bidder
+    )]
+    pub offer: Account<'info, Offer>,
+    #[account(
+        mut,
+        seeds = [b"offer_escrow", offer.key().as_ref()],
+        bump
+    )]
+    pub offer_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    /// CHECK: receives the offer rent, checked against offer
+    #[account(mut)]
+    pub bidder: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        token::mint = unlock_key.mint,
+        token::authority = seller,
+        constraint = seller_key_account.amount == 1 @ ErrorCode::NotKeyOwner
+    )]
+    pub seller_key_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = unlock_key.mint,
+        token::authority = bidder
+    )]
+    pub bidder_key_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = treasury_token_account.owner == marketplace.treasury,
+        token::mint = payment_mint
+    )]
+    pub treasury_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(
+        mut,
+        constraint = seller_token_account.owner == seller.key(),
+        token::mint = payment_mint
+    )]
+    pub seller_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = CREATOR_VAULT_SIZE,
+        seeds = [b"creator_vault", question.creator.as_ref(), question.payment_mint.as_ref()],
+        bump
+    )]
+    pub creator_vault: Account<'info, CreatorVault>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        seeds = [b"creator_vault_tokens", creator_vault.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = listing_authority,
+        token::token_program = payment_token_program
+    )]
+    pub creator_vault_tokens: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(
+        seeds = [b"question_split", question.key().as_ref()],
+        bump
+    )]
+    pub question_split: Option<Account<'info, QuestionSplit>>,
+    /// CHECK: PDA that owns listing and sale escrow token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: UncheckedAccount<'info>,
+    #[account(mut, address = question.payment_mint @ ErrorCode::InvalidPaymentMint)]
+    pub payment_mint: InterfaceAccount<'info, token_interface::Mint>,
+    pub token_program: Program<'info, Token>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateAuction<'info> {
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        seeds = [
+            b"unlock_key",
+            question.key().as_ref(),
+            &unlock_key.token_id.to_le_bytes()
+        ],
+        bump
+    )]
+    pub unlock_key: Account<'info, UnlockKey>,
+    #[account(
+        init,
+        payer = seller,
+        space = MIN_ACCOUNT_SPACE +
+        32 + // seller: Pubkey
+        32 + // unlock_key: Pubkey
+        32 + // question: Pubkey
+        1 +  // auction_type: AuctionType
+        8 +  // start_price: u64
+        8 +  // reserve_price: u64
+        8 +  // end_price: u64
+        8 +  // min_bid_increment: u64
+        8 +  // start_time: i64
+        8 +  // end_time: i64
+        32 + // highest_bidder: Pubkey
+        8,   // highest_bid: u64
+        seeds = [b"auction", unlock_key.key().as_ref()],
+        bump
+    )]
+    pub auction: Account<'info, Auction>,
+    #[account(
+        init,
+        payer = seller,
+        seeds = [b"auction_escrow", auction.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = listing_authority,
+        token::token_program = payment_token_program
+    )]
+    pub auction_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        token::mint = unlock_key.mint,
+        token::authority = seller,
+        constraint = seller_key_account.amount == 1 @ ErrorCode::NotKeyOwner
+    )]
+    pub seller_key_account: Account<'info, TokenAccount>,
+    #[account(address = unlock_key.mint)]
+    pub key_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = seller,
+        seeds = [b"listing_escrow", unlock_key.key().as_ref()],
+        bump,
+        token::mint = key_mint,
+        token::authority = listing_authority
+    )]
+    pub listing_escrow: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns listing and sale escrow token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: UncheckedAccount<'info>,
+    #[account(address = question.payment_mint @ ErrorCode::InvalidPaymentMint)]
+    pub payment_mint: InterfaceAccount<'info, token_interface::Mint>,
+    pub token_program: Program<'info, Token>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceBid<'info> {
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        mut,
+        seeds = [b"auction", auction.unlock_key.as_ref()],
+        bump,
+        has_one = question
+    )]
+    pub auction: Account<'info, Auction>,
+    #[account(
+        mut,
+        seeds = [b"auction_escrow", auction.key().as_ref()],
+        bump
+    )]
+    pub auction_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    pub bidder: Signer<'info>,
+    #[account(
+        mut,
+        token::mint = auction_escrow.mint,
+        token::authority = bidder
+    )]
+    pub bidder_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(
+        mut,
+        token::mint = auction_escrow.mint
+    )]
+    pub previous_bidder_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    /// CHECK: PDA that owns listing and sale escrow token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: UncheckedAccount<'info>,
+    #[account(mut, address = auction_escrow.mint @ ErrorCode::InvalidPaymentMint)]
+    pub payment_mint: InterfaceAccount<'info, token_interface::Mint>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(new_encrypted_key: Vec<u8>)]
+pub struct SettleAuction<'info> {
+    #[account(mut)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        mut,
+        seeds = [
+            b"unlock_key",
+            question.key().as_ref(),
+            &unlock_key.token_id.to_le_bytes()
+        ],
+        bump,
+        realloc = UNLOCK_KEY_BASE_SIZE + 
+            4 + new_encrypted_key.len() +  // encrypted_key: Vec<u8>
+            4 + unlock_key.metadata_uri.len(),  // metadata_uri: String
+        realloc::payer = settler,
+        realloc::zero = false
+    )]
+    pub unlock_key: Account<'info, UnlockKey>,
+    #[account(
+        mut,
+        seeds = [b"auction", unlock_key.key().as_ref()],
+        bump,
+        has_one = seller,
+        close = seller
+    )]
+    pub auction: Account<'info, Auction>,
+    #[account(
+        mut,
+        seeds = [b"auction_escrow", auction.key().as_ref()],
+        bump
+    )]
+    pub auction_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"listing_escrow", unlock_key.key().as_ref()],
+        bump
+    )]
+    pub listing_escrow: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub settler: Signer<'info>,
+    /// CHECK: receives the auction rent, checked against auction
+    #[account(mut)]
+    pub seller: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        token::mint = unlock_key.mint,
+        token::authority = auction.highest_bidder
+    )]
+    pub winner_key_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = treasury_token_account.owner == marketplace.treasury,
+        token::mint = payment_mint
+    )]
+    pub treasury_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(
+        mut,
+        constraint = seller_token_account.owner == seller.key(),
+        token::mint = payment_mint
+    )]
+    pub seller_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = settler,
+        space = CREATOR_VAULT_SIZE,
+        seeds = [b"creator_vault", question.creator.as_ref(), question.payment_mint.as_ref()],
+        bump
+    )]
+    pub creator_vault: Account<'info, CreatorVault>,
+    #[account(
+        init_if_needed,
+        payer = settler,
+        seeds = [b"creator_vault_tokens", creator_vault.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = listing_authority,
+        token::token_program = payment_token_program
+    )]
+    pub creator_vault_tokens: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(
+        seeds = [b"question_split", question.key().as_ref()],
+        bump
+    )]
+    pub question_split: Option<Account<'info, QuestionSplit>>,
+    /// CHECK: PDA that owns listing and sale escrow token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: UncheckedAccount<'info>,
+    #[account(mut, address = question.payment_mint @ ErrorCode::InvalidPaymentMint)]
+    pub payment_mint: InterfaceAccount<'info, token_interface::Mint>,
+    pub token_program: Program<'info, Token>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelAuction<'info> {
+    pub unlock_key: Account<'info, UnlockKey>,
+    #[account(mut, address = unlock_key.question)]
+    pub question: Account<'info, Question>,
+    #[account(
+        mut,
+        seeds = [b"auction", unlock_key.key().as_ref()],
+        bump,
+        has_one = seller,
+        close = seller
+    )]
+    pub auction: Account<'info, Auction>,
+    #[account(
+        mut,
+        seeds = [b"auction_escrow", auction.key().as_ref()],
+        bump
+    )]
+    pub auction_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"listing_escrow", unlock_key.key().as_ref()],
+        bump
+    )]
+    pub listing_escrow: Account<'info, TokenAccount>,
+    pub caller: Signer<'info>,
+    /// CHECK: receives the escrow rent, checked against auction
+    #[account(mut)]
+    pub seller: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        token::mint = unlock_key.mint,
+        token::authority = seller
+    )]
+    pub seller_key_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = auction_escrow.mint
+    )]
+    pub bidder_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    /// CHECK: PDA that owns listing and sale escrow token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: UncheckedAccount<'info>,
+    #[account(mut, address = auction_escrow.mint @ ErrorCode::InvalidPaymentMint)]
+    pub payment_mint: InterfaceAccount<'info, token_interface::Mint>,
+    pub token_program: Program<'info, Token>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct GetNextUnlockPrice<'info> {
+    pub question: Account<'info, Question>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateQuestionTerms<'info> {
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump,
+        has_one = creator @ ErrorCode::NotQuestionCreator
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        seeds = [b"key_tree", question.key().as_ref()],
+        bump
+    )]
+    pub key_tree: Option<Account<'info, KeyTree>>,
+    #[account(
+        mut,
+        seeds = [b"user_state", creator.key().as_ref()],
+        bump
+    )]
+    pub user_state: Account<'info, UserState>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(new_content_cid: String)]
+pub struct UpdateQuestionContent<'info> {
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump,
+        has_one = creator @ ErrorCode::NotQuestionCreator,
+        realloc = QUESTION_BASE_SIZE + new_content_cid.len(),
+        realloc::payer = creator,
+        realloc::zero = false
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        init,
+        payer = creator,
+        space = MIN_ACCOUNT_SPACE +
+        32 +                            // question: Pubkey
+        8 +                             // version: u64
+        4 + question.content_cid.len() + // content_cid: String
+        32 +                            // content_hash: [u8; 32]
+        8,                              // replaced_at: i64
+        seeds = [
+            b"question_revision",
+            question.key().as_ref(),
+            &question.content_version.to_le_bytes()
+        ],
+        bump
+    )]
+    pub revision: Account<'info, QuestionRevision>,
+    #[account(
+        mut,
+        seeds = [b"user_state", creator.key().as_ref()],
+        bump
+    )]
+    pub user_state: Account<'info, UserState>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetQuestionActive<'info> {
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump,
+        has_one = creator @ ErrorCode::NotQuestionCreator
+    )]
+    pub question: Account<'info, Question>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateQuestionCollection<'info> {
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump,
+        has_one = creator @ ErrorCode::NotQuestionCreator
+    )]
+    pub question: Account<'info, Question>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    #[account(
+        init,
+        payer = creator,
+        seeds = [b"collection_mint", question.key().as_ref()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = mint_authority,
+        mint::freeze_authority = mint_authority
+    )]
+    pub collection_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = creator,
+        associated_token::mint = collection_mint,
+        associated_token::authority = mint_authority
+    )]
+    pub collection_token_account: Account<'info, TokenAccount>,
+    /// CHECK: Metaplex will check this
+    #[account(mut)]
+    pub collection_metadata: UncheckedAccount<'info>,
+    /// CHECK: Metaplex will check this
+    #[account(mut)]
+    pub collection_master_edition: UncheckedAccount<'info>,
+    /// CHECK: PDA for mint authority, also the collection's update authority
+    #[account(
+        seeds = [b"mint_authority"],
+        bump
+    )]
+    pub mint_authority: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+    pub metadata_program: Program<'info, Metadata>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(max_depth: u8)]
+pub struct CreateKeyTree<'info> {
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump,
+        has_one = creator @ ErrorCode::NotQuestionCreator
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        init,
+        payer = creator,
+        space = KeyTree::space(max_depth),
+        seeds = [b"key_tree", question.key().as_ref()],
+        bump
+    )]
+    pub key_tree: Account<'info, KeyTree>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MintCompressedKey<'info> {
+    #[account(mut)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump,
+        constraint = question.key_mode == KeyMode::Compressed @ ErrorCode::UnsupportedKeyMode
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        mut,
+        seeds = [b"key_tree", question.key().as_ref()],
+        bump
+    )]
+    pub key_tree: Account<'info, KeyTree>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = MIN_ACCOUNT_SPACE +
+        32 + // question: Pubkey
+        32 + // buyer: Pubkey
+        8,   // purchased: u64
+        seeds = [b"purchase_record", question.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub purchase_record: Account<'info, PurchaseRecord>,
+    #[account(
+        mut,
+        constraint = buyer_token_account.owner == buyer.key(),
+        token::mint = payment_mint
+    )]
+    pub buyer_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = CREATOR_VAULT_SIZE,
+        seeds = [b"creator_vault", question.creator.as_ref(), question.payment_mint.as_ref()],
+        bump
+    )]
+    pub creator_vault: Account<'info, CreatorVault>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        seeds = [b"creator_vault_tokens", creator_vault.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = listing_authority,
+        token::token_program = payment_token_program
+    )]
+    pub creator_vault_tokens: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    #[account(
+        seeds = [b"question_split", question.key().as_ref()],
+        bump
+    )]
+    pub question_split: Option<Account<'info, QuestionSplit>>,
+    /// CHECK: PDA that owns escrow and creator vault token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: Option<UncheckedAccount<'info>>,
+    #[account(
+        mut,
+        constraint = treasury_token_account.owner == marketplace.treasury,
+        token::mint = payment_mint
+    )]
+    pub treasury_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    /// CHECK: receives the platform fee when the question is priced in SOL
+    #[account(mut, address = marketplace.treasury)]
+    pub treasury: Option<UncheckedAccount<'info>>,
+    #[account(address = question.payment_mint @ ErrorCode::InvalidPaymentMint)]
+    pub payment_mint: Option<InterfaceAccount<'info, token_interface::Mint>>,
+    #[account(
+        seeds = [b"payment_mint", marketplace.key().as_ref(), question.payment_mint.as_ref()],
+        bump,
+        constraint = payment_mint_config.enabled @ ErrorCode::PaymentMintDisabled
+    )]
+    pub payment_mint_config: Option<Account<'info, PaymentMint>>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    #[account(mut)]
+    pub user_state: Account<'info, UserState>,
 }
 
 #[derive(Accounts)]
-#[instruction()]
-pub struct Initialize<'info> {
+pub struct TransferCompressedKey<'info> {
+    pub marketplace: Account<'info, Marketplace>,
     #[account(
-        init,
-        payer = authority,
-        space = 8 + // discriminator
-        32 + // authority: Pubkey
-        32 + // treasury: Pubkey
-        8 + // question_counter: u64
-        2 + // platform_fee_bps: u16
-        2 + // creator_royalty_bps: u16
-        8 + // total_volume: u64
-        1 + // paused: bool
-        4 + // paused_operations
-        32, // bonk_mint: Pubkey
-        seeds = [b"marketplace", authority.key().as_ref()],
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        mut,
+        seeds = [b"key_tree", question.key().as_ref()],
         bump
     )]
+    pub key_tree: Account<'info, KeyTree>,
+    pub owner: Signer<'info>,
+    /// CHECK: only recorded as the owner in the new leaf
+    pub new_owner: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyCompressedKey<'info> {
     pub marketplace: Account<'info, Marketplace>,
-    /// CHECK: Validated in instruction
-    pub bonk_mint: UncheckedAccount<'info>,
-    /// CHECK: Treasury account
-    pub treasury: UncheckedAccount<'info>,
-    #[account(mut)]
+    #[account(
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        seeds = [b"key_tree", question.key().as_ref()],
+        bump
+    )]
+    pub key_tree: Account<'info, KeyTree>,
+}
+
+#[derive(Accounts)]
+pub struct SetMaxKeysPerWallet<'info> {
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump,
+        has_one = creator @ ErrorCode::NotQuestionCreator
+    )]
+    pub question: Account<'info, Question>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRefundWindow<'info> {
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump,
+        has_one = creator @ ErrorCode::NotQuestionCreator
+    )]
+    pub question: Account<'info, Question>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetAllowlistRoot<'info> {
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump,
+        has_one = creator @ ErrorCode::NotQuestionCreator
+    )]
+    pub question: Account<'info, Question>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ModerateQuestion<'info> {
+    #[account(has_one = authority)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
     pub authority: Signer<'info>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct InitializeUserState<'info> {
+pub struct CloseQuestion<'info> {
+    pub marketplace: Account<'info, Marketplace>,
     #[account(
-        init,
-        payer = user,
-        space = 8 + 8 + 8 + 1,  // discriminator + questions_created + last_operation_time + is_blacklisted
-        seeds = [b"user_state", user.key().as_ref()],
+        mut,
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump,
+        has_one = creator @ ErrorCode::NotQuestionCreator,
+        close = creator
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        mut,
+        seeds = [b"question_split", question.key().as_ref()],
+        bump,
+        close = creator
+    )]
+    pub question_split: Option<Account<'info, QuestionSplit>>,
+    #[account(
+        mut,
+        seeds = [b"key_tree", question.key().as_ref()],
+        bump,
+        close = creator
+    )]
+    pub key_tree: Option<Account<'info, KeyTree>>,
+    #[account(
+        mut,
+        seeds = [b"user_state", creator.key().as_ref()],
         bump
     )]
     pub user_state: Account<'info, UserState>,
     #[account(mut)]
-    pub user: Signer<'info>,
-    pub system_program: Program<'info, System>,
+    pub creator: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct UpdateFees<'info> {
-    #[account(mut, has_one = authority)]
+pub struct VerifyKeyCreator<'info> {
     pub marketplace: Account<'info, Marketplace>,
-    #[account(mut)]
-    pub authority: Signer<'info>,
+    #[account(
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        seeds = [
+            b"unlock_key",
+            question.key().as_ref(),
+            &unlock_key.token_id.to_le_bytes()
+        ],
+        bump
+    )]
+    pub unlock_key: Account<'info, UnlockKey>,
+    /// CHECK: the key NFT's metadata, found by its seeds; Metaplex checks the creator
+    #[account(
+        mut,
+        seeds = [b"metadata", metadata_program.key().as_ref(), unlock_key.mint.as_ref()],
+        seeds::program = metadata_program.key(),
+        bump
+    )]
+    pub metadata: UncheckedAccount<'info>,
+    pub creator: Signer<'info>,
+    pub metadata_program: Program<'info, Metadata>,
 }
 
 #[derive(Accounts)]
-#[instruction(content_cid: String, content_hash: [u8; 32])]
-pub struct CreateQuestion<'info> {
+pub struct BurnUnlockKey<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"unlock_key",
+            unlock_key.question.as_ref(),
+            &unlock_key.token_id.to_le_bytes()
+        ],
+        bump,
+        close = holder
+    )]
+    pub unlock_key: Account<'info, UnlockKey>,
     #[account(mut)]
-    pub marketplace: Account<'info, Marketplace>,
+    pub holder: Signer<'info>,
+    #[account(
+        mut,
+        token::mint = unlock_key.mint,
+        token::authority = holder,
+        constraint = holder_key_account.amount == 1 @ ErrorCode::NotKeyOwner
+    )]
+    pub holder_key_account: Account<'info, TokenAccount>,
+    #[account(mut, address = unlock_key.mint)]
+    pub mint: Account<'info, Mint>,
+    /// CHECK: Metaplex will check this
     #[account(mut)]
-    pub user_state: Account<'info, UserState>,
+    pub metadata: UncheckedAccount<'info>,
+    /// CHECK: Metaplex will check this
+    #[account(mut)]
+    pub master_edition: UncheckedAccount<'info>,
+    /// CHECK: the question's collection metadata; Metaplex checks it against the key
+    #[account(mut)]
+    pub collection_metadata: Option<UncheckedAccount<'info>>,
+    pub token_program: Program<'info, Token>,
+    pub metadata_program: Program<'info, Metadata>,
+}
+
+#[derive(Accounts)]
+pub struct RequestRefund<'info> {
     #[account(
-        init,
-        payer = creator,
-        space = MIN_ACCOUNT_SPACE +
-        32 +                        // creator: Pubkey
-        4 + content_cid.len() +     // content_cid: String
-        32 +                        // content_hash: [u8; 32]
-        8 +                         // unlock_price: u64
-        8 +                         // max_keys: u64
-        8 +                         // current_keys: u64
-        8 +                         // index: u64
-        8 +                         // creation_time: i64
-        8 +                         // total_sales: u64
-        1 +                         // is_active: bool
-        8,                          // validation_timestamp: i64
+        mut,
         seeds = [
-            b"question",
-            marketplace.key().as_ref(),
-            &marketplace.question_counter.to_le_bytes()
+            b"unlock_key",
+            unlock_key.question.as_ref(),
+            &unlock_key.token_id.to_le_bytes()
         ],
+        bump,
+        close = buyer
+    )]
+    pub unlock_key: Account<'info, UnlockKey>,
+    #[account(
+        mut,
+        seeds = [b"refund_escrow", unlock_key.key().as_ref()],
+        bump,
+        has_one = buyer,
+        close = buyer
+    )]
+    pub refund_escrow: Account<'info, RefundEscrow>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(
+        mut,
+        token::mint = unlock_key.mint,
+        token::authority = buyer,
+        constraint = buyer_key_account.amount == 1 @ ErrorCode::NotKeyOwner
+    )]
+    pub buyer_key_account: Account<'info, TokenAccount>,
+    #[account(mut, address = unlock_key.mint)]
+    pub mint: Account<'info, Mint>,
+    /// CHECK: Metaplex will check this
+    #[account(mut)]
+    pub metadata: UncheckedAccount<'info>,
+    /// CHECK: Metaplex will check this
+    #[account(mut)]
+    pub master_edition: UncheckedAccount<'info>,
+    /// CHECK: the question's collection metadata; Metaplex checks it against the key
+    #[account(mut)]
+    pub collection_metadata: Option<UncheckedAccount<'info>>,
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = buyer
+    )]
+    pub buyer_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"refund_escrow_tokens", unlock_key.key().as_ref()],
+        bump,
+        token::mint = payment_mint
+    )]
+    pub refund_escrow_tokens: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    /// CHECK: PDA that owns listing, sale and refund escrow token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: Option<UncheckedAccount<'info>>,
+    #[account(mut)]
+    pub payment_mint: Option<InterfaceAccount<'info, token_interface::Mint>>,
+    pub token_program: Program<'info, Token>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub metadata_program: Program<'info, Metadata>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseEscrow<'info> {
+    #[account(
+        mut,
+        seeds = [b"refund_escrow", refund_escrow.unlock_key.as_ref()],
+        bump,
+        has_one = buyer,
+        close = buyer
+    )]
+    pub refund_escrow: Account<'info, RefundEscrow>,
+    /// CHECK: receives the escrow rent back
+    #[account(mut)]
+    pub buyer: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"creator_vault", refund_escrow.creator.as_ref(), refund_escrow.payment_mint.as_ref()],
         bump
     )]
-    pub question: Account<'info, Question>,
+    pub creator_vault: Account<'info, CreatorVault>,
+    #[account(
+        mut,
+        seeds = [b"creator_vault_tokens", creator_vault.key().as_ref()],
+        bump,
+        token::mint = payment_mint
+    )]
+    pub creator_vault_tokens: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"refund_escrow_tokens", refund_escrow.unlock_key.as_ref()],
+        bump,
+        token::mint = payment_mint
+    )]
+    pub refund_escrow_tokens: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    /// CHECK: PDA that owns listing, sale and refund escrow token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: Option<UncheckedAccount<'info>>,
     #[account(mut)]
-    pub creator: Signer<'info>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
+    pub payment_mint: Option<InterfaceAccount<'info, token_interface::Mint>>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-#[instruction(metadata_uri: String, encrypted_key: Vec<u8>)]
-pub struct MintUnlockKey<'info> {
-    #[account(mut)]
-    pub marketplace: Account<'info, Marketplace>,
-    #[account(mut)]
-    pub question: Account<'info, Question>,
+pub struct WithdrawEarnings<'info> {
     #[account(
         mut,
-        constraint = treasury_token_account.owner == marketplace.treasury,
-        token::mint = bonk_mint
-    )]
-    pub treasury_token_account: Account<'info, TokenAccount>,
-    #[account(
-        init,
-        payer = buyer,
-        space = MIN_ACCOUNT_SPACE +   // discriminator
-        1 +                          // discriminator: u8
-        32 +                         // owner: Pubkey
-        32 +                         // question: Pubkey
-        8 +                          // token_id: u64
-        4 + encrypted_key.len() +    // encrypted_key: Vec<u8>
-        1 +                          // is_listed: bool
-        8 +                          // list_price: u64
-        8 +                          // mint_time: i64
-        4 + metadata_uri.len() +     // metadata_uri: String
-        8 +                          // last_sold_price: u64
-        8 +                          // last_sold_time: i64
-        8,                          // list_time: i64
-        seeds = [
-            b"unlock_key",
-            question.key().as_ref(),
-            &question.current_keys.to_le_bytes()
-        ],
+        seeds = [b"creator_vault", creator.key().as_ref(), creator_vault.payment_mint.as_ref()],
         bump,
-        constraint = question.current_keys < question.max_keys @ ErrorCode::NoKeysAvailable
+        has_one = creator
     )]
-    pub unlock_key: Account<'info, UnlockKey>,
+    pub creator_vault: Account<'info, CreatorVault>,
     #[account(mut)]
-    pub buyer: Signer<'info>,
-    
+    pub creator: Signer<'info>,
     #[account(
         mut,
-        constraint = buyer_token_account.owner == buyer.key(),
-        token::mint = bonk_mint
+        seeds = [b"creator_vault_tokens", creator_vault.key().as_ref()],
+        bump,
+        token::mint = payment_mint
     )]
-    pub buyer_token_account: Account<'info, TokenAccount>,
-    
+    pub creator_vault_tokens: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
     #[account(
         mut,
-        constraint = creator_token_account.owner == question.creator,
-        token::mint = bonk_mint
+        token::mint = payment_mint,
+        token::authority = creator
     )]
-    pub creator_token_account: Account<'info, TokenAccount>,
-    
-    pub bonk_mint: Account<'info, Mint>,
-    
-    /// CHECK: Metaplex will check this
-    #[account(mut)]
-    pub metadata: UncheckedAccount<'info>,
-    
-    #[account(mut)]
-    pub mint: Account<'info, Mint>,
-    
-    /// CHECK: PDA for mint authority
+    pub creator_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    /// CHECK: PDA that owns the creator vault token accounts
     #[account(
-        seeds = [b"mint_authority"],
+        seeds = [b"listing_authority"],
         bump
     )]
-    pub mint_authority: UncheckedAccount<'info>,
-    
-    /// CHECK: PDA for update authority
-    pub update_authority: UncheckedAccount<'info>,
-    
-    pub token_program: Program<'info, Token>,
-    /// CHECK: Metaplex Token Metadata Program
-    pub metadata_program: UncheckedAccount<'info>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
-    #[account(mut)]
-    pub user_state: Account<'info, UserState>,
+    pub listing_authority: UncheckedAccount<'info>,
+    #[account(address = creator_vault.payment_mint @ ErrorCode::InvalidPaymentMint)]
+    pub payment_mint: Option<InterfaceAccount<'info, token_interface::Mint>>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct ListKey<'info> {
-    #[account(mut)]
-    pub marketplace: Account<'info, Marketplace>,
-    #[account(mut, constraint = unlock_key.owner == seller.key() @ ErrorCode::NotKeyOwner)]
-    pub unlock_key: Account<'info, UnlockKey>,
-    #[account(mut)]
-    pub seller: Signer<'info>,
+#[instruction(payment_mint: Pubkey)]
+pub struct OpenCreatorVault<'info> {
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = CREATOR_VAULT_SIZE,
+        seeds = [b"creator_vault", creator.key().as_ref(), payment_mint.as_ref()],
+        bump
+    )]
+    pub creator_vault: Account<'info, CreatorVault>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        seeds = [b"creator_vault_tokens", creator_vault.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = listing_authority,
+        token::token_program = payment_token_program
+    )]
+    pub creator_vault_tokens: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    /// CHECK: the wallet the vault belongs to
+    pub creator: UncheckedAccount<'info>,
     #[account(mut)]
-    pub user_state: Account<'info, UserState>,
+    pub payer: Signer<'info>,
+    /// CHECK: PDA that owns the creator vault token accounts
+    #[account(
+        seeds = [b"listing_authority"],
+        bump
+    )]
+    pub listing_authority: UncheckedAccount<'info>,
+    #[account(address = payment_mint @ ErrorCode::InvalidPaymentMint)]
+    pub mint: Option<InterfaceAccount<'info, token_interface::Mint>>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct UpdateListing<'info> {
-    #[account(mut)]
-    pub marketplace: Account<'info, Marketplace>,
-    #[account(mut, constraint = unlock_key.owner == seller.key() @ ErrorCode::NotKeyOwner)]
-    pub unlock_key: Account<'info, UnlockKey>,
+pub struct CloseUserState<'info> {
+    #[account(
+        mut,
+        seeds = [b"user_state", user.key().as_ref()],
+        bump,
+        close = user
+    )]
+    pub user_state: Account<'info, UserState>,
     #[account(mut)]
-    pub seller: Signer<'info>,
+    pub user: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct CancelListing<'info> {
-    #[account(mut)]
+pub struct SyncKeyOwner<'info> {
     pub marketplace: Account<'info, Marketplace>,
-    #[account(mut, constraint = unlock_key.owner == seller.key() @ ErrorCode::NotKeyOwner)]
+    #[account(
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub question: Account<'info, Question>,
+    #[account(
+        mut,
+        seeds = [
+            b"unlock_key",
+            question.key().as_ref(),
+            &unlock_key.token_id.to_le_bytes()
+        ],
+        bump
+    )]
     pub unlock_key: Account<'info, UnlockKey>,
-    #[account(mut)]
-    pub seller: Signer<'info>,
+    pub holder: Signer<'info>,
+    #[account(
+        token::mint = unlock_key.mint,
+        token::authority = holder,
+        constraint = holder_key_account.amount == 1 @ ErrorCode::NotKeyOwner
+    )]
+    pub holder_key_account: Account<'info, TokenAccount>,
 }
 
 #[derive(Accounts)]
 #[instruction(new_encrypted_key: Vec<u8>)]
-pub struct BuyListedKey<'info> {
-    #[account(mut)]
+pub struct ReencryptKey<'info> {
     pub marketplace: Account<'info, Marketplace>,
     #[account(
-        mut,
-        constraint = !marketplace.paused @ ErrorCode::MarketplacePaused,
-        constraint = !marketplace.paused_operations.buy_key @ ErrorCode::OperationPaused
+        seeds = [
+            b"question",
+            marketplace.key().as_ref(),
+            &question.index.to_le_bytes()
+        ],
+        bump
     )]
     pub question: Account<'info, Question>,
-    #[account(
-        mut,
-        constraint = treasury_token_account.owner == marketplace.treasury,
-        token::mint = bonk_mint
-    )]
-    pub treasury_token_account: Account<'info, TokenAccount>,
     #[account(
         mut,
         seeds = [
@@ -882,45 +5702,16 @@ pub struct BuyListedKey<'info> {
             &unlock_key.token_id.to_le_bytes()
         ],
         bump,
-        constraint = unlock_key.is_listed @ ErrorCode::NotListed,
-        constraint = unlock_key.owner != buyer.key() @ ErrorCode::CannotBuyOwnKey,
-        constraint = unlock_key.owner == seller_token_account.owner @ ErrorCode::InvalidOwner,
-        realloc = UNLOCK_KEY_BASE_SIZE + 
+        realloc = UNLOCK_KEY_BASE_SIZE +
             4 + new_encrypted_key.len() +  // encrypted_key: Vec<u8>
             4 + unlock_key.metadata_uri.len(),  // metadata_uri: String
-        realloc::payer = buyer,
+        realloc::payer = signer,
         realloc::zero = false
     )]
     pub unlock_key: Account<'info, UnlockKey>,
     #[account(mut)]
-    pub buyer: Signer<'info>,
-    
-    #[account(
-        mut,
-        constraint = buyer_token_account.owner == buyer.key(),
-        token::mint = bonk_mint
-    )]
-    pub buyer_token_account: Account<'info, TokenAccount>,
-    
-    #[account(
-        mut,
-        constraint = seller_token_account.owner == unlock_key.owner,
-        token::mint = bonk_mint
-    )]
-    pub seller_token_account: Account<'info, TokenAccount>,
-    
-    #[account(
-        mut,
-        constraint = creator_token_account.owner == question.creator,
-        token::mint = bonk_mint
-    )]
-    pub creator_token_account: Account<'info, TokenAccount>,
-    
-    pub bonk_mint: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
+    pub signer: Signer<'info>,
     pub system_program: Program<'info, System>,
-    #[account(mut)]
-    pub user_state: Account<'info, UserState>,
 }
 
 #[derive(Accounts)]
@@ -931,59 +5722,286 @@ pub struct ToggleMarketplace<'info> {
 }
 
 #[account]
-pub struct Marketplace {
-    pub treasury: Pubkey,
-    pub authority: Pubkey,
-    pub question_counter: u64,
-    pub platform_fee_bps: u16,
-    pub creator_royalty_bps: u16,
-    pub total_volume: u64,
-    pub paused: bool,
-    pub paused_operations: PausedOperations,
-    pub bonk_mint: Pubkey,
+pub struct Marketplace {
+    pub treasury: Pubkey,
+    pub authority: Pubkey,
+    pub question_counter: u64,
+    pub platform_fee_bps: u16,
+    pub creator_royalty_bps: u16, // suggested default; sales use each question's royalty_bps
+    pub total_volume: u64,
+    pub paused: bool,
+    pub paused_operations: PausedOperations,
+    pub bonk_mint: Pubkey, // default payment mint
+    pub key_custodian: Pubkey,
+}
+
+// Token accepted as payment on a marketplace
+#[account]
+pub struct PaymentMint {
+    pub marketplace: Pubkey,
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub enabled: bool,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct PausedOperations {
+    pub create_question: bool,
+    pub mint_key: bool,
+    pub list_key: bool,
+    pub buy_key: bool,
+    pub update_question: bool,
+}
+
+impl PausedOperations {
+    pub fn toggle(&mut self, operation: OperationType) {
+        match operation {
+            OperationType::CreateQuestion => self.create_question = !self.create_question,
+            OperationType::MintKey => self.mint_key = !self.mint_key,
+            OperationType::ListKey => self.list_key = !self.list_key,
+            OperationType::BuyKey => self.buy_key = !self.buy_key,
+            OperationType::UpdateQuestion => self.update_question = !self.update_question,
+        }
+    }
+
+    pub fn is_paused(&self, operation: OperationType) -> bool {
+        match operation {
+            OperationType::CreateQuestion => self.create_question,
+            OperationType::MintKey => self.mint_key,
+            OperationType::ListKey => self.list_key,
+            OperationType::BuyKey => self.buy_key,
+            OperationType::UpdateQuestion => self.update_question,
+        }
+    }
+}
+
+#[account]
+pub struct Question {
+    pub creator: Pubkey,
+    pub content_cid: String,      // IPFS CID containing question and encrypted answer
+    pub content_hash: [u8; 32],   // Hash of the complete IPFS content
+    pub content_version: u64,     // Bumped on every content revision
+    pub unlock_price: u64,
+    pub max_keys: u64,
+    pub current_keys: u64,
+    pub index: u64,
+    pub creation_time: i64,
+    pub total_sales: u64,
+    pub is_active: bool,
+    pub is_taken_down: bool,
+    pub validation_timestamp: i64,
+    pub pricing_curve: PricingCurve, // applied on top of unlock_price
+    pub launch_schedule: Option<LaunchSchedule>,
+    pub allowlist_root: Option<[u8; 32]>, // Merkle root over (buyer, price, max_quantity)
+    pub payment_mint: Pubkey,             // token every sale of this question settles in
+    pub payment_type: PaymentType,
+    pub royalty_bps: u16,                 // creator cut of secondary sales
+    pub collection_mint: Option<Pubkey>,  // Metaplex collection every key is verified into
+    pub key_mode: KeyMode,
+    pub max_keys_per_wallet: Option<u64>, // None lets a wallet buy any number of keys
+    pub refund_window: Option<i64>,       // seconds after a mint the buyer may request a refund
+    pub has_split: bool,                  // creator earnings are shared through a QuestionSplit
+    pub open_escrows: u64,                // keys held in listing escrow by a listing, pending sale or auction
+}
+
+impl Question {
+    pub fn next_unlock_price(&self, now: i64) -> Result<u64> {
+        self.unlock_price_at(self.current_keys, now)
+    }
+
+    // Price of the key minted once `keys_sold` keys have been sold
+    pub fn unlock_price_at(&self, keys_sold: u64, now: i64) -> Result<u64> {
+        let curve_price = self.pricing_curve.price_at(self.unlock_price, keys_sold)?;
+        match &self.launch_schedule {
+            Some(schedule) => schedule.price_at(curve_price, now),
+            None => Ok(curve_price),
+        }
+    }
+}
+
+// Content a question carried before a revision replaced it
+#[account]
+pub struct QuestionRevision {
+    pub question: Pubkey,
+    pub version: u64,
+    pub content_cid: String,
+    pub content_hash: [u8; 32],
+    pub replaced_at: i64,
+}
+
+#[account]
+pub struct Auction {
+    pub seller: Pubkey,
+    pub unlock_key: Pubkey,
+    pub question: Pubkey,
+    pub auction_type: AuctionType,
+    pub start_price: u64, // opening bid for English auctions
+    pub reserve_price: u64, // lowest winning bid settle_auction accepts
+    pub end_price: u64,
+    pub min_bid_increment: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub highest_bidder: Pubkey,
+    pub highest_bid: u64,
+}
+
+// Keys a wallet has minted through a question's allowlist
+#[account]
+pub struct AllowlistClaim {
+    pub question: Pubkey,
+    pub buyer: Pubkey,
+    pub claimed: u64,
+}
+
+// Primary-sale keys a wallet has bought of a question, checked against max_keys_per_wallet
+#[account]
+pub struct PurchaseRecord {
+    pub question: Pubkey,
+    pub buyer: Pubkey,
+    pub purchased: u64,
+}
+
+impl PurchaseRecord {
+    pub fn record(
+        &mut self,
+        question: &Account<Question>,
+        buyer: Pubkey,
+        quantity: u64,
+    ) -> Result<()> {
+        let purchased = self.purchased
+            .checked_add(quantity)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+        if let Some(limit) = question.max_keys_per_wallet {
+            require!(purchased <= limit, ErrorCode::PurchaseLimitReached);
+        }
+        self.question = question.key();
+        self.buyer = buyer;
+        self.purchased = purchased;
+        Ok(())
+    }
+}
+
+// Earnings of a creator in one payment mint. SOL earnings are held as lamports on
+// this account, token earnings in the creator_vault_tokens account beside it.
+#[account]
+pub struct CreatorVault {
+    pub creator: Pubkey,
+    pub payment_mint: Pubkey,             // Pubkey::default() for SOL
+    pub total_earned: u64,
+    pub total_withdrawn: u64,
+}
+
+impl CreatorVault {
+    pub fn credit(&mut self, question: &Question, amount: u64) -> Result<()> {
+        self.creator = question.creator;
+        self.payment_mint = question.payment_mint;
+        self.record_earnings(amount)
+    }
+
+    pub fn record_earnings(&mut self, amount: u64) -> Result<()> {
+        self.total_earned = self.total_earned
+            .checked_add(amount)
+            .ok_or(ErrorCode::NumericalOverflow)?;
+        Ok(())
+    }
+
+    pub fn available(&self) -> u64 {
+        self.total_earned.saturating_sub(self.total_withdrawn)
+    }
+}
+
+// Co-authors sharing a question's creator share and royalties
+#[account]
+pub struct QuestionSplit {
+    pub question: Pubkey,
+    pub payees: Vec<SplitPayee>,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
-pub struct PausedOperations {
-    pub create_question: bool,
-    pub mint_key: bool,
-    pub list_key: bool,
-    pub buy_key: bool,
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Copy, Debug)]
+pub struct SplitPayee {
+    pub payee: Pubkey,
+    pub share_bps: u16,
 }
 
-impl PausedOperations {
-    pub fn toggle(&mut self, operation: OperationType) {
-        match operation {
-            OperationType::CreateQuestion => self.create_question = !self.create_question,
-            OperationType::MintKey => self.mint_key = !self.mint_key,
-            OperationType::ListKey => self.list_key = !self.list_key,
-            OperationType::BuyKey => self.buy_key = !self.buy_key,
+impl QuestionSplit {
+    pub fn validate(payees: &[SplitPayee]) -> Result<()> {
+        require!(
+            !payees.is_empty() && payees.len() <= MAX_SPLIT_PAYEES,
+            ErrorCode::InvalidSplit
+        );
+        require!(
+            payees.iter().all(|payee| payee.share_bps > 0),
+            ErrorCode::InvalidSplit
+        );
+        let total_bps = payees.iter().map(|payee| payee.share_bps as u64).sum::<u64>();
+        require!(total_bps == 10000, ErrorCode::InvalidSplit);
+        for (i, payee) in payees.iter().enumerate() {
+            require!(
+                payees[..i].iter().all(|other| other.payee != payee.payee),
+                ErrorCode::InvalidSplit
+            );
         }
+        Ok(())
     }
 
-    pub fn is_paused(&self, operation: OperationType) -> bool {
-        match operation {
-            OperationType::CreateQuestion => self.create_question,
-            OperationType::MintKey => self.mint_key,
-            OperationType::ListKey => self.list_key,
-            OperationType::BuyKey => self.buy_key,
+    // Each payee's portion of `amount`, rounded down; the rounding dust goes
+    // to the first payee so the portions always add up to `amount`
+    pub fn portions(payees: &[SplitPayee], amount: u64) -> Result<Vec<u64>> {
+        let mut portions = payees
+            .iter()
+            .map(|payee| {
+                (amount as u128 * payee.share_bps as u128 / 10000) as u64
+            })
+            .collect::<Vec<u64>>();
+        let dust = amount
+            .checked_sub(portions.iter().sum())
+            .ok_or(ErrorCode::NumericalOverflow)?;
+        if let Some(first) = portions.first_mut() {
+            *first = first.checked_add(dust).ok_or(ErrorCode::NumericalOverflow)?;
         }
+        Ok(portions)
     }
 }
 
+// Creator share of a primary sale, held until the buyer's refund window closes.
+// Carries everything release_escrow pays out with, so it never needs the question.
 #[account]
-pub struct Question {
+pub struct RefundEscrow {
+    pub unlock_key: Pubkey,
+    pub question: Pubkey,
+    pub buyer: Pubkey,
     pub creator: Pubkey,
-    pub content_cid: String,      // IPFS CID containing question and encrypted answer
-    pub content_hash: [u8; 32],   // Hash of the complete IPFS content
-    pub unlock_price: u64,
-    pub max_keys: u64,
-    pub current_keys: u64,
-    pub index: u64,
-    pub creation_time: i64,
-    pub total_sales: u64,
-    pub is_active: bool,
-    pub validation_timestamp: i64,
+    pub amount: u64,
+    pub platform_fee: u64,                // kept by the treasury, refund or not
+    pub payment_type: PaymentType,        // SOL escrows hold the amount on this account
+    pub payment_mint: Pubkey,
+    pub payees: Vec<SplitPayee>,          // the question's split when the key was bought
+    pub refundable_until: i64,
+}
+
+// Escrowed bid on a single key, or on any key of a question when unlock_key is None
+#[account]
+pub struct Offer {
+    pub bidder: Pubkey,
+    pub question: Pubkey,
+    pub unlock_key: Option<Pubkey>,
+    pub price: u64,
+    pub created_at: i64,
+}
+
+// Buyer payment held in escrow until the key is re-encrypted for them
+#[account]
+pub struct PendingSale {
+    pub unlock_key: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub price: u64,
+    pub platform_fee: u64,
+    pub creator_royalty: u64,
+    pub seller_payment: u64,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub payment_type: PaymentType,        // where the escrowed price is held
 }
 
 #[account]
@@ -992,6 +6010,7 @@ pub struct UnlockKey {
     pub owner: Pubkey,
     pub question: Pubkey,
     pub token_id: u64,
+    pub mint: Pubkey,
     pub encrypted_key: Vec<u8>,
     pub is_listed: bool,
     pub list_price: u64,
@@ -1000,6 +6019,171 @@ pub struct UnlockKey {
     pub last_sold_price: u64,
     pub last_sold_time: i64,
     pub list_time: i64,
+    pub reencryption_pending: bool,  // new holder is waiting on reencrypt_key
+}
+
+// Concurrent Merkle tree holding a question's compressed keys. Appends need no
+// proof, and proofs against any of the last KEY_TREE_BUFFER_SIZE roots are
+// fast-forwarded through the changelog, so concurrent transfers don't collide.
+#[account]
+pub struct KeyTree {
+    pub question: Pubkey,
+    pub max_depth: u8,
+    pub num_leaves: u64,
+    pub sequence: u64,                   // bumped on every append or replace
+    pub root: [u8; 32],
+    pub filled_subtrees: Vec<[u8; 32]>,  // left-hand nodes the next append hashes against
+    pub changelog: Vec<KeyTreeChange>,   // ring buffer, change `sequence` at (sequence - 1) % size
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct KeyTreeChange {
+    pub root: [u8; 32],
+    pub path: Vec<[u8; 32]>, // nodes from the changed leaf up to just below the root
+    pub index: u32,
+}
+
+impl KeyTree {
+    pub fn space(max_depth: u8) -> usize {
+        let depth = max_depth as usize;
+        MIN_ACCOUNT_SPACE +
+        32 + // question: Pubkey
+        1 +  // max_depth: u8
+        8 +  // num_leaves: u64
+        8 +  // sequence: u64
+        32 + // root: [u8; 32]
+        4 + depth * 32 + // filled_subtrees: Vec<[u8; 32]>
+        4 + KEY_TREE_BUFFER_SIZE * (32 + 4 + depth * 32 + 4) // changelog: Vec<KeyTreeChange>
+    }
+
+    pub fn initialize(&mut self, question: Pubkey, max_depth: u8) {
+        let mut empty_nodes = empty_key_tree_nodes(max_depth);
+        self.question = question;
+        self.max_depth = max_depth;
+        self.num_leaves = 0;
+        self.sequence = 0;
+        self.root = empty_nodes.pop().unwrap_or_default();
+        self.filled_subtrees = empty_nodes;
+        self.changelog = Vec::with_capacity(KEY_TREE_BUFFER_SIZE);
+    }
+
+    // Adds a leaf at the next free index and returns that index
+    pub fn append(&mut self, leaf: [u8; 32]) -> Result<u32> {
+        require!(
+            self.num_leaves < 1u64 << self.max_depth,
+            ErrorCode::KeyTreeFull
+        );
+        let index = self.num_leaves as u32;
+        let empty_nodes = empty_key_tree_nodes(self.max_depth);
+
+        let mut path = Vec::with_capacity(self.max_depth as usize);
+        let mut node = leaf;
+        let levels = self.filled_subtrees.iter_mut().zip(&empty_nodes);
+        for (level, (filled, empty)) in levels.enumerate() {
+            path.push(node);
+            node = if (index >> level) & 1 == 0 {
+                *filled = node;
+                hash_key_tree_pair(&node, empty)
+            } else {
+                hash_key_tree_pair(filled, &node)
+            };
+        }
+
+        self.num_leaves += 1;
+        self.record_change(node, path, index);
+        Ok(index)
+    }
+
+    // Swaps the leaf at `index` after proving `old_leaf` against a recent root
+    pub fn replace_leaf(
+        &mut self,
+        root: [u8; 32],
+        old_leaf: [u8; 32],
+        new_leaf: [u8; 32],
+        proof: &[[u8; 32]],
+        index: u32,
+    ) -> Result<()> {
+        let proof = self.fast_forward_proof(root, proof, index)?;
+        require!(
+            key_tree_path(old_leaf, &proof, index).0 == self.root,
+            ErrorCode::InvalidKeyProof
+        );
+
+        let (new_root, path) = key_tree_path(new_leaf, &proof, index);
+        // keep the left-hand nodes future appends hash against current
+        for (level, node) in path.iter().enumerate() {
+            let next = self.num_leaves >> level;
+            if next & 1 == 1 && (index as u64 >> level) == next - 1 {
+                self.filled_subtrees[level] = *node;
+            }
+        }
+        self.record_change(new_root, path, index);
+        Ok(())
+    }
+
+    pub fn verify_leaf(
+        &self,
+        root: [u8; 32],
+        leaf: [u8; 32],
+        proof: &[[u8; 32]],
+        index: u32,
+    ) -> Result<()> {
+        let proof = self.fast_forward_proof(root, proof, index)?;
+        require!(
+            key_tree_path(leaf, &proof, index).0 == self.root,
+            ErrorCode::InvalidKeyProof
+        );
+        Ok(())
+    }
+
+    // Rewrites a proof taken against `root` so it holds against the current root.
+    // Each later change alters exactly one sibling: the one at the level where its
+    // path meets ours.
+    fn fast_forward_proof(
+        &self,
+        root: [u8; 32],
+        proof: &[[u8; 32]],
+        index: u32,
+    ) -> Result<Vec<[u8; 32]>> {
+        require!(
+            proof.len() == self.max_depth as usize && (index as u64) < self.num_leaves,
+            ErrorCode::InvalidKeyProof
+        );
+        let mut proof = proof.to_vec();
+        if root == self.root {
+            return Ok(proof);
+        }
+
+        let oldest = self.sequence + 1 - self.changelog.len() as u64;
+        let since = (oldest..self.sequence)
+            .rev()
+            .find(|sequence| self.change_at(*sequence).root == root)
+            .ok_or(ErrorCode::InvalidKeyProof)?;
+        for sequence in since + 1..=self.sequence {
+            let change = self.change_at(sequence);
+            // the leaf itself changed, so the caller's view of it is stale
+            require!(change.index != index, ErrorCode::InvalidKeyProof);
+            let level = (31 - (index ^ change.index).leading_zeros()) as usize;
+            proof[level] = change.path[level];
+        }
+        Ok(proof)
+    }
+
+    fn change_at(&self, sequence: u64) -> &KeyTreeChange {
+        &self.changelog[((sequence - 1) % KEY_TREE_BUFFER_SIZE as u64) as usize]
+    }
+
+    fn record_change(&mut self, root: [u8; 32], path: Vec<[u8; 32]>, index: u32) {
+        self.sequence += 1;
+        self.root = root;
+        let change = KeyTreeChange { root, path, index };
+        if self.changelog.len() < KEY_TREE_BUFFER_SIZE {
+            self.changelog.push(change);
+        } else {
+            let slot = ((self.sequence - 1) % KEY_TREE_BUFFER_SIZE as u64) as usize;
+            self.changelog[slot] = change;
+        }
+    }
 }
 
 #[event]
@@ -1036,6 +6220,189 @@ pub enum OperationType {
     MintKey,
     ListKey,
     BuyKey,
+    UpdateQuestion,
+}
+
+// Primary sale pricing keyed on the number of keys already sold
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Copy, Debug)]
+pub enum PricingCurve {
+    Flat,
+    // base + increment * keys_sold
+    Linear { increment: u64 },
+    // base * (1 + growth_bps / 10000) ^ keys_sold
+    Exponential { growth_bps: u16 },
+    // base + step_increment for every keys_per_step keys sold
+    Step { keys_per_step: u64, step_increment: u64 },
+}
+
+impl PricingCurve {
+    pub fn price_at(&self, base_price: u64, keys_sold: u64) -> Result<u64> {
+        match *self {
+            PricingCurve::Flat => Ok(base_price),
+            PricingCurve::Linear { increment } => increment
+                .checked_mul(keys_sold)
+                .and_then(|delta| base_price.checked_add(delta))
+                .ok_or_else(|| error!(ErrorCode::NumericalOverflow)),
+            PricingCurve::Exponential { growth_bps } => {
+                // square-and-multiply in fixed point; an overflowing intermediate
+                // means the final price cannot fit in a u64 either
+                let limit = (u64::MAX as u128)
+                    .checked_mul(CURVE_SCALE)
+                    .ok_or(ErrorCode::NumericalOverflow)?;
+                let mut factor = CURVE_SCALE * (10000 + growth_bps as u128) / 10000;
+                let mut multiplier = CURVE_SCALE;
+                let mut exponent = keys_sold;
+                while exponent > 0 {
+                    if exponent & 1 == 1 {
+                        multiplier = multiplier
+                            .checked_mul(factor)
+                            .map(|m| m / CURVE_SCALE)
+                            .filter(|m| *m <= limit)
+                            .ok_or(ErrorCode::NumericalOverflow)?;
+                    }
+                    exponent >>= 1;
+                    if exponent > 0 {
+                        factor = factor
+                            .checked_mul(factor)
+                            .map(|f| f / CURVE_SCALE)
+                            .filter(|f| *f <= limit)
+                            .ok_or(ErrorCode::NumericalOverflow)?;
+                    }
+                }
+                let price = (base_price as u128)
+                    .checked_mul(multiplier)
+                    .ok_or(ErrorCode::NumericalOverflow)?
+                    / CURVE_SCALE;
+                u64::try_from(price).map_err(|_| error!(ErrorCode::NumericalOverflow))
+            }
+            PricingCurve::Step { keys_per_step, step_increment } => {
+                let steps = keys_sold
+                    .checked_div(keys_per_step)
+                    .ok_or(ErrorCode::InvalidPricingCurve)?;
+                step_increment
+                    .checked_mul(steps)
+                    .and_then(|delta| base_price.checked_add(delta))
+                    .ok_or_else(|| error!(ErrorCode::NumericalOverflow))
+            }
+        }
+    }
+
+    // Rejects malformed parameters and curves whose last key would overflow
+    pub fn validate(&self, base_price: u64, max_keys: u64) -> Result<()> {
+        match *self {
+            PricingCurve::Flat => {}
+            PricingCurve::Linear { increment } => {
+                require!(increment > 0, ErrorCode::InvalidPricingCurve);
+            }
+            PricingCurve::Exponential { growth_bps } => {
+                require!(
+                    growth_bps > 0 && growth_bps <= MAX_CURVE_GROWTH_BPS,
+                    ErrorCode::InvalidPricingCurve
+                );
+            }
+            PricingCurve::Step { keys_per_step, step_increment } => {
+                require!(
+                    keys_per_step > 0 && step_increment > 0,
+                    ErrorCode::InvalidPricingCurve
+                );
+            }
+        }
+        self.price_at(base_price, max_keys.saturating_sub(1))?;
+        Ok(())
+    }
+}
+
+// Optional launch timing for a question's primary sale
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Copy, Debug)]
+pub struct LaunchSchedule {
+    pub start_time: i64,       // mints are rejected before this
+    pub early_bird_price: u64, // 0 disables the early-bird window
+    pub early_bird_end: i64,
+    pub decay_bps_per_day: u16, // 0 disables decay
+    pub floor_price: u64,
+}
+
+impl LaunchSchedule {
+    pub fn require_started(&self, now: i64) -> Result<()> {
+        require!(now >= self.start_time, ErrorCode::SaleNotStarted);
+        Ok(())
+    }
+
+    pub fn price_at(&self, price: u64, now: i64) -> Result<u64> {
+        self.require_started(now)?;
+
+        if self.early_bird_price > 0 && now < self.early_bird_end {
+            return Ok(self.early_bird_price);
+        }
+        if self.decay_bps_per_day == 0 || price <= self.floor_price {
+            return Ok(price);
+        }
+
+        // decay linearly from launch, or from the end of the early-bird window
+        let decay_start = if self.early_bird_price > 0 {
+            self.early_bird_end
+        } else {
+            self.start_time
+        };
+        let elapsed = now.saturating_sub(decay_start).max(0) as u128;
+        let decay = (price as u128)
+            .checked_mul(self.decay_bps_per_day as u128)
+            .and_then(|d| d.checked_mul(elapsed))
+            .ok_or(ErrorCode::NumericalOverflow)?
+            / (10000 * SECONDS_PER_DAY as u128);
+        let decayed = (price as u128).saturating_sub(decay) as u64;
+        Ok(decayed.max(self.floor_price))
+    }
+
+    // The floor can't sit above the question's base price: curves only ever
+    // raise the price, so decay always has room to run down to the floor
+    pub fn validate(&self, unlock_price: u64) -> Result<()> {
+        require!(self.start_time >= 0, ErrorCode::InvalidLaunchSchedule);
+        if self.early_bird_price > 0 {
+            require!(
+                self.early_bird_end > self.start_time,
+                ErrorCode::InvalidLaunchSchedule
+            );
+        }
+        if self.decay_bps_per_day > 0 {
+            require!(
+                self.decay_bps_per_day <= 10000
+                    && self.floor_price > 0
+                    && self.floor_price <= unlock_price,
+                ErrorCode::InvalidLaunchSchedule
+            );
+        }
+        Ok(())
+    }
+}
+
+// How buyers pay for a question's keys
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Copy, Debug)]
+pub enum PaymentType {
+    Token, // SPL token transfers in the question's payment mint
+    Sol,   // native lamports via the system program
+}
+
+// How a question's keys are stored
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Copy, Debug)]
+pub enum KeyMode {
+    Standard,   // one UnlockKey account plus a 1/1 NFT per key
+    Compressed, // leaves of the question's KeyTree
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Copy)]
+pub enum AuctionType {
+    English,
+    Dutch,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Copy)]
+pub enum QuestionStatusReason {
+    CreatorRequest,
+    Abuse,
+    Copyright,
+    Spam,
+    Other,
 }
 
 #[error_code]
@@ -1100,75 +6467,411 @@ pub enum ErrorCode {
     InvalidOwner,
     #[msg("Invalid BONK token mint address")]
     InvalidBonkMint,
+    #[msg("Stored key owner does not match the NFT holder; call sync_key_owner")]
+    KeyOwnerOutOfSync,
+    #[msg("Key owner already matches the NFT holder")]
+    KeyOwnerInSync,
+    #[msg("Not the question creator")]
+    NotQuestionCreator,
+    #[msg("Question was taken down by moderation")]
+    QuestionTakenDown,
+    #[msg("Question has minted keys and has not been retired")]
+    QuestionHasKeys,
+    #[msg("User still has open questions")]
+    UserHasQuestions,
+    #[msg("Only the seller or key custodian can fulfill this sale")]
+    NotKeyCustodian,
+    #[msg("Sale fulfillment window has expired")]
+    SaleExpired,
+    #[msg("Sale fulfillment window has not expired yet")]
+    SaleNotExpired,
+    #[msg("Offer does not match this key or question")]
+    InvalidOfferTarget,
+    #[msg("Invalid auction parameters")]
+    InvalidAuctionParameters,
+    #[msg("Auction has ended")]
+    AuctionEnded,
+    #[msg("Auction has not ended yet")]
+    AuctionNotEnded,
+    #[msg("Bid is below the minimum accepted amount")]
+    BidTooLow,
+    #[msg("Token account of the current highest bidder is required")]
+    MissingPreviousBidder,
+    #[msg("Auction has no winning bid")]
+    NoWinningBid,
+    #[msg("Invalid pricing curve parameters")]
+    InvalidPricingCurve,
+    #[msg("Question is not on sale yet")]
+    SaleNotStarted,
+    #[msg("Invalid launch schedule")]
+    InvalidLaunchSchedule,
+    #[msg("Question has no allowlist")]
+    AllowlistNotSet,
+    #[msg("Invalid allowlist proof")]
+    InvalidAllowlistProof,
+    #[msg("Allowlist allocation already claimed")]
+    AllowlistClaimExceeded,
+    #[msg("Payment mint does not match the question")]
+    InvalidPaymentMint,
+    #[msg("Payment mint is disabled")]
+    PaymentMintDisabled,
+    #[msg("Payment accounts for this question's payment type are missing")]
+    MissingPaymentAccount,
+    #[msg("Operation not supported for this question's payment type")]
+    UnsupportedPaymentType,
+    #[msg("Price exceeds the buyer's maximum")]
+    PriceExceedsMaximum,
+    #[msg("Key is not the one the buyer expected")]
+    UnexpectedTokenId,
+    #[msg("Question already has a collection")]
+    CollectionAlreadyExists,
+    #[msg("Collection accounts do not match the question")]
+    InvalidCollection,
+    #[msg("Operation not supported for this question's key mode")]
+    UnsupportedKeyMode,
+    #[msg("Question already has minted keys")]
+    KeysAlreadyMinted,
+    #[msg("Key tree depth cannot hold the question's keys")]
+    InvalidTreeDepth,
+    #[msg("Key tree is full")]
+    KeyTreeFull,
+    #[msg("Invalid compressed key proof")]
+    InvalidKeyProof,
+    #[msg("Batch quantity must be between 1 and the per-purchase cap")]
+    InvalidBatchSize,
+    #[msg("Remaining accounts do not match the batch")]
+    InvalidBatchAccounts,
+    #[msg("Wallet has reached the question's purchase limit")]
+    PurchaseLimitReached,
+    #[msg("Invalid refund window")]
+    InvalidRefundWindow,
+    #[msg("Refund escrow accounts do not match the question's refund window")]
+    RefundEscrowMismatch,
+    #[msg("Refund window has closed")]
+    RefundWindowClosed,
+    #[msg("Refund window is still open")]
+    RefundWindowOpen,
+    #[msg("Withdrawal must be between 1 and the vault's available earnings")]
+    InvalidWithdrawalAmount,
+    #[msg("Split payees must be unique with shares summing to 10000 bps")]
+    InvalidSplit,
+    #[msg("Split payee vault accounts are missing or invalid")]
+    InvalidSplitAccounts,
+    #[msg("Highest bid is below the auction's reserve price")]
+    ReserveNotMet,
+    #[msg("Only the key custodian or question creator can re-encrypt keys")]
+    NotReencryptionAuthority,
+    #[msg("Key is not waiting to be re-encrypted")]
+    ReencryptionNotPending,
+    #[msg("Question has keys listed, on sale or at auction")]
+    QuestionHasOpenEscrows,
+    #[msg("Question split and key tree accounts must close with the question")]
+    QuestionAccountsOpen,
+    #[msg("Revision does not belong to this question")]
+    InvalidRevision,
+}
+
+#[derive(Accounts)]
+pub struct ToggleOperation<'info> {
+    #[account(mut, has_one = authority)]
+    pub marketplace: Account<'info, Marketplace>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct BlacklistUser<'info> {
+    #[account(mut, has_one = authority)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(mut)]
+    pub user_state: Account<'info, UserState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateTreasury<'info> {
+    #[account(mut, has_one = authority)]
+    pub marketplace: Account<'info, Marketplace>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AddPaymentMint<'info> {
+    #[account(has_one = authority)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        init,
+        payer = authority,
+        space = MIN_ACCOUNT_SPACE +
+        32 + // marketplace: Pubkey
+        32 + // mint: Pubkey
+        1 +  // decimals: u8
+        1,   // enabled: bool
+        seeds = [b"payment_mint", marketplace.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub payment_mint_config: Account<'info, PaymentMint>,
+    pub mint: InterfaceAccount<'info, token_interface::Mint>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaymentMintEnabled<'info> {
+    #[account(has_one = authority)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"payment_mint", marketplace.key().as_ref(), payment_mint_config.mint.as_ref()],
+        bump
+    )]
+    pub payment_mint_config: Account<'info, PaymentMint>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateKeyCustodian<'info> {
+    #[account(mut, has_one = authority)]
+    pub marketplace: Account<'info, Marketplace>,
+    pub authority: Signer<'info>,
+}
+
+#[event]
+pub struct FeeUpdateEvent {
+    pub platform_fee_bps: u16,
+    pub creator_royalty_bps: u16,
+}
+
+#[event]
+pub struct QuestionCreated {
+    pub question_id: u64,
+    pub creator: Pubkey,
+    pub unlock_price: u64,
+    pub payment_mint: Pubkey,
+    pub payment_type: PaymentType,
+    pub royalty_bps: u16,
+    pub max_keys: u64,
+    pub creation_time: i64,
+}
+
+#[event]
+pub struct KeyMinted {
+    pub key_id: u64,
+    pub question_id: u64,
+    pub owner: Pubkey,
+    pub mint_time: i64,
+    pub price: u64,
+}
+
+#[event]
+pub struct KeyListed {
+    pub key_id: u64,
+    pub price: u64,
+    pub seller: Pubkey,
+    pub list_time: i64,
+}
+
+#[event]
+pub struct ListingUpdated {
+    pub key_id: u64,
+    pub old_price: u64,
+    pub new_price: u64,
+    pub seller: Pubkey,
+}
+
+#[event]
+pub struct ListingCancelled {
+    pub key_id: u64,
+    pub seller: Pubkey,
+}
+
+#[event]
+pub struct SaleInitiated {
+    pub key_id: u64,
+    pub question_id: u64,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub price: u64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct SaleReclaimed {
+    pub key_id: u64,
+    pub buyer: Pubkey,
+    pub price: u64,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct ToggleOperation<'info> {
-    #[account(mut, has_one = authority)]
-    pub marketplace: Account<'info, Marketplace>,
-    pub authority: Signer<'info>,
+#[event]
+pub struct OfferMade {
+    pub offer: Pubkey,
+    pub question_id: u64,
+    pub unlock_key: Option<Pubkey>,
+    pub bidder: Pubkey,
+    pub price: u64,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct BlacklistUser<'info> {
-    #[account(mut, has_one = authority)]
-    pub marketplace: Account<'info, Marketplace>,
-    #[account(mut)]
-    pub user_state: Account<'info, UserState>,
-    pub authority: Signer<'info>,
+#[event]
+pub struct OfferCancelled {
+    pub offer: Pubkey,
+    pub bidder: Pubkey,
+    pub price: u64,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct UpdateTreasury<'info> {
-    #[account(mut, has_one = authority)]
-    pub marketplace: Account<'info, Marketplace>,
-    pub authority: Signer<'info>,
+#[event]
+pub struct OfferAccepted {
+    pub offer: Pubkey,
+    pub key_id: u64,
+    pub question_id: u64,
+    pub seller: Pubkey,
+    pub bidder: Pubkey,
+    pub price: u64,
+    pub timestamp: i64,
 }
 
 #[event]
-pub struct FeeUpdateEvent {
-    pub platform_fee_bps: u16,
-    pub creator_royalty_bps: u16,
+pub struct AuctionCreated {
+    pub auction: Pubkey,
+    pub key_id: u64,
+    pub seller: Pubkey,
+    pub auction_type: AuctionType,
+    pub start_price: u64,
+    pub reserve_price: u64,
+    pub end_price: u64,
+    pub end_time: i64,
 }
 
 #[event]
-pub struct QuestionCreated {
+pub struct BidPlaced {
+    pub auction: Pubkey,
+    pub bidder: Pubkey,
+    pub amount: u64,
+    pub end_time: i64,
+}
+
+#[event]
+pub struct AuctionSettled {
+    pub auction: Pubkey,
+    pub key_id: u64,
+    pub seller: Pubkey,
+    pub winner: Pubkey,
+    pub price: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuctionCancelled {
+    pub auction: Pubkey,
+    pub key_id: u64,
+    pub refunded_bidder: Option<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct QuestionCollectionCreated {
     pub question_id: u64,
-    pub creator: Pubkey,
-    pub unlock_price: u64,
-    pub max_keys: u64,
-    pub creation_time: i64,
+    pub collection_mint: Pubkey,
+    pub timestamp: i64,
 }
 
 #[event]
-pub struct KeyMinted {
+pub struct KeyTreeCreated {
+    pub question_id: u64,
+    pub key_tree: Pubkey,
+    pub max_depth: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CompressedKeyMinted {
     pub key_id: u64,
     pub question_id: u64,
     pub owner: Pubkey,
-    pub mint_time: i64,
+    pub leaf: [u8; 32],
+    pub root: [u8; 32],
+    pub encrypted_key: Vec<u8>,
     pub price: u64,
+    pub mint_time: i64,
 }
 
 #[event]
-pub struct KeyListed {
+pub struct CompressedKeyTransferred {
     pub key_id: u64,
-    pub price: u64,
-    pub seller: Pubkey,
-    pub list_time: i64,
+    pub question_id: u64,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub leaf: [u8; 32],
+    pub root: [u8; 32],
+    pub encrypted_key: Vec<u8>,
+    pub timestamp: i64,
 }
 
 #[event]
-pub struct ListingUpdated {
-    pub key_id: u64,
-    pub old_price: u64,
-    pub new_price: u64,
-    pub seller: Pubkey,
+pub struct AllowlistUpdated {
+    pub question_id: u64,
+    pub allowlist_root: Option<[u8; 32]>,
+    pub timestamp: i64,
 }
 
 #[event]
-pub struct ListingCancelled {
+pub struct PurchaseLimitUpdated {
+    pub question_id: u64,
+    pub max_keys_per_wallet: Option<u64>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct QuestionSplitCreated {
+    pub question_id: u64,
+    pub payees: Vec<SplitPayee>,
+}
+
+#[event]
+pub struct RefundWindowUpdated {
+    pub question_id: u64,
+    pub refund_window: Option<i64>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct KeyRefunded {
     pub key_id: u64,
-    pub seller: Pubkey,
+    pub question: Pubkey,
+    pub buyer: Pubkey,
+    pub creator_share_refunded: u64,
+    pub platform_fee_retained: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EscrowReleased {
+    pub unlock_key: Pubkey,
+    pub question: Pubkey,
+    pub creator: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EarningsWithdrawn {
+    pub creator: Pubkey,
+    pub payment_mint: Pubkey,
+    pub amount: u64,
+    pub total_earned: u64,
+    pub total_withdrawn: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AllowlistClaimed {
+    pub question_id: u64,
+    pub buyer: Pubkey,
+    pub price: u64,
+    pub claimed: u64,
+    pub max_quantity: u64,
 }
 
 #[event]
@@ -1204,9 +6907,456 @@ pub struct UserUnblacklisted {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct QuestionTermsUpdated {
+    pub question_id: u64,
+    pub old_unlock_price: u64,
+    pub new_unlock_price: u64,
+    pub old_max_keys: u64,
+    pub new_max_keys: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct QuestionContentUpdated {
+    pub question_id: u64,
+    pub content_version: u64,
+    pub content_cid: String,
+    pub content_hash: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct QuestionStatusChanged {
+    pub question_id: u64,
+    pub is_active: bool,
+    pub changed_by: Pubkey,
+    pub reason: QuestionStatusReason,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct QuestionClosed {
+    pub question_id: u64,
+    pub creator: Pubkey,
+    pub keys_sold: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UnlockKeyBurned {
+    pub key_id: u64,
+    pub question: Pubkey,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UserStateClosed {
+    pub user: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct KeyOwnerSynced {
+    pub key_id: u64,
+    pub question: Pubkey,
+    pub previous_owner: Pubkey,
+    pub new_owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct KeyCreatorVerified {
+    pub key_id: u64,
+    pub question: Pubkey,
+    pub creator: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct KeyReencrypted {
+    pub key_id: u64,
+    pub question: Pubkey,
+    pub owner: Pubkey,
+    pub reencrypted_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PaymentMintUpdated {
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub enabled: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct KeyCustodianUpdated {
+    pub previous_custodian: Pubkey,
+    pub new_custodian: Pubkey,
+    pub timestamp: i64,
+}
+
 #[event]
 pub struct TreasuryUpdated {
     pub previous_treasury: Pubkey,
     pub new_treasury: Pubkey,
     pub timestamp: i64,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_curve_ignores_keys_sold() {
+        let curve = PricingCurve::Flat;
+        assert_eq!(curve.price_at(1_000, 0).unwrap(), 1_000);
+        assert_eq!(curve.price_at(1_000, u64::MAX).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn linear_curve_adds_increment_per_key() {
+        let curve = PricingCurve::Linear { increment: 250 };
+        assert_eq!(curve.price_at(1_000, 0).unwrap(), 1_000);
+        assert_eq!(curve.price_at(1_000, 4).unwrap(), 2_000);
+        assert!(curve.price_at(1_000, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn exponential_curve_compounds_growth() {
+        let curve = PricingCurve::Exponential { growth_bps: 10000 };
+        assert_eq!(curve.price_at(1_000, 0).unwrap(), 1_000);
+        assert_eq!(curve.price_at(1_000, 1).unwrap(), 2_000);
+        assert_eq!(curve.price_at(1_000, 10).unwrap(), 1_024_000);
+
+        // 10% growth, rounded down
+        let curve = PricingCurve::Exponential { growth_bps: 1000 };
+        assert_eq!(curve.price_at(1_000_000, 2).unwrap(), 1_210_000);
+        assert_eq!(curve.price_at(1_000_000, 3).unwrap(), 1_331_000);
+    }
+
+    #[test]
+    fn exponential_curve_overflow_is_an_error() {
+        let curve = PricingCurve::Exponential { growth_bps: 10000 };
+        assert_eq!(curve.price_at(1, 63).unwrap(), 1 << 63);
+        assert!(curve.price_at(1, 64).is_err());
+        assert!(curve.price_at(2, 63).is_err());
+        assert!(curve.price_at(1, u64::MAX).is_err());
+
+        let curve = PricingCurve::Exponential { growth_bps: 1 };
+        assert!(curve.price_at(u64::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn step_curve_raises_price_per_tier() {
+        let curve = PricingCurve::Step { keys_per_step: 10, step_increment: 500 };
+        assert_eq!(curve.price_at(1_000, 0).unwrap(), 1_000);
+        assert_eq!(curve.price_at(1_000, 9).unwrap(), 1_000);
+        assert_eq!(curve.price_at(1_000, 10).unwrap(), 1_500);
+        assert_eq!(curve.price_at(1_000, 35).unwrap(), 2_500);
+        assert!(PricingCurve::Step { keys_per_step: 1, step_increment: u64::MAX }
+            .price_at(1, 1)
+            .is_err());
+    }
+
+    #[test]
+    fn validate_rejects_bad_parameters_and_overflowing_caps() {
+        assert!(PricingCurve::Flat.validate(1_000, u64::MAX).is_ok());
+        assert!(PricingCurve::Linear { increment: 0 }.validate(1_000, 10).is_err());
+        assert!(PricingCurve::Exponential { growth_bps: 0 }.validate(1_000, 10).is_err());
+        assert!(PricingCurve::Exponential { growth_bps: MAX_CURVE_GROWTH_BPS + 1 }
+            .validate(1_000, 10)
+            .is_err());
+        assert!(PricingCurve::Step { keys_per_step: 0, step_increment: 1 }
+            .validate(1_000, 10)
+            .is_err());
+        assert!(PricingCurve::Exponential { growth_bps: 10000 }.validate(1, 64).is_ok());
+        assert!(PricingCurve::Exponential { growth_bps: 10000 }.validate(1, 65).is_err());
+    }
+
+    #[test]
+    fn merkle_proof_verifies_sorted_pairs() {
+        let alice = allowlist_leaf(&Pubkey::new_unique(), 0, 1);
+        let bob = allowlist_leaf(&Pubkey::new_unique(), 500, 3);
+        let carol = allowlist_leaf(&Pubkey::new_unique(), 250, 2);
+
+        let hash_pair = |a: [u8; 32], b: [u8; 32]| {
+            if a <= b {
+                keccak::hashv(&[&a, &b]).0
+            } else {
+                keccak::hashv(&[&b, &a]).0
+            }
+        };
+        let alice_bob = hash_pair(alice, bob);
+        let root = hash_pair(alice_bob, carol);
+
+        assert!(verify_merkle_proof(&[bob, carol], root, alice));
+        assert!(verify_merkle_proof(&[alice, carol], root, bob));
+        assert!(verify_merkle_proof(&[alice_bob], root, carol));
+        assert!(!verify_merkle_proof(&[bob], root, alice));
+        assert!(!verify_merkle_proof(&[alice_bob], root, allowlist_leaf(&Pubkey::new_unique(), 250, 2)));
+    }
+
+    fn key_tree(max_depth: u8) -> KeyTree {
+        let mut tree = KeyTree {
+            question: Pubkey::default(),
+            max_depth: 0,
+            num_leaves: 0,
+            sequence: 0,
+            root: [0; 32],
+            filled_subtrees: vec![],
+            changelog: vec![],
+        };
+        tree.initialize(Pubkey::new_unique(), max_depth);
+        tree
+    }
+
+    // Proof for `index` computed from scratch over every leaf
+    fn key_tree_proof(leaves: &[[u8; 32]], max_depth: u8, index: usize) -> Vec<[u8; 32]> {
+        let empty_nodes = empty_key_tree_nodes(max_depth);
+        let mut level_nodes = leaves.to_vec();
+        let mut proof = vec![];
+        for (level, empty) in empty_nodes.iter().take(max_depth as usize).enumerate() {
+            let sibling = (index >> level) ^ 1;
+            proof.push(level_nodes.get(sibling).copied().unwrap_or(*empty));
+            level_nodes = level_nodes
+                .chunks(2)
+                .map(|pair| hash_key_tree_pair(&pair[0], pair.get(1).unwrap_or(empty)))
+                .collect();
+        }
+        proof
+    }
+
+    fn leaf(byte: u8) -> [u8; 32] {
+        [byte; 32]
+    }
+
+    #[test]
+    fn key_tree_appends_match_a_full_rebuild() {
+        let mut tree = key_tree(3);
+        let leaves: Vec<_> = (1..=5).map(leaf).collect();
+        for (index, leaf) in leaves.iter().enumerate() {
+            assert_eq!(tree.append(*leaf).unwrap(), index as u32);
+        }
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = key_tree_proof(&leaves, 3, index);
+            assert_eq!(key_tree_path(*leaf, &proof, index as u32).0, tree.root);
+            assert!(tree.verify_leaf(tree.root, *leaf, &proof, index as u32).is_ok());
+        }
+        assert!(tree.verify_leaf(tree.root, leaf(9), &key_tree_proof(&leaves, 3, 0), 0).is_err());
+    }
+
+    #[test]
+    fn key_tree_rejects_appends_past_capacity() {
+        let mut tree = key_tree(1);
+        tree.append(leaf(1)).unwrap();
+        tree.append(leaf(2)).unwrap();
+        assert!(tree.append(leaf(3)).is_err());
+    }
+
+    #[test]
+    fn key_tree_fast_forwards_stale_proofs() {
+        let mut tree = key_tree(3);
+        let mut leaves: Vec<_> = (1..=4).map(leaf).collect();
+        for leaf in &leaves {
+            tree.append(*leaf).unwrap();
+        }
+        let root = tree.root;
+        let proof_0 = key_tree_proof(&leaves, 3, 0);
+        let proof_3 = key_tree_proof(&leaves, 3, 3);
+
+        // another leaf changes and more are appended after both proofs were taken
+        tree.replace_leaf(root, leaves[0], leaf(10), &proof_0, 0).unwrap();
+        leaves[0] = leaf(10);
+        tree.append(leaf(5)).unwrap();
+        leaves.push(leaf(5));
+
+        tree.replace_leaf(root, leaves[3], leaf(11), &proof_3, 3).unwrap();
+        leaves[3] = leaf(11);
+        assert_eq!(key_tree_path(leaves[3], &key_tree_proof(&leaves, 3, 3), 3).0, tree.root);
+
+        // appends after a replace still hash against the replaced subtree
+        tree.append(leaf(6)).unwrap();
+        leaves.push(leaf(6));
+        assert_eq!(key_tree_path(leaf(6), &key_tree_proof(&leaves, 3, 5), 5).0, tree.root);
+
+        // the original proof of leaf 0 is stale now that leaf 0 itself changed
+        assert!(tree.verify_leaf(root, leaves[0], &proof_0, 0).is_err());
+    }
+
+    #[test]
+    fn key_tree_forgets_roots_beyond_the_buffer() {
+        let mut tree = key_tree(4);
+        let mut leaves = vec![leaf(1)];
+        tree.append(leaf(1)).unwrap();
+        let root = tree.root;
+        let proof = key_tree_proof(&leaves, 4, 0);
+        for byte in 2..=KEY_TREE_BUFFER_SIZE as u8 {
+            tree.append(leaf(byte)).unwrap();
+            leaves.push(leaf(byte));
+        }
+        assert!(tree.verify_leaf(root, leaf(1), &proof, 0).is_ok());
+
+        tree.append(leaf(100)).unwrap();
+        assert!(tree.verify_leaf(root, leaf(1), &proof, 0).is_err());
+    }
+
+    fn question() -> Question {
+        Question {
+            creator: Pubkey::default(),
+            content_cid: String::new(),
+            content_hash: [0; 32],
+            content_version: 1,
+            unlock_price: 1_000,
+            max_keys: 10,
+            current_keys: 3,
+            index: 0,
+            creation_time: 0,
+            total_sales: 0,
+            is_active: true,
+            is_taken_down: false,
+            validation_timestamp: 0,
+            pricing_curve: PricingCurve::Linear { increment: 100 },
+            launch_schedule: None,
+            allowlist_root: None,
+            payment_mint: Pubkey::default(),
+            payment_type: PaymentType::Token,
+            royalty_bps: 0,
+            collection_mint: None,
+            key_mode: KeyMode::Standard,
+            max_keys_per_wallet: None,
+            refund_window: None,
+            has_split: false,
+            open_escrows: 0,
+        }
+    }
+
+    #[test]
+    fn next_unlock_price_follows_current_keys() {
+        assert_eq!(question().next_unlock_price(0).unwrap(), 1_300);
+    }
+
+    #[test]
+    fn unlock_price_at_prices_later_keys_in_a_batch() {
+        let question = question();
+        let prices: Vec<u64> = (3..6)
+            .map(|keys_sold| question.unlock_price_at(keys_sold, 0).unwrap())
+            .collect();
+        assert_eq!(prices, vec![1_300, 1_400, 1_500]);
+    }
+
+    #[test]
+    fn sale_proceeds_use_question_royalty() {
+        let marketplace = Marketplace {
+            treasury: Pubkey::default(),
+            authority: Pubkey::default(),
+            question_counter: 0,
+            platform_fee_bps: 500,
+            creator_royalty_bps: 200,
+            total_volume: 0,
+            paused: false,
+            paused_operations: PausedOperations::default(),
+            bonk_mint: Pubkey::default(),
+            key_custodian: Pubkey::default(),
+        };
+        let question = Question { royalty_bps: 750, ..question() };
+        assert_eq!(
+            split_sale_proceeds(10_000, &marketplace, &question).unwrap(),
+            (500, 750, 8_750)
+        );
+    }
+
+    fn payee(seed: u8, share_bps: u16) -> SplitPayee {
+        SplitPayee { payee: Pubkey::new_from_array([seed; 32]), share_bps }
+    }
+
+    #[test]
+    fn split_validation_requires_unique_payees_summing_to_10000() {
+        assert!(QuestionSplit::validate(&[payee(1, 6000), payee(2, 4000)]).is_ok());
+        assert!(QuestionSplit::validate(&[payee(1, 10000)]).is_ok());
+        assert!(QuestionSplit::validate(&[]).is_err());
+        assert!(QuestionSplit::validate(&[payee(1, 6000), payee(2, 3999)]).is_err());
+        assert!(QuestionSplit::validate(&[payee(1, 5000), payee(1, 5000)]).is_err());
+        assert!(QuestionSplit::validate(&[payee(1, 10000), payee(2, 0)]).is_err());
+        // one Metaplex creator slot is taken by the mint authority PDA
+        let too_many = (1..=5).map(|seed| payee(seed, 2000)).collect::<Vec<_>>();
+        assert!(QuestionSplit::validate(&too_many).is_err());
+    }
+
+    #[test]
+    fn split_portions_give_rounding_dust_to_the_first_payee() {
+        let payees = [payee(1, 3333), payee(2, 3333), payee(3, 3334)];
+        assert_eq!(QuestionSplit::portions(&payees, 10_000).unwrap(), vec![3_333, 3_333, 3_334]);
+        assert_eq!(QuestionSplit::portions(&payees, 100).unwrap(), vec![34, 33, 33]);
+        assert_eq!(QuestionSplit::portions(&payees, 1).unwrap(), vec![1, 0, 0]);
+        assert_eq!(QuestionSplit::portions(&payees, u64::MAX).unwrap().iter().sum::<u64>(), u64::MAX);
+    }
+
+    #[test]
+    fn key_nft_creators_list_split_payees_by_percentage() {
+        let mint_authority = Pubkey::new_from_array([9; 32]);
+        let payees = [payee(1, 3333), payee(2, 3333), payee(3, 3334)];
+        let share = CreatorShare {
+            creator: Pubkey::new_from_array([1; 32]),
+            payment_type: PaymentType::Sol,
+            payment_mint: Pubkey::default(),
+            payees: &payees,
+        };
+        let creators = key_nft_creators(mint_authority, &share).unwrap();
+        assert_eq!(creators.len(), 4);
+        assert!(creators[0].address == mint_authority && creators[0].verified);
+        assert!(creators[1..].iter().all(|creator| !creator.verified));
+        assert_eq!(
+            creators.iter().map(|creator| creator.share).collect::<Vec<_>>(),
+            vec![0, 34, 33, 33]
+        );
+
+        let creators = key_nft_creators(mint_authority, &CreatorShare { payees: &[], ..share }).unwrap();
+        assert_eq!(creators.len(), 2);
+        assert!(creators[1].address == share.creator && creators[1].share == 100);
+    }
+
+    fn schedule() -> LaunchSchedule {
+        LaunchSchedule {
+            start_time: 1_000,
+            early_bird_price: 500,
+            early_bird_end: 2_000,
+            decay_bps_per_day: 1000,
+            floor_price: 600,
+        }
+    }
+
+    #[test]
+    fn launch_schedule_rejects_mints_before_start() {
+        assert!(schedule().price_at(1_000, 999).is_err());
+        assert_eq!(schedule().price_at(1_000, 1_000).unwrap(), 500);
+    }
+
+    #[test]
+    fn launch_schedule_decays_toward_floor_after_early_bird() {
+        let schedule = schedule();
+        assert_eq!(schedule.price_at(1_000, 2_000).unwrap(), 1_000);
+        // 10% per day, so half a day takes 5% off
+        assert_eq!(
+            schedule.price_at(1_000, 2_000 + SECONDS_PER_DAY / 2).unwrap(),
+            950
+        );
+        assert_eq!(
+            schedule.price_at(1_000, 2_000 + 30 * SECONDS_PER_DAY).unwrap(),
+            600
+        );
+    }
+
+    #[test]
+    fn launch_schedule_validation() {
+        assert!(schedule().validate(1_000).is_ok());
+        assert!(LaunchSchedule { early_bird_end: 1_000, ..schedule() }.validate(1_000).is_err());
+        assert!(LaunchSchedule { floor_price: 0, ..schedule() }.validate(1_000).is_err());
+        assert!(LaunchSchedule { decay_bps_per_day: 10001, ..schedule() }.validate(1_000).is_err());
+        // a floor above the base price would never be reached by decay
+        assert!(schedule().validate(599).is_err());
+        assert!(LaunchSchedule { decay_bps_per_day: 0, ..schedule() }.validate(599).is_ok());
+    }
+}
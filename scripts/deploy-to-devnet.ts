@@ -159,6 +159,22 @@ async function deploy() {
       })
       .rpc();
 
+    // register BONK as an accepted payment mint
+    const [bonkPaymentMintPDA] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from('payment_mint'), marketplacePDA.toBuffer(), DEVNET_BONK_MINT.toBuffer()],
+      program.programId,
+    );
+    await program.methods
+      .addPaymentMint()
+      .accounts({
+        marketplace: marketplacePDA,
+        paymentMintConfig: bonkPaymentMintPDA,
+        mint: DEVNET_BONK_MINT,
+        authority: provider.wallet.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
     const deployInfo = {
       programId: PROGRAM_ID.toString(),
       programAuthority: provider.wallet.publicKey.toString(),
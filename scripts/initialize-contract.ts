@@ -94,6 +94,22 @@ async function initialize(network: Network) {
       })
       .rpc();
 
+    // register BONK as an accepted payment mint
+    const [bonkPaymentMintPDA] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from('payment_mint'), marketplacePDA.toBuffer(), networkConfig.bonkMint.toBuffer()],
+      program.programId,
+    );
+    await program.methods
+      .addPaymentMint()
+      .accounts({
+        marketplace: marketplacePDA,
+        paymentMintConfig: bonkPaymentMintPDA,
+        mint: networkConfig.bonkMint,
+        authority: provider.wallet.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
     const initInfo = {
       programId: PROGRAM_ID.toString(),
       programAuthority: provider.wallet.publicKey.toString(),
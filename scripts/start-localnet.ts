@@ -260,6 +260,22 @@ async function deploy() {
       })
       .rpc();
 
+    // register BONK as an accepted payment mint
+    const [bonkPaymentMintPDA] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from('payment_mint'), marketplacePDA.toBuffer(), testBonkMint.toBuffer()],
+      program.programId,
+    );
+    await program.methods
+      .addPaymentMint()
+      .accounts({
+        marketplace: marketplacePDA,
+        paymentMintConfig: bonkPaymentMintPDA,
+        mint: testBonkMint,
+        authority: provider.wallet.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+      })
+      .rpc();
+
     const deployInfo = {
       programId: programId.toString(),
       bonkMint: testBonkMint.toString(),
@@ -9,6 +9,7 @@ import {
   Transaction,
   sendAndConfirmTransaction,
   LAMPORTS_PER_SOL,
+  ComputeBudgetProgram,
 } from '@solana/web3.js';
 import {
   createMint,
@@ -19,8 +20,15 @@ import {
   ASSOCIATED_TOKEN_PROGRAM_ID,
   createAssociatedTokenAccountInstruction,
   getAccount,
+  transfer,
+  TOKEN_2022_PROGRAM_ID,
+  ExtensionType,
+  getMintLen,
+  createInitializeTransferFeeConfigInstruction,
+  createInitializeMintInstruction,
 } from '@solana/spl-token';
 import { assert } from 'chai';
+import { keccak_256 } from '@noble/hashes/sha3';
 import { MPL_TOKEN_METADATA_PROGRAM_ID } from '@metaplex-foundation/mpl-token-metadata';
 import { BorshAccountsCoder } from '@project-serum/anchor';
 
@@ -46,12 +54,15 @@ describe('myqa', function () {
 
   // mint unlock key test accounts
   const buyer = Keypair.generate();
-  const nftMint = Keypair.generate();
   let buyerTokenAccount: PublicKey;
   let creatorTokenAccount: PublicKey;
   let questionPda: PublicKey;
   let mintAuthority: PublicKey;
+  let listingAuthority: PublicKey;
+  let bonkPaymentMint: PublicKey;
   let metadata: PublicKey;
+  let masterEdition: PublicKey;
+  let buyerKeyAccount: PublicKey;
   let updateAuthority: PublicKey;
 
   // configure the client to use the local cluster
@@ -60,11 +71,46 @@ describe('myqa', function () {
 
   const program = anchor.workspace.Myqa as Program<Myqa>;
 
+  // derive the NFT accounts mint_unlock_key creates for an unlock key
+  const findKeyNftAccounts = async (unlockKey: PublicKey, owner: PublicKey) => {
+    const [mint] = PublicKey.findProgramAddressSync([Buffer.from('key_mint'), unlockKey.toBuffer()], program.programId);
+    const [metadata] = PublicKey.findProgramAddressSync(
+      [Buffer.from('metadata'), TOKEN_METADATA_PROGRAM_ID.toBuffer(), mint.toBuffer()],
+      TOKEN_METADATA_PROGRAM_ID,
+    );
+    const [masterEdition] = PublicKey.findProgramAddressSync(
+      [Buffer.from('metadata'), TOKEN_METADATA_PROGRAM_ID.toBuffer(), mint.toBuffer(), Buffer.from('edition')],
+      TOKEN_METADATA_PROGRAM_ID,
+    );
+    const ownerKeyAccount = await getAssociatedTokenAddress(mint, owner);
+    return { mint, metadata, masterEdition, ownerKeyAccount };
+  };
+  const findPurchaseRecord = (question: PublicKey, buyer: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from('purchase_record'), question.toBuffer(), buyer.toBuffer()],
+      program.programId,
+    )[0];
+  // creator earnings vault for a payment mint, PublicKey.default for SOL
+  const findCreatorVault = (creator: PublicKey, paymentMint: PublicKey) => {
+    const [creatorVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from('creator_vault'), creator.toBuffer(), paymentMint.toBuffer()],
+      program.programId,
+    );
+    const [creatorVaultTokens] = PublicKey.findProgramAddressSync(
+      [Buffer.from('creator_vault_tokens'), creatorVault.toBuffer()],
+      program.programId,
+    );
+    return { creatorVault, creatorVaultTokens };
+  };
+
   // constants for testing
   const CONTENT_CID = 'QmT8JtG98Pu6YqHrRxiANrqjaC8ydz3F4uuQvRfQqC3T45';
   const CONTENT_HASH = Array(32).fill(1);
   const UNLOCK_PRICE = new anchor.BN(1_000_000); // 1 BONK (6 decimals)
   const MAX_KEYS = new anchor.BN(10);
+  const FLAT_CURVE = { flat: {} };
+  const TOKEN_PAYMENT = { token: {} };
+  const ROYALTY_BPS = 200; // 2%
   const PINATA_URI = 'https://gateway.pinata.cloud/ipfs/QmT8JtG98Pu6YqHrRxiANrqjaC8ydz3F4uuQvRfQqC3T45';
   const ENCRYPTED_KEY = Buffer.from('encrypted_key_data');
 
@@ -104,6 +150,23 @@ describe('myqa', function () {
     // create BONK token mint
     await createMint(provider.connection, authority, authority.publicKey, null, TEST_BONK_DECIMALS, bonkMint);
 
+    // register BONK as an accepted payment mint
+    [bonkPaymentMint] = PublicKey.findProgramAddressSync(
+      [Buffer.from('payment_mint'), marketplace.toBuffer(), bonkMint.publicKey.toBuffer()],
+      program.programId,
+    );
+    await program.methods
+      .addPaymentMint()
+      .accounts({
+        marketplace,
+        paymentMintConfig: bonkPaymentMint,
+        mint: bonkMint.publicKey,
+        authority: authority.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([authority])
+      .rpc();
+
     // create treasury token account (ATA for treasury)
     treasuryTokenAccount = await getAssociatedTokenAddress(bonkMint.publicKey, treasury.publicKey);
     try {
@@ -182,6 +245,9 @@ describe('myqa', function () {
     // find mintAuthority
     [mintAuthority] = PublicKey.findProgramAddressSync([Buffer.from('mint_authority')], program.programId);
 
+    // find listingAuthority
+    [listingAuthority] = PublicKey.findProgramAddressSync([Buffer.from('listing_authority')], program.programId);
+
     // find question PDA
     const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
     [questionPda] = PublicKey.findProgramAddressSync(
@@ -193,16 +259,7 @@ describe('myqa', function () {
       program.programId,
     );
 
-    // create NFT mint for tests
-    await createMint(provider.connection, authority, mintAuthority, null, 0, nftMint);
-
-    // find metadata
-    [metadata] = PublicKey.findProgramAddressSync(
-      [Buffer.from('metadata'), TOKEN_METADATA_PROGRAM_ID.toBuffer(), nftMint.publicKey.toBuffer()],
-      TOKEN_METADATA_PROGRAM_ID,
-    );
-
-    // updateAuthority can be a PDA or just the mintAuthority for simplicity
+    // updateAuthority must be the mintAuthority PDA so it can sign the master edition
     updateAuthority = mintAuthority;
   });
 
@@ -211,6 +268,7 @@ describe('myqa', function () {
 
     assert.ok(marketplaceAccount.authority.equals(authority.publicKey));
     assert.ok(marketplaceAccount.treasury.equals(treasury.publicKey));
+    assert.ok(marketplaceAccount.keyCustodian.equals(authority.publicKey));
     assert.equal(marketplaceAccount.questionCounter.toNumber(), 0);
     assert.equal(marketplaceAccount.platformFeeBps, 500); // 5%
     assert.equal(marketplaceAccount.creatorRoyaltyBps, 200); // 2%
@@ -221,6 +279,7 @@ describe('myqa', function () {
       mintKey: false,
       listKey: false,
       buyKey: false,
+      updateQuestion: false,
     });
   });
 
@@ -243,11 +302,23 @@ describe('myqa', function () {
       );
 
       await program.methods
-        .createQuestion(CONTENT_CID, CONTENT_HASH, UNLOCK_PRICE, MAX_KEYS)
+        .createQuestion(
+          CONTENT_CID,
+          CONTENT_HASH,
+          UNLOCK_PRICE,
+          MAX_KEYS,
+          FLAT_CURVE,
+          null,
+          TOKEN_PAYMENT,
+          ROYALTY_BPS,
+          [],
+        )
         .accounts({
           marketplace: marketplace,
           userState: userState,
           question: questionPdaNew,
+          questionSplit: null,
+          paymentMintConfig: bonkPaymentMint,
           creator: user.publicKey,
           systemProgram: SystemProgram.programId,
           rent: SYSVAR_RENT_PUBKEY,
@@ -257,6 +328,8 @@ describe('myqa', function () {
 
       const questionAccount = await program.account.question.fetch(questionPdaNew);
       assert.ok(questionAccount.creator.equals(user.publicKey));
+      assert.ok(questionAccount.paymentMint.equals(bonkMint.publicKey));
+      assert.equal(questionAccount.royaltyBps, ROYALTY_BPS);
       assert.equal(questionAccount.contentCid, CONTENT_CID);
       assert.deepEqual(Array.from(questionAccount.contentHash), CONTENT_HASH);
       assert.equal(questionAccount.unlockPrice.toNumber(), UNLOCK_PRICE.toNumber());
@@ -294,11 +367,23 @@ describe('myqa', function () {
 
       try {
         await program.methods
-          .createQuestion(CONTENT_CID, CONTENT_HASH, UNLOCK_PRICE, MAX_KEYS)
+          .createQuestion(
+            CONTENT_CID,
+            CONTENT_HASH,
+            UNLOCK_PRICE,
+            MAX_KEYS,
+            FLAT_CURVE,
+            null,
+            TOKEN_PAYMENT,
+            ROYALTY_BPS,
+            [],
+          )
           .accounts({
             marketplace,
             userState,
             question: questionPda2,
+            questionSplit: null,
+            paymentMintConfig: bonkPaymentMint,
             creator: user.publicKey,
             systemProgram: SystemProgram.programId,
             rent: SYSVAR_RENT_PUBKEY,
@@ -324,198 +409,724 @@ describe('myqa', function () {
         .signers([authority])
         .rpc();
     });
-  });
-
-  describe('MintUnlockKey', () => {
-    let unlockKeyPda: PublicKey;
-    let nftMintLocal: Keypair;
-    beforeEach(async () => {
-      // airdrop SOL to buyer
-      const latestBlockhash = await provider.connection.getLatestBlockhash();
-
-      const signature = await provider.connection.requestAirdrop(buyer.publicKey, 2 * LAMPORTS_PER_SOL);
-      await provider.connection.confirmTransaction({
-        signature,
-        ...latestBlockhash,
-      });
 
-      // ensure marketplace is not paused
+    it('Rejects a creator royalty above the fee cap', async () => {
       const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
-      if (marketplaceAccount.paused) {
+      const [royaltyQuestionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from('question'),
+          marketplace.toBuffer(),
+          marketplaceAccount.questionCounter.toArrayLike(Buffer, 'le', 8),
+        ],
+        program.programId,
+      );
+
+      try {
         await program.methods
-          .toggleMarketplace()
+          .createQuestion(CONTENT_CID, CONTENT_HASH, UNLOCK_PRICE, MAX_KEYS, FLAT_CURVE, null, TOKEN_PAYMENT, 1001, [])
+          .accounts({
+            marketplace,
+            userState,
+            question: royaltyQuestionPda,
+            questionSplit: null,
+            paymentMintConfig: bonkPaymentMint,
+            creator: user.publicKey,
+            systemProgram: SystemProgram.programId,
+            rent: SYSVAR_RENT_PUBKEY,
+          })
+          .signers([user])
+          .rpc();
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.equal(errorMessage, 'Fee too high');
+      }
+    });
+
+    it('Rejects questions priced in a disabled payment mint', async () => {
+      const setEnabled = (enabled: boolean) =>
+        program.methods
+          .setPaymentMintEnabled(enabled)
           .accounts({
             marketplace,
+            paymentMintConfig: bonkPaymentMint,
             authority: authority.publicKey,
           })
           .signers([authority])
           .rpc();
+
+      await setEnabled(false);
+      try {
+        const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
+        const [disabledQuestionPda] = PublicKey.findProgramAddressSync(
+          [
+            Buffer.from('question'),
+            marketplace.toBuffer(),
+            marketplaceAccount.questionCounter.toArrayLike(Buffer, 'le', 8),
+          ],
+          program.programId,
+        );
+        await program.methods
+          .createQuestion(
+            CONTENT_CID,
+            CONTENT_HASH,
+            UNLOCK_PRICE,
+            MAX_KEYS,
+            FLAT_CURVE,
+            null,
+            TOKEN_PAYMENT,
+            ROYALTY_BPS,
+            [],
+          )
+          .accounts({
+            marketplace,
+            userState,
+            question: disabledQuestionPda,
+            questionSplit: null,
+            paymentMintConfig: bonkPaymentMint,
+            creator: user.publicKey,
+            systemProgram: SystemProgram.programId,
+            rent: SYSVAR_RENT_PUBKEY,
+          })
+          .signers([user])
+          .rpc();
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'Payment mint is disabled');
+      } finally {
+        await setEnabled(true);
       }
+    });
+  });
 
-      const questionCounter = marketplaceAccount.questionCounter;
-      [questionPda] = PublicKey.findProgramAddressSync(
-        [Buffer.from('question'), marketplace.toBuffer(), questionCounter.toArrayLike(Buffer, 'le', 8)],
+  describe('Primary pricing', () => {
+    const createCurveQuestion = async (curve: any, schedule: any = null) => {
+      const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
+      const [curveQuestionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from('question'),
+          marketplace.toBuffer(),
+          marketplaceAccount.questionCounter.toArrayLike(Buffer, 'le', 8),
+        ],
         program.programId,
       );
 
-      // create question for this test scenario
       await program.methods
-        .createQuestion(CONTENT_CID, CONTENT_HASH, UNLOCK_PRICE, MAX_KEYS)
+        .createQuestion(
+          CONTENT_CID,
+          CONTENT_HASH,
+          UNLOCK_PRICE,
+          MAX_KEYS,
+          curve,
+          schedule,
+          TOKEN_PAYMENT,
+          ROYALTY_BPS,
+          [],
+        )
         .accounts({
           marketplace,
           userState,
-          question: questionPda,
+          question: curveQuestionPda,
+          questionSplit: null,
+          paymentMintConfig: bonkPaymentMint,
           creator: user.publicKey,
           systemProgram: SystemProgram.programId,
           rent: SYSVAR_RENT_PUBKEY,
         })
         .signers([user])
         .rpc();
+      return curveQuestionPda;
+    };
 
-      const questionAccount = await program.account.question.fetch(questionPda);
-      if (!questionAccount) {
-        throw new Error('Question account not initialized');
+    it('Stores a linear curve and quotes the next key price', async () => {
+      const increment = new anchor.BN(100_000);
+      const curveQuestionPda = await createCurveQuestion({ linear: { increment } });
+
+      const questionAccount = await program.account.question.fetch(curveQuestionPda);
+      assert.equal(questionAccount.pricingCurve.linear.increment.toNumber(), increment.toNumber());
+
+      // no keys sold yet, so the next key costs the base price
+      const nextPrice = await program.methods.getNextUnlockPrice().accounts({ question: curveQuestionPda }).view();
+      assert.equal(nextPrice.toNumber(), UNLOCK_PRICE.toNumber());
+    });
+
+    it('Quotes the early-bird price during the presale window', async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const earlyBirdPrice = new anchor.BN(500_000);
+      const curveQuestionPda = await createCurveQuestion(FLAT_CURVE, {
+        startTime: new anchor.BN(now - 60),
+        earlyBirdPrice,
+        earlyBirdEnd: new anchor.BN(now + 3600),
+        decayBpsPerDay: 0,
+        floorPrice: new anchor.BN(0),
+      });
+
+      const nextPrice = await program.methods.getNextUnlockPrice().accounts({ question: curveQuestionPda }).view();
+      assert.equal(nextPrice.toNumber(), earlyBirdPrice.toNumber());
+    });
+
+    it('Refuses to sell keys before the launch starts', async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const curveQuestionPda = await createCurveQuestion(FLAT_CURVE, {
+        startTime: new anchor.BN(now + 3600),
+        earlyBirdPrice: new anchor.BN(0),
+        earlyBirdEnd: new anchor.BN(0),
+        decayBpsPerDay: 0,
+        floorPrice: new anchor.BN(0),
+      });
+
+      try {
+        await program.methods.getNextUnlockPrice().accounts({ question: curveQuestionPda }).view();
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'Question is not on sale yet');
       }
+    });
 
-      nftMintLocal = Keypair.generate();
-      await createMint(provider.connection, authority, mintAuthority, null, 0, nftMintLocal);
+    it('Rejects malformed curve parameters', async () => {
+      try {
+        await createCurveQuestion({ step: { keysPerStep: new anchor.BN(0), stepIncrement: new anchor.BN(1) } });
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'Invalid pricing curve parameters');
+      }
+    });
+  });
 
-      // find metadata for this new NFT
-      [metadata] = PublicKey.findProgramAddressSync(
-        [Buffer.from('metadata'), TOKEN_METADATA_PROGRAM_ID.toBuffer(), nftMintLocal.publicKey.toBuffer()],
-        TOKEN_METADATA_PROGRAM_ID,
-      );
+  describe('QuestionManagement', () => {
+    let statusQuestionPda: PublicKey;
 
-      // find PDA for unlock key
-      const questionData = await program.account.question.fetch(questionPda);
-      [unlockKeyPda] = PublicKey.findProgramAddressSync(
-        [Buffer.from('unlock_key'), questionPda.toBuffer(), questionData.currentKeys.toArrayLike(Buffer, 'le', 8)],
+    before(async () => {
+      const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
+      [statusQuestionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from('question'),
+          marketplace.toBuffer(),
+          marketplaceAccount.questionCounter.toArrayLike(Buffer, 'le', 8),
+        ],
         program.programId,
       );
 
-      // mint BONK to buyer if needed
-      await mintTo(
-        provider.connection,
-        authority,
-        bonkMint.publicKey,
-        buyerTokenAccount,
-        authority,
-        UNLOCK_PRICE.toNumber() * 2,
-      );
-    });
-
-    it('Successfully mints an unlock key', async () => {
       await program.methods
-        .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY)
+        .createQuestion(
+          CONTENT_CID,
+          CONTENT_HASH,
+          UNLOCK_PRICE,
+          MAX_KEYS,
+          FLAT_CURVE,
+          null,
+          TOKEN_PAYMENT,
+          ROYALTY_BPS,
+          [],
+        )
         .accounts({
           marketplace,
-          question: questionPda,
-          unlockKey: unlockKeyPda,
-          buyer: buyer.publicKey,
-          buyerTokenAccount,
-          creatorTokenAccount,
-          treasuryTokenAccount, // fees go here
-          bonkMint: bonkMint.publicKey,
-          metadata,
-          mint: nftMintLocal.publicKey, // note: using global nftMint created in before block if needed
-          mintAuthority: mintAuthority,
-          updateAuthority: mintAuthority,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          metadataProgram: TOKEN_METADATA_PROGRAM_ID,
-          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          userState,
+          question: statusQuestionPda,
+          questionSplit: null,
+          paymentMintConfig: bonkPaymentMint,
+          creator: user.publicKey,
           systemProgram: SystemProgram.programId,
           rent: SYSVAR_RENT_PUBKEY,
-          userState,
         })
-        .signers([buyer])
+        .signers([user])
         .rpc();
+    });
 
-      const unlockKeyAccount = await program.account.unlockKey.fetch(unlockKeyPda);
-
-      assert.ok(unlockKeyAccount.owner.equals(buyer.publicKey), 'Incorrect owner');
-      assert.ok(unlockKeyAccount.question.equals(questionPda));
-      assert.equal(unlockKeyAccount.tokenId.toNumber(), 0);
-      assert.deepEqual(Buffer.from(unlockKeyAccount.encryptedKey), ENCRYPTED_KEY);
-      assert.equal(unlockKeyAccount.isListed, false);
-      assert.equal(unlockKeyAccount.listPrice.toNumber(), 0);
-      assert.equal(unlockKeyAccount.metadataUri, PINATA_URI);
+    it('Lets the creator deactivate and reactivate a question', async () => {
+      await program.methods
+        .setQuestionActive(false)
+        .accounts({
+          marketplace,
+          question: statusQuestionPda,
+          creator: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
 
-      // verify token transfers
-      const platformFee = Math.floor(UNLOCK_PRICE.toNumber() * 0.05);
-      const creatorPayment = UNLOCK_PRICE.toNumber() - platformFee;
+      let questionAccount = await program.account.question.fetch(statusQuestionPda);
+      assert.isFalse(questionAccount.isActive);
 
-      const buyerBalance = await provider.connection.getTokenAccountBalance(buyerTokenAccount);
-      const creatorBalance = await provider.connection.getTokenAccountBalance(creatorTokenAccount);
-      const treasuryBalance = await provider.connection.getTokenAccountBalance(treasuryTokenAccount);
+      await program.methods
+        .setQuestionActive(true)
+        .accounts({
+          marketplace,
+          question: statusQuestionPda,
+          creator: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
 
-      assert.equal(treasuryBalance.value.amount, platformFee.toString());
-      assert.equal(creatorBalance.value.amount, creatorPayment.toString());
+      questionAccount = await program.account.question.fetch(statusQuestionPda);
+      assert.isTrue(questionAccount.isActive);
     });
 
-    it('Fails to mint when marketplace is paused', async () => {
+    it('Fails when a non-creator changes the question status', async () => {
       try {
-        // pause marketplace
         await program.methods
-          .toggleMarketplace()
+          .setQuestionActive(false)
           .accounts({
             marketplace,
-            authority: authority.publicKey,
+            question: statusQuestionPda,
+            creator: buyer.publicKey,
           })
-          .signers([authority])
+          .signers([buyer])
           .rpc();
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'Not the question creator');
+      }
+    });
 
-        await sleep(2000);
+    it('Lets the creator update price and max keys', async () => {
+      const newPrice = new anchor.BN(2_000_000);
+      const newMaxKeys = new anchor.BN(20);
 
-        // verify marketplace is paused
-        const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
-        assert.isTrue(marketplaceAccount.paused, 'Marketplace should be paused');
+      await program.methods
+        .updateQuestionTerms(newPrice, newMaxKeys)
+        .accounts({
+          marketplace,
+          question: statusQuestionPda,
+          keyTree: null,
+          userState,
+          creator: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
 
-        // try to mint unlock key
+      const questionAccount = await program.account.question.fetch(statusQuestionPda);
+      assert.equal(questionAccount.unlockPrice.toNumber(), newPrice.toNumber());
+      assert.equal(questionAccount.maxKeys.toNumber(), newMaxKeys.toNumber());
+    });
+
+    it('Fails to update terms with an invalid key cap', async () => {
+      try {
         await program.methods
-          .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY)
+          .updateQuestionTerms(UNLOCK_PRICE, new anchor.BN(0))
           .accounts({
             marketplace,
-            question: questionPda,
-            unlockKey: unlockKeyPda,
-            buyer: buyer.publicKey,
-            buyerTokenAccount,
-            creatorTokenAccount,
-            treasuryTokenAccount,
-            bonkMint: bonkMint.publicKey,
-            metadata,
-            mint: nftMint.publicKey,
-            mintAuthority,
-            updateAuthority: mintAuthority,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            metadataProgram: TOKEN_METADATA_PROGRAM_ID,
-            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-            systemProgram: SystemProgram.programId,
-            rent: SYSVAR_RENT_PUBKEY,
+            question: statusQuestionPda,
+            keyTree: null,
             userState,
+            creator: user.publicKey,
           })
-          .signers([buyer])
+          .signers([user])
           .rpc();
-
         assert.fail('Expected transaction to fail');
       } catch (error: any) {
         const errorMessage = error.error?.errorMessage || error.message;
-        assert.include(errorMessage, 'Marketplace is paused');
-      } finally {
-        // unpause marketplace
-        await program.methods
-          .toggleMarketplace()
-          .accounts({
-            marketplace,
-            authority: authority.publicKey,
-          })
-          .signers([authority])
-          .rpc();
-        await sleep(2000);
+        assert.include(errorMessage, 'Invalid key count');
       }
     });
 
-    it('Fails to mint when max keys reached', async () => {
-      try {
+    it('Revises question content and archives the previous version', async () => {
+      const NEW_CONTENT_CID = 'QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG';
+      const NEW_CONTENT_HASH = Array(32).fill(2);
+
+      const before = await program.account.question.fetch(statusQuestionPda);
+      const [revisionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from('question_revision'),
+          statusQuestionPda.toBuffer(),
+          before.contentVersion.toArrayLike(Buffer, 'le', 8),
+        ],
+        program.programId,
+      );
+
+      await program.methods
+        .updateQuestionContent(NEW_CONTENT_CID, NEW_CONTENT_HASH)
+        .accounts({
+          marketplace,
+          question: statusQuestionPda,
+          revision: revisionPda,
+          userState,
+          creator: user.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      const questionAccount = await program.account.question.fetch(statusQuestionPda);
+      assert.equal(questionAccount.contentCid, NEW_CONTENT_CID);
+      assert.deepEqual(Array.from(questionAccount.contentHash), NEW_CONTENT_HASH);
+      assert.equal(questionAccount.contentVersion.toNumber(), before.contentVersion.toNumber() + 1);
+
+      const revisionAccount = await program.account.questionRevision.fetch(revisionPda);
+      assert.equal(revisionAccount.contentCid, before.contentCid);
+      assert.equal(revisionAccount.version.toNumber(), before.contentVersion.toNumber());
+    });
+
+    it('Prevents the creator from reactivating a taken down question', async () => {
+      await program.methods
+        .moderateQuestion(true, { abuse: {} })
+        .accounts({
+          marketplace,
+          question: statusQuestionPda,
+          authority: authority.publicKey,
+        })
+        .signers([authority])
+        .rpc();
+
+      const questionAccount = await program.account.question.fetch(statusQuestionPda);
+      assert.isFalse(questionAccount.isActive);
+      assert.isTrue(questionAccount.isTakenDown);
+
+      try {
+        await program.methods
+          .setQuestionActive(true)
+          .accounts({
+            marketplace,
+            question: statusQuestionPda,
+            creator: user.publicKey,
+          })
+          .signers([user])
+          .rpc();
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'Question was taken down by moderation');
+      }
+    });
+  });
+
+  describe('MintUnlockKey', () => {
+    let unlockKeyPda: PublicKey;
+    let keyMint: PublicKey;
+    beforeEach(async () => {
+      // airdrop SOL to buyer
+      const latestBlockhash = await provider.connection.getLatestBlockhash();
+
+      const signature = await provider.connection.requestAirdrop(buyer.publicKey, 2 * LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction({
+        signature,
+        ...latestBlockhash,
+      });
+
+      // ensure marketplace is not paused
+      const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
+      if (marketplaceAccount.paused) {
+        await program.methods
+          .toggleMarketplace()
+          .accounts({
+            marketplace,
+            authority: authority.publicKey,
+          })
+          .signers([authority])
+          .rpc();
+      }
+
+      const questionCounter = marketplaceAccount.questionCounter;
+      [questionPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from('question'), marketplace.toBuffer(), questionCounter.toArrayLike(Buffer, 'le', 8)],
+        program.programId,
+      );
+
+      // create question for this test scenario
+      await program.methods
+        .createQuestion(
+          CONTENT_CID,
+          CONTENT_HASH,
+          UNLOCK_PRICE,
+          MAX_KEYS,
+          FLAT_CURVE,
+          null,
+          TOKEN_PAYMENT,
+          ROYALTY_BPS,
+          [],
+        )
+        .accounts({
+          marketplace,
+          userState,
+          question: questionPda,
+          questionSplit: null,
+          paymentMintConfig: bonkPaymentMint,
+          creator: user.publicKey,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([user])
+        .rpc();
+
+      const questionAccount = await program.account.question.fetch(questionPda);
+      if (!questionAccount) {
+        throw new Error('Question account not initialized');
+      }
+
+      // find PDA for unlock key
+      const questionData = await program.account.question.fetch(questionPda);
+      [unlockKeyPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from('unlock_key'), questionPda.toBuffer(), questionData.currentKeys.toArrayLike(Buffer, 'le', 8)],
+        program.programId,
+      );
+
+      // find the NFT accounts for this unlock key
+      const keyNft = await findKeyNftAccounts(unlockKeyPda, buyer.publicKey);
+      keyMint = keyNft.mint;
+      metadata = keyNft.metadata;
+      masterEdition = keyNft.masterEdition;
+      buyerKeyAccount = keyNft.ownerKeyAccount;
+
+      // mint BONK to buyer if needed
+      await mintTo(
+        provider.connection,
+        authority,
+        bonkMint.publicKey,
+        buyerTokenAccount,
+        authority,
+        UNLOCK_PRICE.toNumber() * 2,
+      );
+    });
+
+    it('Successfully mints an unlock key', async () => {
+      await program.methods
+        .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY, UNLOCK_PRICE, new anchor.BN(0))
+        .accounts({
+          marketplace,
+          question: questionPda,
+          unlockKey: unlockKeyPda,
+          buyer: buyer.publicKey,
+          purchaseRecord: findPurchaseRecord(questionPda, buyer.publicKey),
+          refundEscrow: null,
+          refundEscrowTokens: null,
+          listingAuthority,
+          buyerTokenAccount,
+          ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+          questionSplit: null,
+          treasuryTokenAccount, // fees go here
+          paymentMint: bonkMint.publicKey,
+          paymentMintConfig: bonkPaymentMint,
+          treasury: null,
+          metadata,
+          masterEdition,
+          mint: keyMint,
+          buyerKeyAccount,
+          mintAuthority: mintAuthority,
+          updateAuthority: mintAuthority,
+          collectionMint: null,
+          collectionMetadata: null,
+          collectionMasterEdition: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          metadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+          userState,
+        })
+        .signers([buyer])
+        .rpc();
+
+      const unlockKeyAccount = await program.account.unlockKey.fetch(unlockKeyPda);
+
+      assert.ok(unlockKeyAccount.owner.equals(buyer.publicKey), 'Incorrect owner');
+      assert.ok(unlockKeyAccount.question.equals(questionPda));
+      assert.equal(unlockKeyAccount.tokenId.toNumber(), 0);
+      assert.deepEqual(Buffer.from(unlockKeyAccount.encryptedKey), ENCRYPTED_KEY);
+      assert.equal(unlockKeyAccount.isListed, false);
+      assert.equal(unlockKeyAccount.listPrice.toNumber(), 0);
+      assert.equal(unlockKeyAccount.metadataUri, PINATA_URI);
+      assert.ok(unlockKeyAccount.mint.equals(keyMint));
+
+      // verify the buyer holds the 1/1 key NFT
+      const keyTokenAccount = await getAccount(provider.connection, buyerKeyAccount);
+      assert.equal(keyTokenAccount.amount.toString(), '1');
+      const masterEditionInfo = await provider.connection.getAccountInfo(masterEdition);
+      assert.ok(masterEditionInfo, 'Master edition not created');
+
+      // verify token transfers
+      const platformFee = Math.floor(UNLOCK_PRICE.toNumber() * 0.05);
+      const creatorPayment = UNLOCK_PRICE.toNumber() - platformFee;
+
+      const { creatorVault, creatorVaultTokens } = findCreatorVault(user.publicKey, bonkMint.publicKey);
+      const buyerBalance = await provider.connection.getTokenAccountBalance(buyerTokenAccount);
+      const vaultBalance = await provider.connection.getTokenAccountBalance(creatorVaultTokens);
+      const treasuryBalance = await provider.connection.getTokenAccountBalance(treasuryTokenAccount);
+
+      assert.equal(treasuryBalance.value.amount, platformFee.toString());
+      assert.equal(vaultBalance.value.amount, creatorPayment.toString());
+
+      // the creator share accrues in the vault's lifetime earnings
+      const vaultAccount = await program.account.creatorVault.fetch(creatorVault);
+      assert.ok(vaultAccount.creator.equals(user.publicKey));
+      assert.ok(vaultAccount.paymentMint.equals(bonkMint.publicKey));
+      assert.equal(vaultAccount.totalEarned.toNumber(), creatorPayment);
+      assert.equal(vaultAccount.totalWithdrawn.toNumber(), 0);
+    });
+
+    it('Mints a free allowlisted key once per allocation', async () => {
+      // a single-leaf tree: the root is the leaf and the proof is empty
+      const price = new anchor.BN(0);
+      const maxQuantity = new anchor.BN(1);
+      const leaf = Buffer.from(
+        keccak_256(
+          Buffer.concat([
+            buyer.publicKey.toBuffer(),
+            price.toArrayLike(Buffer, 'le', 8),
+            maxQuantity.toArrayLike(Buffer, 'le', 8),
+          ]),
+        ),
+      );
+
+      await program.methods
+        .setAllowlistRoot(Array.from(leaf))
+        .accounts({
+          marketplace,
+          question: questionPda,
+          creator: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+      const [allowlistClaim] = PublicKey.findProgramAddressSync(
+        [Buffer.from('allowlist_claim'), questionPda.toBuffer(), buyer.publicKey.toBuffer()],
+        program.programId,
+      );
+      const mintWithProof = (keyPda: PublicKey, nft: Awaited<ReturnType<typeof findKeyNftAccounts>>) =>
+        program.methods
+          .mintUnlockKeyWithProof(PINATA_URI, ENCRYPTED_KEY, price, maxQuantity, [])
+          .accounts({
+            mintKey: {
+              marketplace,
+              question: questionPda,
+              unlockKey: keyPda,
+              buyer: buyer.publicKey,
+              purchaseRecord: findPurchaseRecord(questionPda, buyer.publicKey),
+              refundEscrow: null,
+              refundEscrowTokens: null,
+              listingAuthority,
+              buyerTokenAccount,
+              ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+              questionSplit: null,
+              treasuryTokenAccount,
+              paymentMint: bonkMint.publicKey,
+              paymentMintConfig: bonkPaymentMint,
+              treasury: null,
+              metadata: nft.metadata,
+              masterEdition: nft.masterEdition,
+              mint: nft.mint,
+              buyerKeyAccount: nft.ownerKeyAccount,
+              mintAuthority: mintAuthority,
+              updateAuthority: mintAuthority,
+              collectionMint: null,
+              collectionMetadata: null,
+              collectionMasterEdition: null,
+              tokenProgram: TOKEN_PROGRAM_ID,
+              paymentTokenProgram: TOKEN_PROGRAM_ID,
+              metadataProgram: TOKEN_METADATA_PROGRAM_ID,
+              associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+              systemProgram: SystemProgram.programId,
+              rent: SYSVAR_RENT_PUBKEY,
+              userState,
+            },
+            allowlistClaim,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([buyer])
+          .rpc();
+
+      const balanceBefore = (await getAccount(provider.connection, buyerTokenAccount)).amount;
+      await mintWithProof(unlockKeyPda, await findKeyNftAccounts(unlockKeyPda, buyer.publicKey));
+
+      const balanceAfter = (await getAccount(provider.connection, buyerTokenAccount)).amount;
+      assert.equal(balanceAfter.toString(), balanceBefore.toString(), 'Allowlisted key should be free');
+      const claim = await program.account.allowlistClaim.fetch(allowlistClaim);
+      assert.equal(claim.claimed.toNumber(), 1);
+
+      // the allocation is used up
+      const [nextKeyPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from('unlock_key'), questionPda.toBuffer(), new anchor.BN(1).toArrayLike(Buffer, 'le', 8)],
+        program.programId,
+      );
+      try {
+        await mintWithProof(nextKeyPda, await findKeyNftAccounts(nextKeyPda, buyer.publicKey));
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'Allowlist allocation already claimed');
+      }
+    });
+
+    it('Fails to mint when marketplace is paused', async () => {
+      try {
+        // pause marketplace
+        await program.methods
+          .toggleMarketplace()
+          .accounts({
+            marketplace,
+            authority: authority.publicKey,
+          })
+          .signers([authority])
+          .rpc();
+
+        await sleep(2000);
+
+        // verify marketplace is paused
+        const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
+        assert.isTrue(marketplaceAccount.paused, 'Marketplace should be paused');
+
+        // try to mint unlock key
+        await program.methods
+          .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY, UNLOCK_PRICE, new anchor.BN(0))
+          .accounts({
+            marketplace,
+            question: questionPda,
+            unlockKey: unlockKeyPda,
+            buyer: buyer.publicKey,
+            purchaseRecord: findPurchaseRecord(questionPda, buyer.publicKey),
+            refundEscrow: null,
+            refundEscrowTokens: null,
+            listingAuthority,
+            buyerTokenAccount,
+            ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+            questionSplit: null,
+            treasuryTokenAccount,
+            paymentMint: bonkMint.publicKey,
+            paymentMintConfig: bonkPaymentMint,
+            treasury: null,
+            metadata,
+            masterEdition,
+            mint: keyMint,
+            buyerKeyAccount,
+            mintAuthority,
+            updateAuthority: mintAuthority,
+            collectionMint: null,
+            collectionMetadata: null,
+            collectionMasterEdition: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+            metadataProgram: TOKEN_METADATA_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: SYSVAR_RENT_PUBKEY,
+            userState,
+          })
+          .signers([buyer])
+          .rpc();
+
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'Marketplace is paused');
+      } finally {
+        // unpause marketplace
+        await program.methods
+          .toggleMarketplace()
+          .accounts({
+            marketplace,
+            authority: authority.publicKey,
+          })
+          .signers([authority])
+          .rpc();
+        await sleep(2000);
+      }
+    });
+
+    it('Fails to mint when max keys reached', async () => {
+      try {
         // get current marketplace counter
         const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
         const currentCounter = marketplaceAccount.questionCounter;
@@ -527,11 +1138,23 @@ describe('myqa', function () {
         );
 
         await program.methods
-          .createQuestion(CONTENT_CID, CONTENT_HASH, UNLOCK_PRICE, new anchor.BN(1))
+          .createQuestion(
+            CONTENT_CID,
+            CONTENT_HASH,
+            UNLOCK_PRICE,
+            new anchor.BN(1),
+            FLAT_CURVE,
+            null,
+            TOKEN_PAYMENT,
+            ROYALTY_BPS,
+            [],
+          )
           .accounts({
             marketplace,
             userState,
             question: questionPdaLimited,
+            questionSplit: null,
+            paymentMintConfig: bonkPaymentMint,
             creator: user.publicKey,
             systemProgram: SystemProgram.programId,
             rent: SYSVAR_RENT_PUBKEY,
@@ -547,36 +1170,42 @@ describe('myqa', function () {
         // add delay between transactions
         await sleep(2000);
 
-        const firstNftMint = Keypair.generate();
-        await createMint(provider.connection, authority, mintAuthority, null, 0, firstNftMint);
-
-        const [firstMetadata] = PublicKey.findProgramAddressSync(
-          [Buffer.from('metadata'), TOKEN_METADATA_PROGRAM_ID.toBuffer(), firstNftMint.publicKey.toBuffer()],
-          TOKEN_METADATA_PROGRAM_ID,
-        );
-
         // get PDA for first unlock key using current_keys = 0
         const [firstKeyPda] = PublicKey.findProgramAddressSync(
           [Buffer.from('unlock_key'), questionPdaLimited.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, 'le', 8)],
           program.programId,
         );
+        const firstKeyNft = await findKeyNftAccounts(firstKeyPda, buyer.publicKey);
 
         await program.methods
-          .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY)
+          .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY, UNLOCK_PRICE, new anchor.BN(0))
           .accounts({
             marketplace,
             question: questionPdaLimited,
             unlockKey: firstKeyPda,
             buyer: buyer.publicKey,
+            purchaseRecord: findPurchaseRecord(questionPdaLimited, buyer.publicKey),
+            refundEscrow: null,
+            refundEscrowTokens: null,
+            listingAuthority,
             buyerTokenAccount,
-            creatorTokenAccount,
+            ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+            questionSplit: null,
             treasuryTokenAccount, // Use treasuryTokenAccount instead of platformTokenAccount
-            bonkMint: bonkMint.publicKey,
-            metadata: firstMetadata,
-            mint: firstNftMint.publicKey,
+            paymentMint: bonkMint.publicKey,
+            paymentMintConfig: bonkPaymentMint,
+            treasury: null,
+            metadata: firstKeyNft.metadata,
+            masterEdition: firstKeyNft.masterEdition,
+            mint: firstKeyNft.mint,
+            buyerKeyAccount: firstKeyNft.ownerKeyAccount,
             mintAuthority: mintAuthority,
             updateAuthority: mintAuthority,
+            collectionMint: null,
+            collectionMetadata: null,
+            collectionMasterEdition: null,
             tokenProgram: TOKEN_PROGRAM_ID,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
             metadataProgram: TOKEN_METADATA_PROGRAM_ID, // Use TOKEN_METADATA_PROGRAM_ID
             associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
             systemProgram: SystemProgram.programId,
@@ -594,37 +1223,43 @@ describe('myqa', function () {
         await sleep(2000);
 
         // second mint attempt - should fail
-        const secondNftMint = Keypair.generate();
-        await createMint(provider.connection, authority, mintAuthority, null, 0, secondNftMint);
-
-        const [secondMetadata] = PublicKey.findProgramAddressSync(
-          [Buffer.from('metadata'), TOKEN_METADATA_PROGRAM_ID.toBuffer(), secondNftMint.publicKey.toBuffer()],
-          TOKEN_METADATA_PROGRAM_ID,
-        );
-
         // use current_keys = 1 for second key attempt
         const [secondKeyPda] = PublicKey.findProgramAddressSync(
           [Buffer.from('unlock_key'), questionPdaLimited.toBuffer(), new anchor.BN(1).toArrayLike(Buffer, 'le', 8)],
           program.programId,
         );
+        const secondKeyNft = await findKeyNftAccounts(secondKeyPda, buyer.publicKey);
 
         // this should fail with NoKeysAvailable
         await program.methods
-          .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY)
+          .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY, UNLOCK_PRICE, new anchor.BN(1))
           .accounts({
             marketplace,
             question: questionPdaLimited,
             unlockKey: secondKeyPda,
             buyer: buyer.publicKey,
+            purchaseRecord: findPurchaseRecord(questionPdaLimited, buyer.publicKey),
+            refundEscrow: null,
+            refundEscrowTokens: null,
+            listingAuthority,
             buyerTokenAccount,
-            creatorTokenAccount,
+            ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+            questionSplit: null,
             treasuryTokenAccount, // Use treasuryTokenAccount instead of platformTokenAccount
-            bonkMint: bonkMint.publicKey,
-            metadata: secondMetadata,
-            mint: secondNftMint.publicKey,
+            paymentMint: bonkMint.publicKey,
+            paymentMintConfig: bonkPaymentMint,
+            treasury: null,
+            metadata: secondKeyNft.metadata,
+            masterEdition: secondKeyNft.masterEdition,
+            mint: secondKeyNft.mint,
+            buyerKeyAccount: secondKeyNft.ownerKeyAccount,
             mintAuthority: mintAuthority,
             updateAuthority: mintAuthority,
+            collectionMint: null,
+            collectionMetadata: null,
+            collectionMasterEdition: null,
             tokenProgram: TOKEN_PROGRAM_ID,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
             metadataProgram: TOKEN_METADATA_PROGRAM_ID, // Use TOKEN_METADATA_PROGRAM_ID
             associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
             systemProgram: SystemProgram.programId,
@@ -644,26 +1279,143 @@ describe('myqa', function () {
       }
     });
 
-    it('Prevents duplicate minting of the same token ID', async () => {
+    it('Mints a SOL-priced key with lamport payments', async () => {
+      const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
+      const [solQuestion] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from('question'),
+          marketplace.toBuffer(),
+          marketplaceAccount.questionCounter.toArrayLike(Buffer, 'le', 8),
+        ],
+        program.programId,
+      );
+
+      await program.methods
+        .createQuestion(
+          CONTENT_CID,
+          CONTENT_HASH,
+          UNLOCK_PRICE,
+          MAX_KEYS,
+          FLAT_CURVE,
+          null,
+          { sol: {} },
+          ROYALTY_BPS,
+          [],
+        )
+        .accounts({
+          marketplace,
+          userState,
+          question: solQuestion,
+          questionSplit: null,
+          paymentMintConfig: null,
+          creator: user.publicKey,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([user])
+        .rpc();
+      await sleep(2000);
+
+      const questionAccount = await program.account.question.fetch(solQuestion);
+      assert.deepEqual(questionAccount.paymentType, { sol: {} });
+
+      const [solKey] = PublicKey.findProgramAddressSync(
+        [Buffer.from('unlock_key'), solQuestion.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, 'le', 8)],
+        program.programId,
+      );
+      const solKeyNft = await findKeyNftAccounts(solKey, buyer.publicKey);
+      const treasuryBefore = await provider.connection.getBalance(treasury.publicKey);
+      const creatorBefore = await provider.connection.getBalance(user.publicKey);
+      const buyerTokensBefore = (await getAccount(provider.connection, buyerTokenAccount)).amount;
+
+      await program.methods
+        .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY, UNLOCK_PRICE, new anchor.BN(0))
+        .accounts({
+          marketplace,
+          question: solQuestion,
+          unlockKey: solKey,
+          buyer: buyer.publicKey,
+          purchaseRecord: findPurchaseRecord(solQuestion, buyer.publicKey),
+          refundEscrow: null,
+          refundEscrowTokens: null,
+          listingAuthority: null,
+          buyerTokenAccount: null,
+          ...findCreatorVault(user.publicKey, PublicKey.default),
+          creatorVaultTokens: null,
+          questionSplit: null,
+          treasuryTokenAccount: null,
+          paymentMint: null,
+          paymentMintConfig: null,
+          treasury: treasury.publicKey,
+          metadata: solKeyNft.metadata,
+          masterEdition: solKeyNft.masterEdition,
+          mint: solKeyNft.mint,
+          buyerKeyAccount: solKeyNft.ownerKeyAccount,
+          mintAuthority: mintAuthority,
+          updateAuthority: mintAuthority,
+          collectionMint: null,
+          collectionMetadata: null,
+          collectionMasterEdition: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          metadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+          userState,
+        })
+        .signers([buyer])
+        .rpc();
+
+      // same bps split as token payments, settled in lamports
+      const platformFee = UNLOCK_PRICE.muln(marketplaceAccount.platformFeeBps).divn(10000);
+      const treasuryAfter = await provider.connection.getBalance(treasury.publicKey);
+      const creatorAfter = await provider.connection.getBalance(user.publicKey);
+      assert.equal(treasuryAfter - treasuryBefore, platformFee.toNumber(), 'Treasury not paid in SOL');
+      assert.equal(
+        creatorAfter - creatorBefore,
+        UNLOCK_PRICE.sub(platformFee).toNumber(),
+        'Creator not paid in SOL',
+      );
+      const buyerTokensAfter = (await getAccount(provider.connection, buyerTokenAccount)).amount;
+      assert.equal(buyerTokensAfter.toString(), buyerTokensBefore.toString(), 'No tokens should move');
+
+      const keyAccount = await program.account.unlockKey.fetch(solKey);
+      assert.ok(keyAccount.owner.equals(buyer.publicKey));
+    });
+
+    it('Prevents duplicate minting of the same token ID', async () => {
       try {
-        // first mint - this should succeed (use nftMintLocal and its metadata)
+        // first mint - this should succeed
         await program.methods
-          .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY)
+          .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY, UNLOCK_PRICE, new anchor.BN(0))
           .accounts({
             marketplace,
             question: questionPda,
             unlockKey: unlockKeyPda,
             buyer: buyer.publicKey,
+            purchaseRecord: findPurchaseRecord(questionPda, buyer.publicKey),
+            refundEscrow: null,
+            refundEscrowTokens: null,
+            listingAuthority,
             buyerTokenAccount,
-            creatorTokenAccount,
+            ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+            questionSplit: null,
             treasuryTokenAccount,
-            bonkMint: bonkMint.publicKey,
-            // Use the metadata and mint derived from nftMintLocal
+            paymentMint: bonkMint.publicKey,
+            paymentMintConfig: bonkPaymentMint,
+            treasury: null,
             metadata,
-            mint: nftMintLocal.publicKey,
+            masterEdition,
+            mint: keyMint,
+            buyerKeyAccount,
             mintAuthority,
             updateAuthority: mintAuthority,
+            collectionMint: null,
+            collectionMetadata: null,
+            collectionMasterEdition: null,
             tokenProgram: TOKEN_PROGRAM_ID,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
             metadataProgram: TOKEN_METADATA_PROGRAM_ID,
             associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
             systemProgram: SystemProgram.programId,
@@ -676,38 +1428,42 @@ describe('myqa', function () {
         // add delay between transactions
         await sleep(2000);
 
-        // create a new NFT mint for the second attempt
-        const secondNftMint = Keypair.generate();
-        await createMint(provider.connection, authority, mintAuthority, null, 0, secondNftMint);
-
-        // find metadata for second attempt
-        const [secondMetadata] = PublicKey.findProgramAddressSync(
-          [Buffer.from('metadata'), TOKEN_METADATA_PROGRAM_ID.toBuffer(), secondNftMint.publicKey.toBuffer()],
-          TOKEN_METADATA_PROGRAM_ID,
-        );
-
         // try to mint again with the same PDA seeds
         const [duplicateUnlockKeyPda] = PublicKey.findProgramAddressSync(
           [Buffer.from('unlock_key'), questionPda.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, 'le', 8)],
           program.programId,
         );
+        const secondKeyNft = await findKeyNftAccounts(duplicateUnlockKeyPda, buyer.publicKey);
 
         await program.methods
-          .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY)
+          .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY, UNLOCK_PRICE, new anchor.BN(0))
           .accounts({
             marketplace,
             question: questionPda,
             unlockKey: duplicateUnlockKeyPda,
             buyer: buyer.publicKey,
+            purchaseRecord: findPurchaseRecord(questionPda, buyer.publicKey),
+            refundEscrow: null,
+            refundEscrowTokens: null,
+            listingAuthority,
             buyerTokenAccount,
-            creatorTokenAccount,
+            ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+            questionSplit: null,
             treasuryTokenAccount, // use treasuryTokenAccount
-            bonkMint: bonkMint.publicKey,
-            metadata: secondMetadata,
-            mint: secondNftMint.publicKey,
+            paymentMint: bonkMint.publicKey,
+            paymentMintConfig: bonkPaymentMint,
+            treasury: null,
+            metadata: secondKeyNft.metadata,
+            masterEdition: secondKeyNft.masterEdition,
+            mint: secondKeyNft.mint,
+            buyerKeyAccount: secondKeyNft.ownerKeyAccount,
             mintAuthority,
             updateAuthority: mintAuthority,
+            collectionMint: null,
+            collectionMetadata: null,
+            collectionMasterEdition: null,
             tokenProgram: TOKEN_PROGRAM_ID,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
             metadataProgram: TOKEN_METADATA_PROGRAM_ID, // Use TOKEN_METADATA_PROGRAM_ID
             associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
             systemProgram: SystemProgram.programId,
@@ -734,6 +1490,11 @@ describe('myqa', function () {
 
   describe('Listings', () => {
     let unlockKeyPda: PublicKey;
+    let keyMint: PublicKey;
+    let sellerKeyAccount: PublicKey;
+    let listingEscrow: PublicKey;
+    let pendingSale: PublicKey;
+    let saleEscrow: PublicKey;
     const LIST_PRICE = new anchor.BN(2_000_000); // 2 BONK (6 decimals)
     const UPDATED_PRICE = new anchor.BN(3_000_000); // 3 BONK (6 decimals)
     const CONTENT_CID = 'QmT8JtG98Pu6YqHrRxiANrqjaC8ydz3F4uuQvRfQqC3T45';
@@ -800,11 +1561,23 @@ describe('myqa', function () {
 
         // 5. create the question
         await program.methods
-          .createQuestion(CONTENT_CID, CONTENT_HASH, UNLOCK_PRICE, MAX_KEYS)
+          .createQuestion(
+            CONTENT_CID,
+            CONTENT_HASH,
+            UNLOCK_PRICE,
+            MAX_KEYS,
+            FLAT_CURVE,
+            null,
+            TOKEN_PAYMENT,
+            ROYALTY_BPS,
+            [],
+          )
           .accounts({
             marketplace: marketplace,
             userState: userState,
             question: questionPda,
+            questionSplit: null,
+            paymentMintConfig: bonkPaymentMint,
             creator: user.publicKey,
             systemProgram: SystemProgram.programId,
             rent: SYSVAR_RENT_PUBKEY,
@@ -814,32 +1587,23 @@ describe('myqa', function () {
 
         await sleep(1000);
 
-        // 6. create NFT mint
-        const nftMint = Keypair.generate();
-        await createMint(
-          provider.connection,
-          buyer, // Use buyer as payer
-          mintAuthority,
-          null,
-          0,
-          nftMint,
-        );
-
-        await sleep(1000);
-
-        // 7. find metadata PDA
-        [metadata] = PublicKey.findProgramAddressSync(
-          [Buffer.from('metadata'), TOKEN_METADATA_PROGRAM_ID.toBuffer(), nftMint.publicKey.toBuffer()],
-          TOKEN_METADATA_PROGRAM_ID,
-        );
-
-        // 8. find PDA for unlock key
+        // 6. find PDA for unlock key
         [unlockKeyPda] = PublicKey.findProgramAddressSync(
           [Buffer.from('unlock_key'), questionPda.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, 'le', 8)],
           program.programId,
         );
 
-        // 9. get or create associated token accounts
+        // 7. find the NFT accounts for the unlock key
+        const keyNft = await findKeyNftAccounts(unlockKeyPda, buyer.publicKey);
+        metadata = keyNft.metadata;
+        keyMint = keyNft.mint;
+        sellerKeyAccount = keyNft.ownerKeyAccount;
+        [listingEscrow] = PublicKey.findProgramAddressSync(
+          [Buffer.from('listing_escrow'), unlockKeyPda.toBuffer()],
+          program.programId,
+        );
+
+        // 8. get or create associated token accounts
         const buyerATA = await getAssociatedTokenAddress(bonkMint.publicKey, buyer.publicKey);
 
         const creatorATA = await getAssociatedTokenAddress(bonkMint.publicKey, user.publicKey);
@@ -867,7 +1631,7 @@ describe('myqa', function () {
 
         await sleep(1000);
 
-        // 10. mint BONK tokens to buyer
+        // 9. mint BONK tokens to buyer
         await mintTo(
           provider.connection,
           authority,
@@ -879,23 +1643,41 @@ describe('myqa', function () {
 
         await sleep(1000);
 
-        // 11. mint the unlock key
+        // 10. mint the unlock key
         await program.methods
-          .mintUnlockKey('https://example.com/metadata.json', Buffer.from('encrypted_key'))
+          .mintUnlockKey(
+            'https://example.com/metadata.json',
+            Buffer.from('encrypted_key'),
+            UNLOCK_PRICE,
+            new anchor.BN(0),
+          )
           .accounts({
             marketplace: marketplace,
             question: questionPda,
             unlockKey: unlockKeyPda,
             buyer: buyer.publicKey,
+            purchaseRecord: findPurchaseRecord(questionPda, buyer.publicKey),
+            refundEscrow: null,
+            refundEscrowTokens: null,
+            listingAuthority,
             buyerTokenAccount: buyerATA,
-            creatorTokenAccount: creatorATA,
+            ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+            questionSplit: null,
             treasuryTokenAccount: treasuryTokenAccount,
-            bonkMint: bonkMint.publicKey,
+            paymentMint: bonkMint.publicKey,
+            paymentMintConfig: bonkPaymentMint,
+            treasury: null,
             metadata,
-            mint: nftMint.publicKey,
+            masterEdition: keyNft.masterEdition,
+            mint: keyNft.mint,
+            buyerKeyAccount: keyNft.ownerKeyAccount,
             mintAuthority: mintAuthority,
             updateAuthority: mintAuthority,
+            collectionMint: null,
+            collectionMetadata: null,
+            collectionMasterEdition: null,
             tokenProgram: TOKEN_PROGRAM_ID,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
             metadataProgram: TOKEN_METADATA_PROGRAM_ID,
             associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
             systemProgram: SystemProgram.programId,
@@ -922,9 +1704,16 @@ describe('myqa', function () {
           .listKey(LIST_PRICE)
           .accounts({
             marketplace,
+            question: questionPda,
             unlockKey: unlockKeyPda,
             seller: buyer.publicKey,
+            sellerKeyAccount,
+            keyMint,
+            listingEscrow,
+            listingAuthority,
             userState,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
           })
           .signers([buyer])
           .rpc();
@@ -945,9 +1734,16 @@ describe('myqa', function () {
           .listKey(LIST_PRICE)
           .accounts({
             marketplace,
+            question: questionPda,
             unlockKey: unlockKeyPda,
             seller: buyer.publicKey,
+            sellerKeyAccount,
+            keyMint,
+            listingEscrow,
+            listingAuthority,
             userState,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
           })
           .signers([buyer])
           .rpc();
@@ -959,8 +1755,10 @@ describe('myqa', function () {
           .updateListing(UPDATED_PRICE)
           .accounts({
             marketplace,
+            question: questionPda,
             unlockKey: unlockKeyPda,
             seller: buyer.publicKey,
+            listingEscrow,
           })
           .signers([buyer])
           .rpc();
@@ -981,9 +1779,16 @@ describe('myqa', function () {
           .listKey(LIST_PRICE)
           .accounts({
             marketplace,
+            question: questionPda,
             unlockKey: unlockKeyPda,
             seller: buyer.publicKey,
+            sellerKeyAccount,
+            keyMint,
+            listingEscrow,
+            listingAuthority,
             userState,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
           })
           .signers([buyer])
           .rpc();
@@ -995,8 +1800,13 @@ describe('myqa', function () {
           .cancelListing()
           .accounts({
             marketplace,
+            question: questionPda,
             unlockKey: unlockKeyPda,
             seller: buyer.publicKey,
+            sellerKeyAccount,
+            listingEscrow,
+            listingAuthority,
+            tokenProgram: TOKEN_PROGRAM_ID,
           })
           .signers([buyer])
           .rpc();
@@ -1029,9 +1839,16 @@ describe('myqa', function () {
           .listKey(LIST_PRICE)
           .accounts({
             marketplace,
+            question: questionPda,
             unlockKey: unlockKeyPda,
             seller: buyer.publicKey,
+            sellerKeyAccount,
+            keyMint,
+            listingEscrow,
+            listingAuthority,
             userState,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
           })
           .signers([buyer])
           .rpc();
@@ -1059,9 +1876,16 @@ describe('myqa', function () {
           .listKey(new anchor.BN(0))
           .accounts({
             marketplace,
+            question: questionPda,
             unlockKey: unlockKeyPda,
             seller: buyer.publicKey,
+            sellerKeyAccount,
+            keyMint,
+            listingEscrow,
+            listingAuthority,
             userState,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
           })
           .signers([buyer])
           .rpc();
@@ -1077,6 +1901,9 @@ describe('myqa', function () {
   describe('Buy Unlock Keys', () => {
     let unlockKeyPda: PublicKey;
     let questionPda: PublicKey;
+    let keyMint: PublicKey;
+    let sellerKeyAccount: PublicKey;
+    let listingEscrow: PublicKey;
     const LIST_PRICE = new anchor.BN(2_000_000); // 2 BONK (6 decimals)
     const ENCRYPTED_KEY = Buffer.from('encrypted_key_data');
     const NEW_ENCRYPTED_KEY = Buffer.from('new_encrypted_key_data');
@@ -1187,11 +2014,23 @@ describe('myqa', function () {
         );
 
         await program.methods
-          .createQuestion(CONTENT_CID, CONTENT_HASH, UNLOCK_PRICE, MAX_KEYS)
+          .createQuestion(
+            CONTENT_CID,
+            CONTENT_HASH,
+            UNLOCK_PRICE,
+            MAX_KEYS,
+            FLAT_CURVE,
+            null,
+            TOKEN_PAYMENT,
+            ROYALTY_BPS,
+            [],
+          )
           .accounts({
             marketplace,
             userState,
             question: questionPda,
+            questionSplit: null,
+            paymentMintConfig: bonkPaymentMint,
             creator: user.publicKey,
             systemProgram: SystemProgram.programId,
             rent: SYSVAR_RENT_PUBKEY,
@@ -1201,21 +2040,30 @@ describe('myqa', function () {
 
         await sleep(1000);
 
-        // 5. create NFT mint and metadata
-        const nftMint = Keypair.generate();
-        await createMint(provider.connection, authority, mintAuthority, null, 0, nftMint);
-
-        [metadata] = PublicKey.findProgramAddressSync(
-          [Buffer.from('metadata'), TOKEN_METADATA_PROGRAM_ID.toBuffer(), nftMint.publicKey.toBuffer()],
-          TOKEN_METADATA_PROGRAM_ID,
-        );
-
-        // 6. find PDA for unlock key
+        // 5. find PDA for unlock key
         [unlockKeyPda] = PublicKey.findProgramAddressSync(
           [Buffer.from('unlock_key'), questionPda.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, 'le', 8)],
           program.programId,
         );
 
+        // 6. find the NFT accounts for the unlock key
+        const keyNft = await findKeyNftAccounts(unlockKeyPda, buyer.publicKey);
+        metadata = keyNft.metadata;
+        keyMint = keyNft.mint;
+        sellerKeyAccount = keyNft.ownerKeyAccount;
+        [listingEscrow] = PublicKey.findProgramAddressSync(
+          [Buffer.from('listing_escrow'), unlockKeyPda.toBuffer()],
+          program.programId,
+        );
+        [pendingSale] = PublicKey.findProgramAddressSync(
+          [Buffer.from('pending_sale'), unlockKeyPda.toBuffer()],
+          program.programId,
+        );
+        [saleEscrow] = PublicKey.findProgramAddressSync(
+          [Buffer.from('sale_escrow'), unlockKeyPda.toBuffer()],
+          program.programId,
+        );
+
         // 7. verify token accounts
         assert(buyerTokenAccount, 'Buyer token account not initialized');
         assert(newBuyerTokenAccount, 'New buyer token account not initialized');
@@ -1223,21 +2071,34 @@ describe('myqa', function () {
 
         // 8. mint key to original buyer
         await program.methods
-          .mintUnlockKey(METADATA_URI, ENCRYPTED_KEY)
+          .mintUnlockKey(METADATA_URI, ENCRYPTED_KEY, UNLOCK_PRICE, new anchor.BN(0))
           .accounts({
             marketplace,
             question: questionPda,
             unlockKey: unlockKeyPda,
             buyer: buyer.publicKey,
+            purchaseRecord: findPurchaseRecord(questionPda, buyer.publicKey),
+            refundEscrow: null,
+            refundEscrowTokens: null,
+            listingAuthority,
             buyerTokenAccount,
-            creatorTokenAccount,
+            ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+            questionSplit: null,
             treasuryTokenAccount,
-            bonkMint: bonkMint.publicKey,
+            paymentMint: bonkMint.publicKey,
+            paymentMintConfig: bonkPaymentMint,
+            treasury: null,
             metadata,
-            mint: nftMint.publicKey,
+            masterEdition: keyNft.masterEdition,
+            mint: keyNft.mint,
+            buyerKeyAccount: keyNft.ownerKeyAccount,
             mintAuthority,
             updateAuthority: mintAuthority,
+            collectionMint: null,
+            collectionMetadata: null,
+            collectionMasterEdition: null,
             tokenProgram: TOKEN_PROGRAM_ID,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
             metadataProgram: TOKEN_METADATA_PROGRAM_ID,
             associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
             systemProgram: SystemProgram.programId,
@@ -1254,9 +2115,16 @@ describe('myqa', function () {
           .listKey(LIST_PRICE)
           .accounts({
             marketplace,
+            question: questionPda,
             unlockKey: unlockKeyPda,
             seller: buyer.publicKey,
+            sellerKeyAccount,
+            keyMint,
+            listingEscrow,
+            listingAuthority,
             userState,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
           })
           .signers([buyer])
           .rpc();
@@ -1327,6 +2195,15 @@ describe('myqa', function () {
         );
         await sleep(1000);
 
+        // create the new buyer's token account for the key NFT
+        const newBuyerKeyAccount = await createAssociatedTokenAccount(
+          provider.connection,
+          newBuyer,
+          keyMint,
+          newBuyer.publicKey,
+        );
+        await sleep(1000);
+
         // Verify state before purchase
         const preUnlockKeyAccount = await program.account.unlockKey.fetch(unlockKeyPda);
         assert.isTrue(preUnlockKeyAccount.isListed, 'Key should be listed before purchase');
@@ -1334,24 +2211,61 @@ describe('myqa', function () {
 
         // Execute purchase
         await program.methods
-          .buyListedKey(NEW_ENCRYPTED_KEY)
+          .buyListedKey(LIST_PRICE, new anchor.BN(0))
           .accounts({
             marketplace,
             question: questionPda,
             unlockKey: unlockKeyPda,
             buyer: newBuyer.publicKey,
             buyerTokenAccount: newBuyerTokenAccount,
-            sellerTokenAccount: buyerTokenAccount,
-            creatorTokenAccount,
-            treasuryTokenAccount, // Use treasuryTokenAccount instead of platformTokenAccount
-            bonkMint: bonkMint.publicKey,
-            tokenProgram: TOKEN_PROGRAM_ID,
+            pendingSale,
+            saleEscrow,
+            listingAuthority,
+            paymentMint: bonkMint.publicKey,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
             systemProgram: SystemProgram.programId,
             userState: newBuyerState,
           })
           .signers([newBuyer])
           .rpc();
 
+        // payment is held in escrow until the seller re-encrypts the key
+        const sale = await program.account.pendingSale.fetch(pendingSale);
+        assert.ok(sale.buyer.equals(newBuyer.publicKey), 'Incorrect pending sale buyer');
+        assert.ok(sale.seller.equals(buyer.publicKey), 'Incorrect pending sale seller');
+        assert.equal(sale.price.toNumber(), LIST_PRICE.toNumber(), 'Incorrect pending sale price');
+        const saleEscrowToken = await getAccount(provider.connection, saleEscrow);
+        assert.equal(saleEscrowToken.amount.toString(), LIST_PRICE.toString(), 'Payment not escrowed');
+        const pendingKeyAccount = await program.account.unlockKey.fetch(unlockKeyPda);
+        assert.isFalse(pendingKeyAccount.isListed, 'Listing should be locked while the sale is pending');
+
+        await program.methods
+          .fulfillSale(NEW_ENCRYPTED_KEY)
+          .accounts({
+            marketplace,
+            question: questionPda,
+            unlockKey: unlockKeyPda,
+            pendingSale,
+            fulfiller: buyer.publicKey,
+            buyer: newBuyer.publicKey,
+            seller: buyer.publicKey,
+            saleEscrow,
+            listingEscrow,
+            treasuryTokenAccount,
+            sellerTokenAccount: buyerTokenAccount,
+            ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+            questionSplit: null,
+            treasury: null,
+            buyerKeyAccount: newBuyerKeyAccount,
+            listingAuthority,
+            paymentMint: bonkMint.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([buyer])
+          .rpc();
+
         // Verify the purchase
         const unlockKeyAccount = await program.account.unlockKey.fetch(unlockKeyPda);
         assert.equal(unlockKeyAccount.discriminator, 1, 'Incorrect unlock key discriminator');
@@ -1363,6 +2277,16 @@ describe('myqa', function () {
         );
         assert.isFalse(unlockKeyAccount.isListed, 'Key should not be listed after purchase');
         assert.equal(unlockKeyAccount.listPrice.toNumber(), 0, 'List price should be reset to 0');
+
+        // verify the key NFT moved with the sale
+        const newBuyerKeyToken = await getAccount(provider.connection, newBuyerKeyAccount);
+        assert.equal(newBuyerKeyToken.amount.toString(), '1', 'Key NFT not transferred to buyer');
+        const sellerKeyToken = await getAccount(provider.connection, sellerKeyAccount);
+        assert.equal(sellerKeyToken.amount.toString(), '0', 'Seller should no longer hold the key NFT');
+        const escrowInfo = await provider.connection.getAccountInfo(listingEscrow);
+        assert.isNull(escrowInfo, 'Listing escrow should be closed after the sale');
+        assert.isNull(await provider.connection.getAccountInfo(saleEscrow), 'Sale escrow should be closed');
+        assert.isNull(await provider.connection.getAccountInfo(pendingSale), 'Pending sale should be closed');
       } catch (error) {
         console.error('Buy listed key error:', error);
         if ('logs' in error) {
@@ -1372,6 +2296,141 @@ describe('myqa', function () {
       }
     });
 
+    // an attacker's own marketplace, with the attacker as its key custodian and treasury
+    const createForeignMarketplace = async () => {
+      const attacker = Keypair.generate();
+      const latestBlockhash = await provider.connection.getLatestBlockhash();
+      const signature = await provider.connection.requestAirdrop(attacker.publicKey, 2 * LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction({ signature, ...latestBlockhash });
+      await sleep(1000);
+
+      const [foreignMarketplace] = PublicKey.findProgramAddressSync(
+        [Buffer.from('marketplace'), attacker.publicKey.toBuffer()],
+        program.programId,
+      );
+      await program.methods
+        .initialize()
+        .accounts({
+          marketplace: foreignMarketplace,
+          authority: attacker.publicKey,
+          treasury: attacker.publicKey,
+          bonkMint: bonkMint.publicKey,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([attacker])
+        .rpc();
+      await sleep(1000);
+
+      return { attacker, foreignMarketplace };
+    };
+
+    it('Fails to buy a listed key through a foreign marketplace', async () => {
+      const { foreignMarketplace } = await createForeignMarketplace();
+      const [newBuyerState] = PublicKey.findProgramAddressSync(
+        [Buffer.from('user_state'), newBuyer.publicKey.toBuffer()],
+        program.programId,
+      );
+      const newBuyerPaymentAccount = await getAssociatedTokenAddress(bonkMint.publicKey, newBuyer.publicKey);
+
+      try {
+        await program.methods
+          .buyListedKey(LIST_PRICE, new anchor.BN(0))
+          .accounts({
+            marketplace: foreignMarketplace,
+            question: questionPda,
+            unlockKey: unlockKeyPda,
+            buyer: newBuyer.publicKey,
+            buyerTokenAccount: newBuyerPaymentAccount,
+            pendingSale,
+            saleEscrow,
+            listingAuthority,
+            paymentMint: bonkMint.publicKey,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            userState: newBuyerState,
+          })
+          .signers([newBuyer])
+          .rpc();
+
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'A seeds constraint was violated');
+      }
+
+      assert.isNull(await provider.connection.getAccountInfo(pendingSale), 'No pending sale should be opened');
+    });
+
+    it('Fails to fulfill a sale through a foreign marketplace', async () => {
+      const { attacker, foreignMarketplace } = await createForeignMarketplace();
+      const [newBuyerState] = PublicKey.findProgramAddressSync(
+        [Buffer.from('user_state'), newBuyer.publicKey.toBuffer()],
+        program.programId,
+      );
+      const newBuyerPaymentAccount = await getAssociatedTokenAddress(bonkMint.publicKey, newBuyer.publicKey);
+      const newBuyerKeyAccount = await getAssociatedTokenAddress(keyMint, newBuyer.publicKey);
+      await createAssociatedTokenAccount(provider.connection, newBuyer, keyMint, newBuyer.publicKey);
+      await sleep(1000);
+
+      await program.methods
+        .buyListedKey(LIST_PRICE, new anchor.BN(0))
+        .accounts({
+          marketplace,
+          question: questionPda,
+          unlockKey: unlockKeyPda,
+          buyer: newBuyer.publicKey,
+          buyerTokenAccount: newBuyerPaymentAccount,
+          pendingSale,
+          saleEscrow,
+          listingAuthority,
+          paymentMint: bonkMint.publicKey,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          userState: newBuyerState,
+        })
+        .signers([newBuyer])
+        .rpc();
+      await sleep(1000);
+
+      try {
+        await program.methods
+          .fulfillSale(NEW_ENCRYPTED_KEY)
+          .accounts({
+            marketplace: foreignMarketplace,
+            question: questionPda,
+            unlockKey: unlockKeyPda,
+            pendingSale,
+            fulfiller: attacker.publicKey,
+            buyer: newBuyer.publicKey,
+            seller: buyer.publicKey,
+            saleEscrow,
+            listingEscrow,
+            treasuryTokenAccount,
+            sellerTokenAccount: buyerTokenAccount,
+            ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+            questionSplit: null,
+            treasury: null,
+            buyerKeyAccount: newBuyerKeyAccount,
+            listingAuthority,
+            paymentMint: bonkMint.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([attacker])
+          .rpc();
+
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'A seeds constraint was violated');
+      }
+
+      const sale = await program.account.pendingSale.fetch(pendingSale);
+      assert.ok(sale.buyer.equals(newBuyer.publicKey), 'Pending sale should be untouched');
+    });
+
     it('Fails to buy when marketplace is paused', async () => {
       try {
         // initialize newBuyerTokenAccount if not already done
@@ -1431,18 +2490,18 @@ describe('myqa', function () {
         }
 
         await program.methods
-          .buyListedKey(NEW_ENCRYPTED_KEY)
+          .buyListedKey(LIST_PRICE, new anchor.BN(0))
           .accounts({
             marketplace,
             question: questionPda,
             unlockKey: unlockKeyPda,
             buyer: newBuyer.publicKey,
             buyerTokenAccount: newBuyerTokenAccount,
-            sellerTokenAccount: buyerTokenAccount,
-            creatorTokenAccount,
-            treasuryTokenAccount, // replaced platformTokenAccount with treasuryTokenAccount
-            bonkMint: bonkMint.publicKey,
-            tokenProgram: TOKEN_PROGRAM_ID,
+            pendingSale,
+            saleEscrow,
+            listingAuthority,
+            paymentMint: bonkMint.publicKey,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
             systemProgram: SystemProgram.programId,
             userState: newBuyerState,
           })
@@ -1473,18 +2532,18 @@ describe('myqa', function () {
     it('Fails to buy own listed key', async () => {
       try {
         await program.methods
-          .buyListedKey(NEW_ENCRYPTED_KEY)
+          .buyListedKey(LIST_PRICE, new anchor.BN(0))
           .accounts({
             marketplace,
             question: questionPda,
             unlockKey: unlockKeyPda,
             buyer: buyer.publicKey,
             buyerTokenAccount,
-            sellerTokenAccount: buyerTokenAccount,
-            creatorTokenAccount,
-            treasuryTokenAccount, // replaced platformTokenAccount with treasuryTokenAccount
-            bonkMint: bonkMint.publicKey,
-            tokenProgram: TOKEN_PROGRAM_ID,
+            pendingSale,
+            saleEscrow,
+            listingAuthority,
+            paymentMint: bonkMint.publicKey,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
             systemProgram: SystemProgram.programId,
             userState,
           })
@@ -1498,6 +2557,40 @@ describe('myqa', function () {
       }
     });
 
+    it('Fails to buy when the listing price exceeds the buyer maximum', async () => {
+      const newBuyerTokenAccount = await getAssociatedTokenAddress(bonkMint.publicKey, newBuyer.publicKey);
+      const [newBuyerState] = PublicKey.findProgramAddressSync(
+        [Buffer.from('user_state'), newBuyer.publicKey.toBuffer()],
+        program.programId,
+      );
+
+      try {
+        await program.methods
+          .buyListedKey(LIST_PRICE.subn(1), new anchor.BN(0))
+          .accounts({
+            marketplace,
+            question: questionPda,
+            unlockKey: unlockKeyPda,
+            buyer: newBuyer.publicKey,
+            buyerTokenAccount: newBuyerTokenAccount,
+            pendingSale,
+            saleEscrow,
+            listingAuthority,
+            paymentMint: bonkMint.publicKey,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            userState: newBuyerState,
+          })
+          .signers([newBuyer])
+          .rpc();
+
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, "Price exceeds the buyer's maximum");
+      }
+    });
+
     it('Fails to buy with insufficient funds', async () => {
       try {
         const poorBuyer = Keypair.generate();
@@ -1542,18 +2635,18 @@ describe('myqa', function () {
 
         try {
           await program.methods
-            .buyListedKey(NEW_ENCRYPTED_KEY)
+            .buyListedKey(LIST_PRICE, new anchor.BN(0))
             .accounts({
               marketplace,
               question: questionPda,
               unlockKey: unlockKeyPda,
               buyer: poorBuyer.publicKey,
               buyerTokenAccount: poorBuyerTokenAccount,
-              sellerTokenAccount: buyerTokenAccount,
-              creatorTokenAccount,
-              treasuryTokenAccount, // replaced platformTokenAccount with treasuryTokenAccount
-              bonkMint: bonkMint.publicKey,
-              tokenProgram: TOKEN_PROGRAM_ID,
+              pendingSale,
+              saleEscrow,
+              listingAuthority,
+              paymentMint: bonkMint.publicKey,
+              paymentTokenProgram: TOKEN_PROGRAM_ID,
               systemProgram: SystemProgram.programId,
               userState: poorBuyerState,
             })
@@ -1577,6 +2670,1909 @@ describe('myqa', function () {
         throw error;
       }
     });
+
+    it('Syncs the key owner after the NFT moves outside the marketplace', async () => {
+      const newBuyerKeyAccount = await getAssociatedTokenAddress(keyMint, newBuyer.publicKey);
+      try {
+        await getAccount(provider.connection, newBuyerKeyAccount);
+      } catch {
+        await createAssociatedTokenAccount(provider.connection, authority, keyMint, newBuyer.publicKey);
+        await sleep(1000);
+      }
+
+      // take the key out of escrow, then move it with a plain token transfer
+      await program.methods
+        .cancelListing()
+        .accounts({
+          marketplace,
+          question: questionPda,
+          unlockKey: unlockKeyPda,
+          seller: buyer.publicKey,
+          sellerKeyAccount,
+          listingEscrow,
+          listingAuthority,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+      await sleep(1000);
+
+      await transfer(provider.connection, buyer, sellerKeyAccount, newBuyerKeyAccount, buyer, 1);
+      await sleep(1000);
+
+      await program.methods
+        .syncKeyOwner()
+        .accounts({
+          marketplace,
+          question: questionPda,
+          unlockKey: unlockKeyPda,
+          holder: newBuyer.publicKey,
+          holderKeyAccount: newBuyerKeyAccount,
+        })
+        .signers([newBuyer])
+        .rpc();
+
+      // the old owner's key material is dropped until the key is re-encrypted
+      let unlockKeyAccount = await program.account.unlockKey.fetch(unlockKeyPda);
+      assert.ok(unlockKeyAccount.owner.equals(newBuyer.publicKey), 'Owner not synced to NFT holder');
+      assert.isTrue(unlockKeyAccount.reencryptionPending, 'Key should wait for re-encryption');
+      assert.equal(unlockKeyAccount.encryptedKey.length, 0, 'Previous encrypted key should be cleared');
+
+      const reencrypt = (signer: Keypair) =>
+        program.methods
+          .reencryptKey(NEW_ENCRYPTED_KEY)
+          .accounts({
+            marketplace,
+            question: questionPda,
+            unlockKey: unlockKeyPda,
+            signer: signer.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([signer])
+          .rpc();
+
+      // the new holder cannot supply their own key material
+      try {
+        await reencrypt(newBuyer);
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'Only the key custodian or question creator can re-encrypt keys');
+      }
+
+      await reencrypt(user);
+      unlockKeyAccount = await program.account.unlockKey.fetch(unlockKeyPda);
+      assert.isFalse(unlockKeyAccount.reencryptionPending, 'Key should no longer be pending');
+      assert.deepEqual(Array.from(unlockKeyAccount.encryptedKey), Array.from(NEW_ENCRYPTED_KEY));
+
+      // the previous owner can no longer list the key
+      try {
+        await program.methods
+          .listKey(LIST_PRICE)
+          .accounts({
+            marketplace,
+            question: questionPda,
+            unlockKey: unlockKeyPda,
+            seller: buyer.publicKey,
+            sellerKeyAccount,
+            keyMint,
+            listingEscrow,
+            listingAuthority,
+            userState,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([buyer])
+          .rpc();
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'Not the key owner');
+      }
+    });
+
+    it('Refunds the buyer when a pending sale is not fulfilled in time', async () => {
+      const [newBuyerState] = PublicKey.findProgramAddressSync(
+        [Buffer.from('user_state'), newBuyer.publicKey.toBuffer()],
+        program.programId,
+      );
+      newBuyerTokenAccount = await getAssociatedTokenAddress(bonkMint.publicKey, newBuyer.publicKey);
+      const balanceBefore = (await getAccount(provider.connection, newBuyerTokenAccount)).amount;
+
+      await program.methods
+        .buyListedKey(LIST_PRICE, new anchor.BN(0))
+        .accounts({
+          marketplace,
+          question: questionPda,
+          unlockKey: unlockKeyPda,
+          buyer: newBuyer.publicKey,
+          buyerTokenAccount: newBuyerTokenAccount,
+          pendingSale,
+          saleEscrow,
+          listingAuthority,
+          paymentMint: bonkMint.publicKey,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          userState: newBuyerState,
+        })
+        .signers([newBuyer])
+        .rpc();
+
+      const reclaim = () =>
+        program.methods
+          .reclaimSale()
+          .accounts({
+            unlockKey: unlockKeyPda,
+            pendingSale,
+            buyer: newBuyer.publicKey,
+            buyerTokenAccount: newBuyerTokenAccount,
+            saleEscrow,
+            listingAuthority,
+            paymentMint: bonkMint.publicKey,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([newBuyer])
+          .rpc();
+
+      // the seller still has time to fulfill
+      try {
+        await reclaim();
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'Sale fulfillment window has not expired yet');
+      }
+
+      // wait out the test fulfillment window
+      await sleep(12000);
+      await reclaim();
+
+      const balanceAfter = (await getAccount(provider.connection, newBuyerTokenAccount)).amount;
+      assert.equal(balanceAfter.toString(), balanceBefore.toString(), 'Buyer not refunded');
+      const unlockKeyAccount = await program.account.unlockKey.fetch(unlockKeyPda);
+      assert.isTrue(unlockKeyAccount.isListed, 'Key should be listed again after the refund');
+      assert.ok(unlockKeyAccount.owner.equals(buyer.publicKey), 'Owner should not change');
+      assert.isNull(await provider.connection.getAccountInfo(pendingSale), 'Pending sale should be closed');
+    });
+
+    it('Accepts an escrowed offer on an unlisted key', async () => {
+      newBuyerTokenAccount = await getAssociatedTokenAddress(bonkMint.publicKey, newBuyer.publicKey);
+      const newBuyerKeyAccount = await createAssociatedTokenAccount(
+        provider.connection,
+        newBuyer,
+        keyMint,
+        newBuyer.publicKey,
+      );
+      const OFFER_PRICE = new anchor.BN(1_500_000);
+      const [offer] = PublicKey.findProgramAddressSync(
+        [Buffer.from('offer'), unlockKeyPda.toBuffer(), newBuyer.publicKey.toBuffer()],
+        program.programId,
+      );
+      const [offerEscrow] = PublicKey.findProgramAddressSync(
+        [Buffer.from('offer_escrow'), offer.toBuffer()],
+        program.programId,
+      );
+
+      await program.methods
+        .makeOffer(unlockKeyPda, OFFER_PRICE)
+        .accounts({
+          marketplace,
+          question: questionPda,
+          unlockKey: unlockKeyPda,
+          offer,
+          offerEscrow,
+          bidder: newBuyer.publicKey,
+          bidderTokenAccount: newBuyerTokenAccount,
+          listingAuthority,
+          paymentMint: bonkMint.publicKey,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([newBuyer])
+        .rpc();
+
+      const escrowToken = await getAccount(provider.connection, offerEscrow);
+      assert.equal(escrowToken.amount.toString(), OFFER_PRICE.toString(), 'Offer not escrowed');
+
+      // offers are accepted on unlisted keys held by the seller
+      await program.methods
+        .cancelListing()
+        .accounts({
+          marketplace,
+          question: questionPda,
+          unlockKey: unlockKeyPda,
+          seller: buyer.publicKey,
+          sellerKeyAccount,
+          listingEscrow,
+          listingAuthority,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+
+      const sellerBalanceBefore = (await getAccount(provider.connection, buyerTokenAccount)).amount;
+
+      await program.methods
+        .acceptOffer(NEW_ENCRYPTED_KEY)
+        .accounts({
+          marketplace,
+          question: questionPda,
+          unlockKey: unlockKeyPda,
+          offer,
+          offerEscrow,
+          seller: buyer.publicKey,
+          bidder: newBuyer.publicKey,
+          sellerKeyAccount,
+          bidderKeyAccount: newBuyerKeyAccount,
+          treasuryTokenAccount,
+          sellerTokenAccount: buyerTokenAccount,
+          ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+          questionSplit: null,
+          listingAuthority,
+          paymentMint: bonkMint.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+      const unlockKeyAccount = await program.account.unlockKey.fetch(unlockKeyPda);
+      assert.ok(unlockKeyAccount.owner.equals(newBuyer.publicKey), 'Ownership not transferred');
+      assert.deepEqual(Array.from(unlockKeyAccount.encryptedKey), Array.from(NEW_ENCRYPTED_KEY));
+      const bidderKeyToken = await getAccount(provider.connection, newBuyerKeyAccount);
+      assert.equal(bidderKeyToken.amount.toString(), '1', 'Key NFT not transferred to bidder');
+
+      // 5% platform fee and 2% creator royalty leave 93% for the seller
+      const sellerBalanceAfter = (await getAccount(provider.connection, buyerTokenAccount)).amount;
+      assert.equal(
+        (sellerBalanceAfter - sellerBalanceBefore).toString(),
+        ((OFFER_PRICE.toNumber() * 93) / 100).toString(),
+        'Incorrect seller payment',
+      );
+      assert.isNull(await provider.connection.getAccountInfo(offer), 'Offer should be closed');
+    });
+
+    it('Cancels a collection-wide offer and refunds the bidder', async () => {
+      newBuyerTokenAccount = await getAssociatedTokenAddress(bonkMint.publicKey, newBuyer.publicKey);
+      const balanceBefore = (await getAccount(provider.connection, newBuyerTokenAccount)).amount;
+      const [offer] = PublicKey.findProgramAddressSync(
+        [Buffer.from('offer'), questionPda.toBuffer(), newBuyer.publicKey.toBuffer()],
+        program.programId,
+      );
+      const [offerEscrow] = PublicKey.findProgramAddressSync(
+        [Buffer.from('offer_escrow'), offer.toBuffer()],
+        program.programId,
+      );
+
+      await program.methods
+        .makeOffer(questionPda, LIST_PRICE)
+        .accounts({
+          marketplace,
+          question: questionPda,
+          unlockKey: null,
+          offer,
+          offerEscrow,
+          bidder: newBuyer.publicKey,
+          bidderTokenAccount: newBuyerTokenAccount,
+          listingAuthority,
+          paymentMint: bonkMint.publicKey,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([newBuyer])
+        .rpc();
+
+      const offerAccount = await program.account.offer.fetch(offer);
+      assert.isNull(offerAccount.unlockKey, 'Collection-wide offer should not target a key');
+
+      await program.methods
+        .cancelOffer()
+        .accounts({
+          offer,
+          offerEscrow,
+          bidder: newBuyer.publicKey,
+          bidderTokenAccount: newBuyerTokenAccount,
+          listingAuthority,
+          paymentMint: bonkMint.publicKey,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([newBuyer])
+        .rpc();
+
+      const balanceAfter = (await getAccount(provider.connection, newBuyerTokenAccount)).amount;
+      assert.equal(balanceAfter.toString(), balanceBefore.toString(), 'Bidder not refunded');
+      assert.isNull(await provider.connection.getAccountInfo(offerEscrow), 'Offer escrow should be closed');
+    });
+
+    const startAuction = async (
+      auctionType: any,
+      startPrice: anchor.BN,
+      reservePrice: anchor.BN,
+      endPrice: anchor.BN,
+      duration: number,
+    ) => {
+      const [auction] = PublicKey.findProgramAddressSync(
+        [Buffer.from('auction'), unlockKeyPda.toBuffer()],
+        program.programId,
+      );
+      const [auctionEscrow] = PublicKey.findProgramAddressSync(
+        [Buffer.from('auction_escrow'), auction.toBuffer()],
+        program.programId,
+      );
+
+      // auctions take custody of unlisted keys
+      await program.methods
+        .cancelListing()
+        .accounts({
+          marketplace,
+          question: questionPda,
+          unlockKey: unlockKeyPda,
+          seller: buyer.publicKey,
+          sellerKeyAccount,
+          listingEscrow,
+          listingAuthority,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+
+      await program.methods
+        .createAuction(auctionType, startPrice, reservePrice, endPrice, new anchor.BN(100_000), new anchor.BN(duration))
+        .accounts({
+          marketplace,
+          question: questionPda,
+          unlockKey: unlockKeyPda,
+          auction,
+          auctionEscrow,
+          seller: buyer.publicKey,
+          sellerKeyAccount,
+          keyMint,
+          listingEscrow,
+          listingAuthority,
+          paymentMint: bonkMint.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+      return { auction, auctionEscrow };
+    };
+
+    const settleAuction = async (auction: PublicKey, auctionEscrow: PublicKey, winnerKeyAccount: PublicKey) =>
+      program.methods
+        .settleAuction(NEW_ENCRYPTED_KEY)
+        .accounts({
+          marketplace,
+          question: questionPda,
+          unlockKey: unlockKeyPda,
+          auction,
+          auctionEscrow,
+          listingEscrow,
+          settler: buyer.publicKey,
+          seller: buyer.publicKey,
+          winnerKeyAccount,
+          treasuryTokenAccount,
+          sellerTokenAccount: buyerTokenAccount,
+          ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+          questionSplit: null,
+          listingAuthority,
+          paymentMint: bonkMint.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+    it('Runs an English auction and settles it to the highest bidder', async () => {
+      newBuyerTokenAccount = await getAssociatedTokenAddress(bonkMint.publicKey, newBuyer.publicKey);
+      const newBuyerKeyAccount = await createAssociatedTokenAccount(
+        provider.connection,
+        newBuyer,
+        keyMint,
+        newBuyer.publicKey,
+      );
+      const { auction, auctionEscrow } = await startAuction(
+        { english: {} },
+        LIST_PRICE,
+        LIST_PRICE,
+        new anchor.BN(0),
+        4,
+      );
+
+      const bid = (amount: anchor.BN) =>
+        program.methods
+          .placeBid(amount)
+          .accounts({
+            marketplace,
+            question: questionPda,
+            auction,
+            auctionEscrow,
+            bidder: newBuyer.publicKey,
+            bidderTokenAccount: newBuyerTokenAccount,
+            previousBidderTokenAccount: null,
+            listingAuthority,
+            paymentMint: bonkMint.publicKey,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([newBuyer])
+          .rpc();
+
+      // bids must meet the opening price
+      try {
+        await bid(LIST_PRICE.subn(1));
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'Bid is below the minimum accepted amount');
+      }
+
+      await bid(LIST_PRICE);
+      const escrowToken = await getAccount(provider.connection, auctionEscrow);
+      assert.equal(escrowToken.amount.toString(), LIST_PRICE.toString(), 'Bid not escrowed');
+
+      // cannot settle while bidding is still open
+      try {
+        await settleAuction(auction, auctionEscrow, newBuyerKeyAccount);
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'Auction has not ended yet');
+      }
+
+      await sleep(6000);
+      await settleAuction(auction, auctionEscrow, newBuyerKeyAccount);
+
+      const unlockKeyAccount = await program.account.unlockKey.fetch(unlockKeyPda);
+      assert.ok(unlockKeyAccount.owner.equals(newBuyer.publicKey), 'Winner did not receive the key');
+      assert.equal(unlockKeyAccount.lastSoldPrice.toNumber(), LIST_PRICE.toNumber());
+      const winnerKeyToken = await getAccount(provider.connection, newBuyerKeyAccount);
+      assert.equal(winnerKeyToken.amount.toString(), '1', 'Key NFT not transferred to winner');
+      assert.isNull(await provider.connection.getAccountInfo(auction), 'Auction should be closed');
+    });
+
+    it('Sells a Dutch auction to the first bid at the current price', async () => {
+      newBuyerTokenAccount = await getAssociatedTokenAddress(bonkMint.publicKey, newBuyer.publicKey);
+      const newBuyerKeyAccount = await createAssociatedTokenAccount(
+        provider.connection,
+        newBuyer,
+        keyMint,
+        newBuyer.publicKey,
+      );
+      const START_PRICE = new anchor.BN(3_000_000);
+      const END_PRICE = new anchor.BN(1_000_000);
+      const { auction, auctionEscrow } = await startAuction({ dutch: {} }, START_PRICE, END_PRICE, END_PRICE, 60);
+
+      await program.methods
+        .placeBid(START_PRICE)
+        .accounts({
+          marketplace,
+          question: questionPda,
+          auction,
+          auctionEscrow,
+          bidder: newBuyer.publicKey,
+          bidderTokenAccount: newBuyerTokenAccount,
+          previousBidderTokenAccount: null,
+          listingAuthority,
+          paymentMint: bonkMint.publicKey,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([newBuyer])
+        .rpc();
+
+      // the winning bid only pays the decayed price and closes the auction
+      const auctionAccount = await program.account.auction.fetch(auction);
+      assert.ok(auctionAccount.highestBidder.equals(newBuyer.publicKey));
+      assert.isAtMost(auctionAccount.highestBid.toNumber(), START_PRICE.toNumber());
+      assert.isAtLeast(auctionAccount.highestBid.toNumber(), END_PRICE.toNumber());
+
+      await settleAuction(auction, auctionEscrow, newBuyerKeyAccount);
+
+      const unlockKeyAccount = await program.account.unlockKey.fetch(unlockKeyPda);
+      assert.ok(unlockKeyAccount.owner.equals(newBuyer.publicKey), 'Winner did not receive the key');
+      assert.equal(unlockKeyAccount.lastSoldPrice.toNumber(), auctionAccount.highestBid.toNumber());
+    });
+
+    it('Refunds an English auction that ends below its reserve', async () => {
+      newBuyerTokenAccount = await getAssociatedTokenAddress(bonkMint.publicKey, newBuyer.publicKey);
+      const newBuyerKeyAccount = await createAssociatedTokenAccount(
+        provider.connection,
+        newBuyer,
+        keyMint,
+        newBuyer.publicKey,
+      );
+      const RESERVE_PRICE = LIST_PRICE.muln(2);
+      const { auction, auctionEscrow } = await startAuction(
+        { english: {} },
+        LIST_PRICE,
+        RESERVE_PRICE,
+        new anchor.BN(0),
+        4,
+      );
+      const balanceBefore = (await getAccount(provider.connection, newBuyerTokenAccount)).amount;
+
+      await program.methods
+        .placeBid(LIST_PRICE)
+        .accounts({
+          marketplace,
+          question: questionPda,
+          auction,
+          auctionEscrow,
+          bidder: newBuyer.publicKey,
+          bidderTokenAccount: newBuyerTokenAccount,
+          previousBidderTokenAccount: null,
+          listingAuthority,
+          paymentMint: bonkMint.publicKey,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([newBuyer])
+        .rpc();
+
+      await sleep(6000);
+      try {
+        await settleAuction(auction, auctionEscrow, newBuyerKeyAccount);
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, "Highest bid is below the auction's reserve price");
+      }
+
+      // the unmet bid can be unwound as soon as bidding ends
+      await program.methods
+        .cancelAuction()
+        .accounts({
+          unlockKey: unlockKeyPda,
+          question: questionPda,
+          auction,
+          auctionEscrow,
+          listingEscrow,
+          caller: newBuyer.publicKey,
+          seller: buyer.publicKey,
+          sellerKeyAccount,
+          bidderTokenAccount: newBuyerTokenAccount,
+          listingAuthority,
+          paymentMint: bonkMint.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([newBuyer])
+        .rpc();
+
+      const balanceAfter = (await getAccount(provider.connection, newBuyerTokenAccount)).amount;
+      assert.equal(balanceAfter.toString(), balanceBefore.toString(), 'Bidder not refunded');
+      const sellerKeyToken = await getAccount(provider.connection, sellerKeyAccount);
+      assert.equal(sellerKeyToken.amount.toString(), '1', 'Key NFT not returned to the seller');
+      assert.isNull(await provider.connection.getAccountInfo(auction), 'Auction should be closed');
+    });
+  });
+
+  describe('Token-2022 payments', () => {
+    const feeMint = Keypair.generate();
+    const bidder = Keypair.generate();
+    const TRANSFER_FEE_BPS = 100; // 1%
+    const OFFER_PRICE = new anchor.BN(1_000_000);
+    let feePaymentMint: PublicKey;
+    let feeQuestion: PublicKey;
+    let bidderTokenAccount: PublicKey;
+
+    // fee withheld by the mint's transfer-fee extension, rounded up like the token program
+    const withheld = (amount: bigint) => (amount * BigInt(TRANSFER_FEE_BPS) + 9_999n) / 10_000n;
+
+    before(async () => {
+      const signature = await provider.connection.requestAirdrop(bidder.publicKey, 10 * LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction({ signature, ...(await provider.connection.getLatestBlockhash()) });
+
+      const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+      const lamports = await provider.connection.getMinimumBalanceForRentExemption(mintLen);
+      await sendAndConfirmTransaction(
+        provider.connection,
+        new Transaction().add(
+          SystemProgram.createAccount({
+            fromPubkey: authority.publicKey,
+            newAccountPubkey: feeMint.publicKey,
+            space: mintLen,
+            lamports,
+            programId: TOKEN_2022_PROGRAM_ID,
+          }),
+          createInitializeTransferFeeConfigInstruction(
+            feeMint.publicKey,
+            authority.publicKey,
+            authority.publicKey,
+            TRANSFER_FEE_BPS,
+            BigInt(1_000_000_000),
+            TOKEN_2022_PROGRAM_ID,
+          ),
+          createInitializeMintInstruction(
+            feeMint.publicKey,
+            TEST_BONK_DECIMALS,
+            authority.publicKey,
+            null,
+            TOKEN_2022_PROGRAM_ID,
+          ),
+        ),
+        [authority, feeMint],
+      );
+
+      [feePaymentMint] = PublicKey.findProgramAddressSync(
+        [Buffer.from('payment_mint'), marketplace.toBuffer(), feeMint.publicKey.toBuffer()],
+        program.programId,
+      );
+      await program.methods
+        .addPaymentMint()
+        .accounts({
+          marketplace,
+          paymentMintConfig: feePaymentMint,
+          mint: feeMint.publicKey,
+          authority: authority.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([authority])
+        .rpc();
+
+      const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
+      [feeQuestion] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from('question'),
+          marketplace.toBuffer(),
+          marketplaceAccount.questionCounter.toArrayLike(Buffer, 'le', 8),
+        ],
+        program.programId,
+      );
+      await program.methods
+        .createQuestion(
+          CONTENT_CID,
+          CONTENT_HASH,
+          UNLOCK_PRICE,
+          MAX_KEYS,
+          FLAT_CURVE,
+          null,
+          TOKEN_PAYMENT,
+          ROYALTY_BPS,
+          [],
+        )
+        .accounts({
+          marketplace,
+          userState,
+          question: feeQuestion,
+          questionSplit: null,
+          paymentMintConfig: feePaymentMint,
+          creator: user.publicKey,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([user])
+        .rpc();
+
+      bidderTokenAccount = await createAssociatedTokenAccount(
+        provider.connection,
+        bidder,
+        feeMint.publicKey,
+        bidder.publicKey,
+        undefined,
+        TOKEN_2022_PROGRAM_ID,
+      );
+      await mintTo(
+        provider.connection,
+        authority,
+        feeMint.publicKey,
+        bidderTokenAccount,
+        authority,
+        10_000_000,
+        [],
+        undefined,
+        TOKEN_2022_PROGRAM_ID,
+      );
+    });
+
+    it('Escrows offers net of transfer fees and refunds what was received', async () => {
+      const [offer] = PublicKey.findProgramAddressSync(
+        [Buffer.from('offer'), feeQuestion.toBuffer(), bidder.publicKey.toBuffer()],
+        program.programId,
+      );
+      const [offerEscrow] = PublicKey.findProgramAddressSync(
+        [Buffer.from('offer_escrow'), offer.toBuffer()],
+        program.programId,
+      );
+      const fetchBalance = async () =>
+        (await getAccount(provider.connection, bidderTokenAccount, undefined, TOKEN_2022_PROGRAM_ID)).amount;
+      const balanceBefore = await fetchBalance();
+
+      await program.methods
+        .makeOffer(feeQuestion, OFFER_PRICE)
+        .accounts({
+          marketplace,
+          question: feeQuestion,
+          unlockKey: null,
+          offer,
+          offerEscrow,
+          bidder: bidder.publicKey,
+          bidderTokenAccount,
+          listingAuthority,
+          paymentMint: feeMint.publicKey,
+          paymentTokenProgram: TOKEN_2022_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([bidder])
+        .rpc();
+
+      const price = BigInt(OFFER_PRICE.toString());
+      const escrowed = price - withheld(price);
+      const escrow = await getAccount(provider.connection, offerEscrow, undefined, TOKEN_2022_PROGRAM_ID);
+      assert.equal(escrow.amount.toString(), escrowed.toString(), 'Escrow should hold the price net of the fee');
+
+      // the escrow holds withheld fees, which must be swept before it can close
+      await program.methods
+        .cancelOffer()
+        .accounts({
+          offer,
+          offerEscrow,
+          bidder: bidder.publicKey,
+          bidderTokenAccount,
+          listingAuthority,
+          paymentMint: feeMint.publicKey,
+          paymentTokenProgram: TOKEN_2022_PROGRAM_ID,
+        })
+        .signers([bidder])
+        .rpc();
+
+      const balanceAfter = await fetchBalance();
+      assert.equal(
+        balanceAfter.toString(),
+        (balanceBefore - price + escrowed - withheld(escrowed)).toString(),
+        'Bidder should be refunded the escrowed amount less the refund fee',
+      );
+      assert.isNull(await provider.connection.getAccountInfo(offerEscrow), 'Offer escrow should be closed');
+    });
+  });
+
+  describe('Compressed keys', () => {
+    const TREE_DEPTH = 2;
+    let compressedQuestion: PublicKey;
+    let keyTree: PublicKey;
+
+    const hashPair = (left: Buffer, right: Buffer) => Buffer.from(keccak_256(Buffer.concat([left, right])));
+    const keyLeaf = (tokenId: number, owner: PublicKey, encryptedKey: Buffer) =>
+      Buffer.from(
+        keccak_256(
+          Buffer.concat([
+            compressedQuestion.toBuffer(),
+            new anchor.BN(tokenId).toArrayLike(Buffer, 'le', 8),
+            owner.toBuffer(),
+            Buffer.from(keccak_256(encryptedKey)),
+          ]),
+        ),
+      );
+    // positional proof over every leaf, padding the tree with empty subtrees
+    const keyProof = (leaves: Buffer[], index: number) => {
+      let empty = Buffer.alloc(32);
+      let nodes = leaves;
+      const proof: number[][] = [];
+      for (let level = 0; level < TREE_DEPTH; level++) {
+        proof.push(Array.from(nodes[(index >> level) ^ 1] ?? empty));
+        const parents: Buffer[] = [];
+        for (let i = 0; i < nodes.length; i += 2) {
+          parents.push(hashPair(nodes[i], nodes[i + 1] ?? empty));
+        }
+        nodes = parents;
+        empty = hashPair(empty, empty);
+      }
+      return proof;
+    };
+    const treeRoot = async () => Array.from((await program.account.keyTree.fetch(keyTree)).root);
+
+    before(async () => {
+      const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
+      [compressedQuestion] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from('question'),
+          marketplace.toBuffer(),
+          marketplaceAccount.questionCounter.toArrayLike(Buffer, 'le', 8),
+        ],
+        program.programId,
+      );
+      [keyTree] = PublicKey.findProgramAddressSync(
+        [Buffer.from('key_tree'), compressedQuestion.toBuffer()],
+        program.programId,
+      );
+
+      await program.methods
+        .createQuestion(
+          CONTENT_CID,
+          CONTENT_HASH,
+          UNLOCK_PRICE,
+          new anchor.BN(4),
+          FLAT_CURVE,
+          null,
+          TOKEN_PAYMENT,
+          0,
+          [],
+        )
+        .accounts({
+          marketplace,
+          userState,
+          question: compressedQuestion,
+          questionSplit: null,
+          paymentMintConfig: bonkPaymentMint,
+          creator: user.publicKey,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    it('Rejects a key tree too shallow for max_keys', async () => {
+      try {
+        await program.methods
+          .createKeyTree(1)
+          .accounts({
+            marketplace,
+            question: compressedQuestion,
+            keyTree,
+            creator: user.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+        assert.fail('Expected the tree depth to be rejected');
+      } catch (error) {
+        assert.include(error.message, 'Key tree depth cannot hold the question');
+      }
+    });
+
+    it('Mints, transfers and verifies compressed keys', async () => {
+      await program.methods
+        .createKeyTree(TREE_DEPTH)
+        .accounts({
+          marketplace,
+          question: compressedQuestion,
+          keyTree,
+          creator: user.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      const questionAccount = await program.account.question.fetch(compressedQuestion);
+      assert.deepEqual(questionAccount.keyMode, { compressed: {} });
+
+      await mintTo(
+        provider.connection,
+        authority,
+        bonkMint.publicKey,
+        buyerTokenAccount,
+        authority,
+        UNLOCK_PRICE.toNumber() * 2,
+      );
+      const balanceBefore = (await getAccount(provider.connection, buyerTokenAccount)).amount;
+      for (const tokenId of [0, 1]) {
+        await program.methods
+          .mintCompressedKey(ENCRYPTED_KEY, UNLOCK_PRICE, new anchor.BN(tokenId))
+          .accounts({
+            marketplace,
+            question: compressedQuestion,
+            keyTree,
+            buyer: buyer.publicKey,
+            purchaseRecord: findPurchaseRecord(compressedQuestion, buyer.publicKey),
+            buyerTokenAccount,
+            ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+            questionSplit: null,
+            listingAuthority,
+            treasuryTokenAccount,
+            treasury: null,
+            paymentMint: bonkMint.publicKey,
+            paymentMintConfig: bonkPaymentMint,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            userState,
+          })
+          .signers([buyer])
+          .rpc();
+      }
+      const balanceAfter = (await getAccount(provider.connection, buyerTokenAccount)).amount;
+      assert.equal((balanceBefore - balanceAfter).toString(), (UNLOCK_PRICE.toNumber() * 2).toString());
+
+      const leaves = [0, 1].map((tokenId) => keyLeaf(tokenId, buyer.publicKey, ENCRYPTED_KEY));
+      const tree = await program.account.keyTree.fetch(keyTree);
+      assert.equal(tree.numLeaves.toNumber(), 2);
+
+      // the holder re-encrypts key 0 for the recipient
+      const newEncryptedKey = Buffer.from('re_encrypted_key_data');
+      await program.methods
+        .transferCompressedKey(
+          await treeRoot(),
+          new anchor.BN(0),
+          Array.from(keccak_256(ENCRYPTED_KEY)),
+          newEncryptedKey,
+          keyProof(leaves, 0),
+        )
+        .accounts({
+          marketplace,
+          question: compressedQuestion,
+          keyTree,
+          owner: buyer.publicKey,
+          newOwner: user.publicKey,
+        })
+        .signers([buyer])
+        .rpc();
+      leaves[0] = keyLeaf(0, user.publicKey, newEncryptedKey);
+
+      await program.methods
+        .verifyCompressedKey(
+          await treeRoot(),
+          new anchor.BN(0),
+          user.publicKey,
+          Array.from(keccak_256(newEncryptedKey)),
+          keyProof(leaves, 0),
+        )
+        .accounts({ marketplace, question: compressedQuestion, keyTree })
+        .rpc();
+
+      try {
+        await program.methods
+          .verifyCompressedKey(
+            await treeRoot(),
+            new anchor.BN(0),
+            buyer.publicKey,
+            Array.from(keccak_256(ENCRYPTED_KEY)),
+            keyProof(leaves, 0),
+          )
+          .accounts({ marketplace, question: compressedQuestion, keyTree })
+          .rpc();
+        assert.fail('Previous owner should no longer verify');
+      } catch (error) {
+        assert.include(error.message, 'Invalid compressed key proof');
+      }
+    });
+
+    it('Rejects raising max_keys past the key tree capacity', async () => {
+      try {
+        await program.methods
+          .updateQuestionTerms(UNLOCK_PRICE, new anchor.BN((1 << TREE_DEPTH) + 1))
+          .accounts({
+            marketplace,
+            question: compressedQuestion,
+            keyTree,
+            userState,
+            creator: user.publicKey,
+          })
+          .signers([user])
+          .rpc();
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, "Key tree depth cannot hold the question's keys");
+      }
+    });
+  });
+
+  describe('Batch minting', () => {
+    let batchQuestion: PublicKey;
+
+    const batchMint = async (quantity: number, firstTokenId: number, maxTotalPrice: anchor.BN) => {
+      const remainingAccounts = [];
+      for (let tokenId = firstTokenId; tokenId < firstTokenId + quantity; tokenId++) {
+        const [keyPda] = PublicKey.findProgramAddressSync(
+          [Buffer.from('unlock_key'), batchQuestion.toBuffer(), new anchor.BN(tokenId).toArrayLike(Buffer, 'le', 8)],
+          program.programId,
+        );
+        const nft = await findKeyNftAccounts(keyPda, buyer.publicKey);
+        for (const pubkey of [keyPda, nft.mint, nft.ownerKeyAccount, nft.metadata, nft.masterEdition]) {
+          remainingAccounts.push({ pubkey, isSigner: false, isWritable: true });
+        }
+      }
+
+      return program.methods
+        .mintUnlockKeysBatch(
+          PINATA_URI,
+          quantity,
+          Array(quantity).fill(ENCRYPTED_KEY),
+          maxTotalPrice,
+          new anchor.BN(firstTokenId),
+        )
+        .accounts({
+          marketplace,
+          question: batchQuestion,
+          buyer: buyer.publicKey,
+          purchaseRecord: findPurchaseRecord(batchQuestion, buyer.publicKey),
+          buyerTokenAccount,
+          ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+          questionSplit: null,
+          listingAuthority,
+          treasuryTokenAccount,
+          treasury: null,
+          paymentMint: bonkMint.publicKey,
+          paymentMintConfig: bonkPaymentMint,
+          mintAuthority,
+          collectionMint: null,
+          collectionMetadata: null,
+          collectionMasterEdition: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          metadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+          userState,
+        })
+        .remainingAccounts(remainingAccounts)
+        .preInstructions([ComputeBudgetProgram.setComputeUnitLimit({ units: 1_400_000 })])
+        .signers([buyer])
+        .rpc();
+    };
+
+    before(async () => {
+      const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
+      [batchQuestion] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from('question'),
+          marketplace.toBuffer(),
+          marketplaceAccount.questionCounter.toArrayLike(Buffer, 'le', 8),
+        ],
+        program.programId,
+      );
+
+      await program.methods
+        .createQuestion(
+          CONTENT_CID,
+          CONTENT_HASH,
+          UNLOCK_PRICE,
+          new anchor.BN(3),
+          FLAT_CURVE,
+          null,
+          TOKEN_PAYMENT,
+          0,
+          [],
+        )
+        .accounts({
+          marketplace,
+          userState,
+          question: batchQuestion,
+          questionSplit: null,
+          paymentMintConfig: bonkPaymentMint,
+          creator: user.publicKey,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([user])
+        .rpc();
+
+      await mintTo(
+        provider.connection,
+        authority,
+        bonkMint.publicKey,
+        buyerTokenAccount,
+        authority,
+        UNLOCK_PRICE.toNumber() * 3,
+      );
+    });
+
+    it('Mints consecutive keys for their summed price', async () => {
+      const balanceBefore = (await getAccount(provider.connection, buyerTokenAccount)).amount;
+      await batchMint(2, 0, UNLOCK_PRICE.muln(2));
+
+      const balanceAfter = (await getAccount(provider.connection, buyerTokenAccount)).amount;
+      assert.equal((balanceBefore - balanceAfter).toString(), UNLOCK_PRICE.muln(2).toString());
+      const questionAccount = await program.account.question.fetch(batchQuestion);
+      assert.equal(questionAccount.currentKeys.toNumber(), 2);
+
+      for (const tokenId of [0, 1]) {
+        const [keyPda] = PublicKey.findProgramAddressSync(
+          [Buffer.from('unlock_key'), batchQuestion.toBuffer(), new anchor.BN(tokenId).toArrayLike(Buffer, 'le', 8)],
+          program.programId,
+        );
+        const key = await program.account.unlockKey.fetch(keyPda);
+        assert.equal(key.tokenId.toNumber(), tokenId);
+        assert.ok(key.owner.equals(buyer.publicKey));
+        const nft = await findKeyNftAccounts(keyPda, buyer.publicKey);
+        assert.equal((await getAccount(provider.connection, nft.ownerKeyAccount)).amount.toString(), '1');
+      }
+    });
+
+    it('Fails to mint a batch past max_keys', async () => {
+      try {
+        await batchMint(2, 2, UNLOCK_PRICE.muln(2));
+        assert.fail('Expected the batch to exceed max_keys');
+      } catch (error) {
+        assert.include(error.message, 'No keys available');
+      }
+    });
+
+    it('Stops a wallet at the question purchase limit', async () => {
+      await program.methods
+        .setMaxKeysPerWallet(new anchor.BN(2))
+        .accounts({
+          marketplace,
+          question: batchQuestion,
+          creator: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+      const record = await program.account.purchaseRecord.fetch(findPurchaseRecord(batchQuestion, buyer.publicKey));
+      assert.equal(record.purchased.toNumber(), 2);
+      try {
+        await batchMint(1, 2, UNLOCK_PRICE);
+        assert.fail('Expected the purchase limit to be enforced');
+      } catch (error) {
+        assert.include(error.message, "Wallet has reached the question's purchase limit");
+      }
+    });
+  });
+
+  describe('Revenue splits', () => {
+    const coAuthor = Keypair.generate();
+    let splitQuestion: PublicKey;
+    let questionSplit: PublicKey;
+
+    const createSplitQuestion = (payees: { payee: PublicKey; shareBps: number }[]) =>
+      program.methods
+        .createQuestion(
+          CONTENT_CID,
+          CONTENT_HASH,
+          UNLOCK_PRICE,
+          MAX_KEYS,
+          FLAT_CURVE,
+          null,
+          TOKEN_PAYMENT,
+          ROYALTY_BPS,
+          payees,
+        )
+        .accounts({
+          marketplace,
+          userState,
+          question: splitQuestion,
+          questionSplit,
+          paymentMintConfig: bonkPaymentMint,
+          creator: user.publicKey,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
+      [splitQuestion] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from('question'),
+          marketplace.toBuffer(),
+          marketplaceAccount.questionCounter.toArrayLike(Buffer, 'le', 8),
+        ],
+        program.programId,
+      );
+      [questionSplit] = PublicKey.findProgramAddressSync(
+        [Buffer.from('question_split'), splitQuestion.toBuffer()],
+        program.programId,
+      );
+
+      // the co-author has never sold anything, so their vault is opened for them
+      await program.methods
+        .openCreatorVault(bonkMint.publicKey)
+        .accounts({
+          ...findCreatorVault(coAuthor.publicKey, bonkMint.publicKey),
+          creator: coAuthor.publicKey,
+          payer: user.publicKey,
+          listingAuthority,
+          mint: bonkMint.publicKey,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    it('Rejects payee shares that do not add up to 10000 bps', async () => {
+      try {
+        await createSplitQuestion([
+          { payee: user.publicKey, shareBps: 7000 },
+          { payee: coAuthor.publicKey, shareBps: 2999 },
+        ]);
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'Split payees must be unique with shares summing to 10000 bps');
+      }
+    });
+
+    it('Splits the creator share between co-author vaults', async () => {
+      await createSplitQuestion([
+        { payee: user.publicKey, shareBps: 7000 },
+        { payee: coAuthor.publicKey, shareBps: 3000 },
+      ]);
+      const split = await program.account.questionSplit.fetch(questionSplit);
+      assert.ok(split.question.equals(splitQuestion));
+      assert.equal(split.payees.length, 2);
+      assert.isTrue((await program.account.question.fetch(splitQuestion)).hasSplit);
+
+      const creatorVault = findCreatorVault(user.publicKey, bonkMint.publicKey);
+      const coAuthorVault = findCreatorVault(coAuthor.publicKey, bonkMint.publicKey);
+      const creatorEarnedBefore = (await program.account.creatorVault.fetch(creatorVault.creatorVault)).totalEarned;
+
+      const [keyPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from('unlock_key'), splitQuestion.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, 'le', 8)],
+        program.programId,
+      );
+      const keyNft = await findKeyNftAccounts(keyPda, buyer.publicKey);
+      await mintTo(
+        provider.connection,
+        authority,
+        bonkMint.publicKey,
+        buyerTokenAccount,
+        authority,
+        UNLOCK_PRICE.toNumber(),
+      );
+
+      // payee vaults follow the mint accounts in split order
+      const payeeAccounts = [creatorVault, coAuthorVault].flatMap((vault) => [
+        { pubkey: vault.creatorVault, isSigner: false, isWritable: true },
+        { pubkey: vault.creatorVaultTokens, isSigner: false, isWritable: true },
+      ]);
+      await program.methods
+        .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY, UNLOCK_PRICE, new anchor.BN(0))
+        .accounts({
+          marketplace,
+          question: splitQuestion,
+          unlockKey: keyPda,
+          buyer: buyer.publicKey,
+          purchaseRecord: findPurchaseRecord(splitQuestion, buyer.publicKey),
+          refundEscrow: null,
+          refundEscrowTokens: null,
+          listingAuthority,
+          buyerTokenAccount,
+          ...creatorVault,
+          questionSplit,
+          treasuryTokenAccount,
+          paymentMint: bonkMint.publicKey,
+          paymentMintConfig: bonkPaymentMint,
+          treasury: null,
+          metadata: keyNft.metadata,
+          masterEdition: keyNft.masterEdition,
+          mint: keyNft.mint,
+          buyerKeyAccount: keyNft.ownerKeyAccount,
+          mintAuthority,
+          updateAuthority: mintAuthority,
+          collectionMint: null,
+          collectionMetadata: null,
+          collectionMasterEdition: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          metadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+          userState,
+        })
+        .remainingAccounts(payeeAccounts)
+        .signers([buyer])
+        .rpc();
+
+      // 5% platform fee, then 70/30 with the rounding dust going to the first payee
+      const creatorShare = UNLOCK_PRICE.toNumber() - Math.floor(UNLOCK_PRICE.toNumber() * 0.05);
+      const coAuthorPortion = Math.floor((creatorShare * 3000) / 10000);
+      const creatorEarnedAfter = (await program.account.creatorVault.fetch(creatorVault.creatorVault)).totalEarned;
+      assert.equal(creatorEarnedAfter.sub(creatorEarnedBefore).toNumber(), creatorShare - coAuthorPortion);
+
+      const coAuthorAccount = await program.account.creatorVault.fetch(coAuthorVault.creatorVault);
+      assert.ok(coAuthorAccount.creator.equals(coAuthor.publicKey));
+      assert.equal(coAuthorAccount.totalEarned.toNumber(), coAuthorPortion);
+      const coAuthorBalance = await provider.connection.getTokenAccountBalance(coAuthorVault.creatorVaultTokens);
+      assert.equal(coAuthorBalance.value.amount, coAuthorPortion.toString());
+    });
+
+    it('Lets a split payee verify themselves as a key creator', async () => {
+      const [keyPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from('unlock_key'), splitQuestion.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, 'le', 8)],
+        program.programId,
+      );
+      const keyNft = await findKeyNftAccounts(keyPda, buyer.publicKey);
+      const verifyAccounts = (creator: PublicKey) => ({
+        marketplace,
+        question: splitQuestion,
+        unlockKey: keyPda,
+        metadata: keyNft.metadata,
+        creator,
+        metadataProgram: TOKEN_METADATA_PROGRAM_ID,
+      });
+
+      // only addresses listed among the key's creators can sign
+      const stranger = Keypair.generate();
+      try {
+        await program.methods.verifyKeyCreator().accounts(verifyAccounts(stranger.publicKey)).signers([stranger]).rpc();
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        assert.notInclude(error.message, 'Expected transaction to fail');
+      }
+
+      await program.methods.verifyKeyCreator().accounts(verifyAccounts(coAuthor.publicKey)).signers([coAuthor]).rpc();
+
+      // a creator entry is the address, then the verified flag, then the share
+      const metadataAccount = await provider.connection.getAccountInfo(keyNft.metadata);
+      const verifiedEntry = Buffer.concat([coAuthor.publicKey.toBuffer(), Buffer.from([1, 30])]);
+      assert.isTrue(metadataAccount.data.includes(verifiedEntry));
+    });
+  });
+
+  describe('Closing accounts', () => {
+    const createFreshQuestion = async () => {
+      const marketplaceAccount = await program.account.marketplace.fetch(marketplace);
+      const [freshQuestionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from('question'),
+          marketplace.toBuffer(),
+          marketplaceAccount.questionCounter.toArrayLike(Buffer, 'le', 8),
+        ],
+        program.programId,
+      );
+
+      await program.methods
+        .createQuestion(
+          CONTENT_CID,
+          CONTENT_HASH,
+          UNLOCK_PRICE,
+          MAX_KEYS,
+          FLAT_CURVE,
+          null,
+          TOKEN_PAYMENT,
+          ROYALTY_BPS,
+          [],
+        )
+        .accounts({
+          marketplace,
+          userState,
+          question: freshQuestionPda,
+          questionSplit: null,
+          paymentMintConfig: bonkPaymentMint,
+          creator: user.publicKey,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([user])
+        .rpc();
+
+      return freshQuestionPda;
+    };
+
+    it('Closes a question with no keys sold', async () => {
+      const closableQuestionPda = await createFreshQuestion();
+      const userStateBefore = await program.account.userState.fetch(userState);
+
+      await program.methods
+        .closeQuestion()
+        .accounts({
+          marketplace,
+          question: closableQuestionPda,
+          questionSplit: null,
+          keyTree: null,
+          userState,
+          creator: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+      const questionInfo = await provider.connection.getAccountInfo(closableQuestionPda);
+      assert.isNull(questionInfo, 'Question account should be closed');
+
+      const userStateAfter = await program.account.userState.fetch(userState);
+      assert.equal(userStateAfter.questionsCreated.toNumber(), userStateBefore.questionsCreated.toNumber() - 1);
+    });
+
+    it('Burns an unlock key and closes its account', async () => {
+      const burnQuestionPda = await createFreshQuestion();
+      const [burnKeyPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from('unlock_key'), burnQuestionPda.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, 'le', 8)],
+        program.programId,
+      );
+      const keyNft = await findKeyNftAccounts(burnKeyPda, buyer.publicKey);
+
+      await mintTo(
+        provider.connection,
+        authority,
+        bonkMint.publicKey,
+        buyerTokenAccount,
+        authority,
+        UNLOCK_PRICE.toNumber(),
+      );
+
+      await program.methods
+        .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY, UNLOCK_PRICE, new anchor.BN(0))
+        .accounts({
+          marketplace,
+          question: burnQuestionPda,
+          unlockKey: burnKeyPda,
+          buyer: buyer.publicKey,
+          purchaseRecord: findPurchaseRecord(burnQuestionPda, buyer.publicKey),
+          refundEscrow: null,
+          refundEscrowTokens: null,
+          listingAuthority,
+          buyerTokenAccount,
+          ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+          questionSplit: null,
+          treasuryTokenAccount,
+          paymentMint: bonkMint.publicKey,
+          paymentMintConfig: bonkPaymentMint,
+          treasury: null,
+          metadata: keyNft.metadata,
+          masterEdition: keyNft.masterEdition,
+          mint: keyNft.mint,
+          buyerKeyAccount: keyNft.ownerKeyAccount,
+          mintAuthority,
+          updateAuthority: mintAuthority,
+          collectionMint: null,
+          collectionMetadata: null,
+          collectionMasterEdition: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          metadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+          userState,
+        })
+        .signers([buyer])
+        .rpc();
+
+      await program.methods
+        .burnUnlockKey()
+        .accounts({
+          unlockKey: burnKeyPda,
+          holder: buyer.publicKey,
+          holderKeyAccount: keyNft.ownerKeyAccount,
+          mint: keyNft.mint,
+          metadata: keyNft.metadata,
+          masterEdition: keyNft.masterEdition,
+          collectionMetadata: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          metadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+
+      const unlockKeyInfo = await provider.connection.getAccountInfo(burnKeyPda);
+      assert.isNull(unlockKeyInfo, 'Unlock key account should be closed');
+      const keyTokenInfo = await provider.connection.getAccountInfo(keyNft.ownerKeyAccount);
+      assert.isNull(keyTokenInfo, 'Key token account should be closed');
+    });
+
+    const mintRefundableKey = async (refundWindow: number) => {
+      const refundQuestionPda = await createFreshQuestion();
+      await program.methods
+        .setRefundWindow(new anchor.BN(refundWindow))
+        .accounts({
+          marketplace,
+          question: refundQuestionPda,
+          creator: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+      const [keyPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from('unlock_key'), refundQuestionPda.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, 'le', 8)],
+        program.programId,
+      );
+      const [refundEscrow] = PublicKey.findProgramAddressSync(
+        [Buffer.from('refund_escrow'), keyPda.toBuffer()],
+        program.programId,
+      );
+      const [refundEscrowTokens] = PublicKey.findProgramAddressSync(
+        [Buffer.from('refund_escrow_tokens'), keyPda.toBuffer()],
+        program.programId,
+      );
+      const keyNft = await findKeyNftAccounts(keyPda, buyer.publicKey);
+      await mintTo(
+        provider.connection,
+        authority,
+        bonkMint.publicKey,
+        buyerTokenAccount,
+        authority,
+        UNLOCK_PRICE.toNumber(),
+      );
+
+      await program.methods
+        .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY, UNLOCK_PRICE, new anchor.BN(0))
+        .accounts({
+          marketplace,
+          question: refundQuestionPda,
+          unlockKey: keyPda,
+          buyer: buyer.publicKey,
+          purchaseRecord: findPurchaseRecord(refundQuestionPda, buyer.publicKey),
+          refundEscrow,
+          refundEscrowTokens,
+          listingAuthority,
+          buyerTokenAccount,
+          ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+          questionSplit: null,
+          treasuryTokenAccount,
+          paymentMint: bonkMint.publicKey,
+          paymentMintConfig: bonkPaymentMint,
+          treasury: null,
+          metadata: keyNft.metadata,
+          masterEdition: keyNft.masterEdition,
+          mint: keyNft.mint,
+          buyerKeyAccount: keyNft.ownerKeyAccount,
+          mintAuthority,
+          updateAuthority: mintAuthority,
+          collectionMint: null,
+          collectionMetadata: null,
+          collectionMasterEdition: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          metadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+          userState,
+        })
+        .signers([buyer])
+        .rpc();
+
+      return { keyPda, keyNft, refundEscrow, refundEscrowTokens };
+    };
+    const creatorShare = UNLOCK_PRICE.toNumber() - Math.floor(UNLOCK_PRICE.toNumber() * 0.05);
+
+    it('Refunds the escrowed creator share within the refund window', async () => {
+      const { creatorVault, creatorVaultTokens } = findCreatorVault(user.publicKey, bonkMint.publicKey);
+      const vaultBefore = (await getAccount(provider.connection, creatorVaultTokens)).amount;
+      const { keyPda, keyNft, refundEscrow, refundEscrowTokens } = await mintRefundableKey(600);
+
+      const escrowBalance = await provider.connection.getTokenAccountBalance(refundEscrowTokens);
+      assert.equal(escrowBalance.value.amount, creatorShare.toString());
+      // the escrow snapshots what release_escrow pays out with; the platform fee is never refunded
+      const escrowAccount = await program.account.refundEscrow.fetch(refundEscrow);
+      assert.ok(escrowAccount.paymentMint.equals(bonkMint.publicKey));
+      assert.isEmpty(escrowAccount.payees);
+      assert.equal(escrowAccount.platformFee.toNumber(), UNLOCK_PRICE.toNumber() - creatorShare);
+      const vaultAfterMint = (await getAccount(provider.connection, creatorVaultTokens)).amount;
+      assert.equal(vaultAfterMint.toString(), vaultBefore.toString(), 'Creator share should be escrowed');
+
+      const buyerBefore = (await getAccount(provider.connection, buyerTokenAccount)).amount;
+      await program.methods
+        .requestRefund()
+        .accounts({
+          unlockKey: keyPda,
+          refundEscrow,
+          buyer: buyer.publicKey,
+          buyerKeyAccount: keyNft.ownerKeyAccount,
+          mint: keyNft.mint,
+          metadata: keyNft.metadata,
+          masterEdition: keyNft.masterEdition,
+          collectionMetadata: null,
+          buyerTokenAccount,
+          refundEscrowTokens,
+          listingAuthority,
+          paymentMint: bonkMint.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          metadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+
+      // only the creator share comes back
+      const buyerAfter = (await getAccount(provider.connection, buyerTokenAccount)).amount;
+      assert.equal((buyerAfter - buyerBefore).toString(), creatorShare.toString());
+      assert.isNull(await provider.connection.getAccountInfo(keyPda), 'Unlock key account should be closed');
+      assert.isNull(await provider.connection.getAccountInfo(refundEscrow), 'Refund escrow should be closed');
+    });
+
+    it('Releases the escrow to the creator once the refund window passes', async () => {
+      const { creatorVault, creatorVaultTokens } = findCreatorVault(user.publicKey, bonkMint.publicKey);
+      const { refundEscrow, refundEscrowTokens } = await mintRefundableKey(1);
+      const vaultBefore = (await getAccount(provider.connection, creatorVaultTokens)).amount;
+      const earnedBefore = (await program.account.creatorVault.fetch(creatorVault)).totalEarned;
+
+      // anyone can crank the release once the window has passed
+      await sleep(3000);
+      await program.methods
+        .releaseEscrow()
+        .accounts({
+          refundEscrow,
+          buyer: buyer.publicKey,
+          creatorVault,
+          creatorVaultTokens,
+          refundEscrowTokens,
+          listingAuthority,
+          paymentMint: bonkMint.publicKey,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      const vaultAfter = (await getAccount(provider.connection, creatorVaultTokens)).amount;
+      assert.equal((vaultAfter - vaultBefore).toString(), creatorShare.toString());
+      const earnedAfter = (await program.account.creatorVault.fetch(creatorVault)).totalEarned;
+      assert.equal(earnedAfter.sub(earnedBefore).toNumber(), creatorShare);
+      assert.isNull(await provider.connection.getAccountInfo(refundEscrow), 'Refund escrow should be closed');
+    });
+
+    it('Closes a sold question only once it is retired and no keys are in escrow', async () => {
+      const { keyPda, keyNft } = await mintRefundableKey(600);
+      const soldQuestionPda = (await program.account.unlockKey.fetch(keyPda)).question;
+      const closeSoldQuestion = () =>
+        program.methods
+          .closeQuestion()
+          .accounts({
+            marketplace,
+            question: soldQuestionPda,
+            questionSplit: null,
+            keyTree: null,
+            userState,
+            creator: user.publicKey,
+          })
+          .signers([user])
+          .rpc();
+
+      try {
+        await closeSoldQuestion();
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'Question has minted keys and has not been retired');
+      }
+
+      await program.methods
+        .setQuestionActive(false)
+        .accounts({
+          marketplace,
+          question: soldQuestionPda,
+          creator: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+      // a listed key sits in escrow, and unlisting it needs the question
+      const [listingEscrow] = PublicKey.findProgramAddressSync(
+        [Buffer.from('listing_escrow'), keyPda.toBuffer()],
+        program.programId,
+      );
+      const listingAccounts = {
+        marketplace,
+        question: soldQuestionPda,
+        unlockKey: keyPda,
+        seller: buyer.publicKey,
+        sellerKeyAccount: keyNft.ownerKeyAccount,
+        listingEscrow,
+        listingAuthority,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+      await program.methods
+        .listKey(UNLOCK_PRICE)
+        .accounts({
+          ...listingAccounts,
+          keyMint: keyNft.mint,
+          userState,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+      assert.equal((await program.account.question.fetch(soldQuestionPda)).openEscrows.toNumber(), 1);
+
+      try {
+        await closeSoldQuestion();
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'Question has keys listed, on sale or at auction');
+      }
+
+      await program.methods.cancelListing().accounts(listingAccounts).signers([buyer]).rpc();
+      await closeSoldQuestion();
+
+      assert.isNull(await provider.connection.getAccountInfo(soldQuestionPda), 'Question should be closed');
+      // the holder keeps their key
+      assert.isNotNull(await provider.connection.getAccountInfo(keyPda), 'Unlock key should stay open');
+    });
+
+    it('Lets the creator withdraw vault earnings', async () => {
+      const { creatorVault, creatorVaultTokens } = findCreatorVault(user.publicKey, bonkMint.publicKey);
+      const before = await program.account.creatorVault.fetch(creatorVault);
+      const available = before.totalEarned.sub(before.totalWithdrawn);
+      const withdrawAccounts = {
+        creatorVault,
+        creator: user.publicKey,
+        creatorVaultTokens,
+        creatorTokenAccount,
+        listingAuthority,
+        paymentMint: bonkMint.publicKey,
+        paymentTokenProgram: TOKEN_PROGRAM_ID,
+      };
+
+      // nothing beyond the unwithdrawn earnings can leave the vault
+      try {
+        await program.methods
+          .withdrawEarnings(available.addn(1))
+          .accounts(withdrawAccounts)
+          .signers([user])
+          .rpc();
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, "Withdrawal must be between 1 and the vault's available earnings");
+      }
+
+      const creatorBefore = (await getAccount(provider.connection, creatorTokenAccount)).amount;
+      await program.methods
+        .withdrawEarnings(available)
+        .accounts(withdrawAccounts)
+        .signers([user])
+        .rpc();
+
+      const creatorAfter = (await getAccount(provider.connection, creatorTokenAccount)).amount;
+      assert.equal((creatorAfter - creatorBefore).toString(), available.toString());
+      const after = await program.account.creatorVault.fetch(creatorVault);
+      assert.ok(after.totalEarned.eq(before.totalEarned));
+      assert.ok(after.totalWithdrawn.eq(before.totalEarned));
+      const vaultBalance = await provider.connection.getTokenAccountBalance(creatorVaultTokens);
+      assert.equal(vaultBalance.value.amount, '0');
+    });
+
+    it('Verifies keys into the question collection and burns them', async () => {
+      const collectionQuestionPda = await createFreshQuestion();
+      const [collectionMint] = PublicKey.findProgramAddressSync(
+        [Buffer.from('collection_mint'), collectionQuestionPda.toBuffer()],
+        program.programId,
+      );
+      const [collectionMetadata] = PublicKey.findProgramAddressSync(
+        [Buffer.from('metadata'), TOKEN_METADATA_PROGRAM_ID.toBuffer(), collectionMint.toBuffer()],
+        TOKEN_METADATA_PROGRAM_ID,
+      );
+      const [collectionMasterEdition] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from('metadata'),
+          TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+          collectionMint.toBuffer(),
+          Buffer.from('edition'),
+        ],
+        TOKEN_METADATA_PROGRAM_ID,
+      );
+
+      await program.methods
+        .createQuestionCollection(PINATA_URI)
+        .accounts({
+          marketplace,
+          question: collectionQuestionPda,
+          creator: user.publicKey,
+          collectionMint,
+          collectionTokenAccount: await getAssociatedTokenAddress(collectionMint, mintAuthority, true),
+          collectionMetadata,
+          collectionMasterEdition,
+          mintAuthority,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          metadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([user])
+        .rpc();
+
+      const questionAccount = await program.account.question.fetch(collectionQuestionPda);
+      assert.ok(questionAccount.collectionMint?.equals(collectionMint), 'Collection not recorded on the question');
+
+      const [keyPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from('unlock_key'), collectionQuestionPda.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, 'le', 8)],
+        program.programId,
+      );
+      const keyNft = await findKeyNftAccounts(keyPda, buyer.publicKey);
+      await mintTo(
+        provider.connection,
+        authority,
+        bonkMint.publicKey,
+        buyerTokenAccount,
+        authority,
+        UNLOCK_PRICE.toNumber(),
+      );
+
+      // minting verifies the key into the sized collection
+      await program.methods
+        .mintUnlockKey(PINATA_URI, ENCRYPTED_KEY, UNLOCK_PRICE, new anchor.BN(0))
+        .accounts({
+          marketplace,
+          question: collectionQuestionPda,
+          unlockKey: keyPda,
+          buyer: buyer.publicKey,
+          purchaseRecord: findPurchaseRecord(collectionQuestionPda, buyer.publicKey),
+          refundEscrow: null,
+          refundEscrowTokens: null,
+          listingAuthority,
+          buyerTokenAccount,
+          ...findCreatorVault(user.publicKey, bonkMint.publicKey),
+          questionSplit: null,
+          treasuryTokenAccount,
+          paymentMint: bonkMint.publicKey,
+          paymentMintConfig: bonkPaymentMint,
+          treasury: null,
+          metadata: keyNft.metadata,
+          masterEdition: keyNft.masterEdition,
+          mint: keyNft.mint,
+          buyerKeyAccount: keyNft.ownerKeyAccount,
+          mintAuthority,
+          updateAuthority: mintAuthority,
+          collectionMint,
+          collectionMetadata,
+          collectionMasterEdition,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          metadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+          userState,
+        })
+        .signers([buyer])
+        .rpc();
+
+      // a verified member can only be burned alongside its collection metadata
+      await program.methods
+        .burnUnlockKey()
+        .accounts({
+          unlockKey: keyPda,
+          holder: buyer.publicKey,
+          holderKeyAccount: keyNft.ownerKeyAccount,
+          mint: keyNft.mint,
+          metadata: keyNft.metadata,
+          masterEdition: keyNft.masterEdition,
+          collectionMetadata,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          metadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+
+      assert.isNull(await provider.connection.getAccountInfo(keyPda), 'Unlock key account should be closed');
+    });
+
+    it('Closes a user state with no open questions', async () => {
+      const departingUser = Keypair.generate();
+      const latestBlockhash = await provider.connection.getLatestBlockhash();
+      const signature = await provider.connection.requestAirdrop(departingUser.publicKey, LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction({ signature, ...latestBlockhash });
+
+      const [departingUserState] = PublicKey.findProgramAddressSync(
+        [Buffer.from('user_state'), departingUser.publicKey.toBuffer()],
+        program.programId,
+      );
+
+      await program.methods
+        .initializeUserState()
+        .accounts({
+          userState: departingUserState,
+          user: departingUser.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([departingUser])
+        .rpc();
+
+      await program.methods
+        .closeUserState()
+        .accounts({
+          userState: departingUserState,
+          user: departingUser.publicKey,
+        })
+        .signers([departingUser])
+        .rpc();
+
+      const userStateInfo = await provider.connection.getAccountInfo(departingUserState);
+      assert.isNull(userStateInfo, 'User state should be closed');
+    });
+
+    it('Fails to close a user state with open questions', async () => {
+      try {
+        await program.methods
+          .closeUserState()
+          .accounts({
+            userState,
+            user: user.publicKey,
+          })
+          .signers([user])
+          .rpc();
+        assert.fail('Expected transaction to fail');
+      } catch (error: any) {
+        const errorMessage = error.error?.errorMessage || error.message;
+        assert.include(errorMessage, 'User still has open questions');
+      }
+    });
   });
 
   it('Initializes with correct BONK mint', async () => {